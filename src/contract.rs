@@ -1,21 +1,44 @@
 #[cfg(not(feature = "library"))]
 use cosmwasm_std::entry_point;
 use cosmwasm_std::{
-    to_json_binary, Binary, Deps, DepsMut, Env, MessageInfo, Reply, Response, StdResult,
+    to_json_binary, Binary, Decimal, Deps, DepsMut, Env, MessageInfo, Reply, Response, StdResult,
 };
+use cw2::set_contract_version;
 use cw_utils::one_coin;
 
 use crate::{
     error::ContractError,
-    execute::{try_liquid_staking, update_config},
-    msg::{ExecuteMsg, InstantiateMsg, LsConfig, QueryMsg},
+    execute::{
+        approve_operator, cancel_pending_change, cancel_schedule, cancel_stake,
+        claim_keeper_rewards, create_schedule, deposit, execute_due_schedules,
+        execute_pending_change, liquid_stake_from, liquid_stake_from_balance, process_batch,
+        register_referrer, retry_stake, revoke_operator, run_schedule, set_denom_config,
+        set_governance, set_keeper_tip, set_post_action, set_receipt_contract,
+        swap_and_liquid_stake, sweep, try_liquid_staking, update_config, validate_config_timelock,
+        validate_ls_prefix, validate_protocol_fee, withdraw, withdraw_queued,
+    },
+    msg::{ExecuteMsg, InstantiateMsg, LsConfig, QueryMsg, StakeFunding},
     query,
-    reply::handle_ls_reply,
+    reply::{
+        handle_batch_reply, handle_ls_reply, handle_post_action_reply, handle_receipt_reply,
+        handle_schedule_reply, handle_swap_reply,
+    },
     state::LS_CONFIG,
 };
 
 pub const LS_REPLY_ID: u64 = 1;
 pub const TRANSFER_REPLY_ID: u64 = 2;
+pub const POST_ACTION_REPLY_ID: u64 = 3;
+pub const SWAP_REPLY_ID: u64 = 4;
+pub const RECEIPT_REPLY_ID: u64 = 5;
+pub const BATCH_REPLY_ID: u64 = 6;
+pub const SCHEDULE_REPLY_ID: u64 = 7;
+
+pub const DEFAULT_HISTORY_LIMIT: u64 = 10_000;
+
+// version info for migration info
+const CONTRACT_NAME: &str = env!("CARGO_PKG_NAME");
+const CONTRACT_VERSION: &str = env!("CARGO_PKG_VERSION");
 
 #[cfg_attr(not(feature = "library"), entry_point)]
 pub fn instantiate(
@@ -25,11 +48,28 @@ pub fn instantiate(
     msg: InstantiateMsg,
 ) -> Result<Response, ContractError> {
     deps.api.debug("WASMDEBUG: ls instantiate");
+    set_contract_version(deps.storage, CONTRACT_NAME, CONTRACT_VERSION)?;
+
+    let fee_recipient = match msg.fee_recipient {
+        Some(fee_recipient) => deps.api.addr_validate(&fee_recipient)?,
+        None => info.sender.clone(),
+    };
+    let swap_router = msg
+        .swap_router
+        .map(|swap_router| deps.api.addr_validate(&swap_router))
+        .transpose()?;
 
     let ls_config = LsConfig {
         admin: info.sender.clone(),
         active: true,
-        ls_prefix: msg.ls_prefix.clone(),
+        ls_prefix: validate_ls_prefix(msg.ls_prefix)?,
+        protocol_fee: validate_protocol_fee(msg.protocol_fee.unwrap_or_default())?,
+        fee_recipient,
+        history_limit: msg.history_limit.unwrap_or(DEFAULT_HISTORY_LIMIT),
+        swap_router,
+        config_timelock: validate_config_timelock(msg.config_timelock.unwrap_or_default())?,
+        keeper_fee_share: Decimal::zero(),
+        keeper_tips_per_block: 0,
     };
     LS_CONFIG.save(deps.storage, &ls_config)?;
 
@@ -37,7 +77,11 @@ pub fn instantiate(
         .add_attribute("method", "instantiate")
         .add_attribute("owner", info.sender.to_string())
         .add_attribute("active", "true")
-        .add_attribute("ls_prefix", msg.ls_prefix))
+        .add_attribute("ls_prefix", ls_config.ls_prefix.clone())
+        .add_attribute("protocol_fee", ls_config.protocol_fee.to_string())
+        .add_attribute("fee_recipient", ls_config.fee_recipient.to_string())
+        .add_attribute("history_limit", ls_config.history_limit.to_string())
+        .add_attribute("config_timelock", ls_config.config_timelock.to_string()))
 }
 
 #[cfg_attr(not(feature = "library"), entry_point)]
@@ -48,14 +92,124 @@ pub fn execute(
     msg: ExecuteMsg,
 ) -> Result<Response, ContractError> {
     match msg {
-        ExecuteMsg::LiquidStake { receiver } => {
+        ExecuteMsg::LiquidStake {
+            receiver,
+            referral,
+            post_action,
+        } => {
             let coin = one_coin(&info)?;
-            try_liquid_staking(deps, env, coin, info.sender, receiver)
+            try_liquid_staking(
+                deps,
+                env,
+                coin,
+                info.sender,
+                receiver,
+                referral,
+                post_action,
+                StakeFunding::Funds,
+            )
+        }
+        ExecuteMsg::SwapAndLiquidStake {
+            receiver,
+            route,
+            min_out,
+            referral,
+            post_action,
+        } => {
+            let offer = one_coin(&info)?;
+            swap_and_liquid_stake(
+                deps,
+                env,
+                offer,
+                info.sender,
+                receiver,
+                route,
+                min_out,
+                referral,
+                post_action,
+            )
         }
 
-        ExecuteMsg::UpdateConfig { active, ls_prefix } => {
-            update_config(deps, env, info, active, ls_prefix)
+        ExecuteMsg::UpdateConfig {
+            active,
+            ls_prefix,
+            protocol_fee,
+            fee_recipient,
+            history_limit,
+            swap_router,
+            verify_ls_prefix,
+            config_timelock,
+        } => update_config(
+            deps,
+            env,
+            info,
+            active,
+            ls_prefix,
+            protocol_fee,
+            fee_recipient,
+            history_limit,
+            swap_router,
+            verify_ls_prefix,
+            config_timelock,
+        ),
+        ExecuteMsg::ExecutePendingChange { id } => execute_pending_change(deps, env, info, id),
+        ExecuteMsg::CancelPendingChange { id } => cancel_pending_change(deps, env, info, id),
+        ExecuteMsg::RetryStake { id } => retry_stake(deps, env, info, id),
+        ExecuteMsg::CancelStake { id } => cancel_stake(deps, env, info, id),
+        ExecuteMsg::Deposit {} => {
+            let coin = one_coin(&info)?;
+            deposit(deps, env, info, coin)
+        }
+        ExecuteMsg::LiquidStakeFromBalance { amount, receiver } => {
+            liquid_stake_from_balance(deps, env, info, amount, receiver)
+        }
+        ExecuteMsg::Withdraw { amount } => withdraw(deps, env, info, amount),
+        ExecuteMsg::CreateSchedule {
+            denom,
+            amount_per_run,
+            interval,
+            receiver,
+        } => create_schedule(deps, env, info, denom, amount_per_run, interval, receiver),
+        ExecuteMsg::CancelSchedule { id } => cancel_schedule(deps, env, info, id),
+        ExecuteMsg::ExecuteDueSchedules { limit } => execute_due_schedules(deps, env, info, limit),
+        ExecuteMsg::RunSchedule { id, keeper } => run_schedule(deps, env, info, id, keeper),
+        ExecuteMsg::ApproveOperator {
+            operator,
+            expires,
+            max_amount,
+        } => approve_operator(deps, env, info, operator, expires, max_amount),
+        ExecuteMsg::RevokeOperator { operator } => revoke_operator(deps, env, info, operator),
+        ExecuteMsg::LiquidStakeFrom {
+            owner,
+            coin,
+            receiver,
+        } => liquid_stake_from(deps, env, info, owner, coin, receiver),
+        ExecuteMsg::ProcessBatch { denom } => process_batch(deps, env, info, denom),
+        ExecuteMsg::WithdrawQueued { denom } => withdraw_queued(deps, env, info, denom),
+        ExecuteMsg::SetKeeperTip {
+            fee_share,
+            tips_per_block,
+        } => set_keeper_tip(deps, env, info, fee_share, tips_per_block),
+        ExecuteMsg::ClaimKeeperRewards {} => claim_keeper_rewards(deps, env, info),
+        ExecuteMsg::RegisterReferrer {
+            code,
+            address,
+            fee_share,
+        } => register_referrer(deps, env, info, code, address, fee_share),
+        ExecuteMsg::SetDenomConfig { denom, config } => {
+            set_denom_config(deps, env, info, denom, config)
         }
+        ExecuteMsg::SetPostAction { name, action } => {
+            set_post_action(deps, env, info, name, action)
+        }
+        ExecuteMsg::SetGovernance {
+            contract,
+            proposal_required,
+        } => set_governance(deps, env, info, contract, proposal_required),
+        ExecuteMsg::SetReceiptContract { contract } => {
+            set_receipt_contract(deps, env, info, contract)
+        }
+        ExecuteMsg::Sweep { denoms, to } => sweep(deps, env, info, denoms, to),
     }
 }
 
@@ -63,118 +217,97 @@ pub fn execute(
 pub fn reply(deps: DepsMut, env: Env, msg: Reply) -> Result<Response, ContractError> {
     match msg.id {
         LS_REPLY_ID => handle_ls_reply(deps, env, msg),
+        POST_ACTION_REPLY_ID => handle_post_action_reply(deps, env, msg),
+        SWAP_REPLY_ID => handle_swap_reply(deps, env, msg),
+        RECEIPT_REPLY_ID => handle_receipt_reply(deps, env, msg),
+        BATCH_REPLY_ID => handle_batch_reply(deps, env, msg),
+        SCHEDULE_REPLY_ID => handle_schedule_reply(deps, env, msg),
         _ => Err(ContractError::UnknownReplyId { id: msg.id }),
     }
 }
 
 #[cfg_attr(not(feature = "library"), entry_point)]
-pub fn query(deps: Deps, _env: Env, msg: QueryMsg) -> StdResult<Binary> {
+pub fn query(deps: Deps, env: Env, msg: QueryMsg) -> StdResult<Binary> {
     match msg {
         QueryMsg::LsConfig {} => to_json_binary(&query::query_ls_config(deps)?),
+        QueryMsg::StakeHistory {
+            address,
+            start_after,
+            limit,
+        } => to_json_binary(&query::query_stake_history(
+            deps,
+            address,
+            start_after,
+            limit,
+        )?),
+        QueryMsg::Stake { id } => to_json_binary(&query::query_stake(deps, id)?),
+        QueryMsg::ReferrerStats { code } => {
+            to_json_binary(&query::query_referrer_stats(deps, code)?)
+        }
+        QueryMsg::DenomConfig { denom } => to_json_binary(&query::query_denom_config(deps, denom)?),
+        QueryMsg::PostAction { name } => to_json_binary(&query::query_post_action(deps, name)?),
+        QueryMsg::Status {} => to_json_binary(&query::query_status(deps, env)?),
+        QueryMsg::SweepableBalances {} => {
+            to_json_binary(&query::query_sweepable_balances(deps, env)?)
+        }
+        QueryMsg::GovernanceInfo {} => to_json_binary(&query::query_governance_info(deps)?),
+        QueryMsg::Receipt { id } => to_json_binary(&query::query_receipt(deps, id)?),
+        QueryMsg::PendingChanges {} => to_json_binary(&query::query_pending_changes(deps)?),
+        QueryMsg::DepositBalance { address } => {
+            to_json_binary(&query::query_deposit_balance(deps, address)?)
+        }
+        QueryMsg::Schedules { owner } => to_json_binary(&query::query_schedules(deps, owner)?),
+        QueryMsg::Operators { owner } => to_json_binary(&query::query_operators(deps, owner)?),
+        QueryMsg::Batch { denom } => to_json_binary(&query::query_batch(deps, denom)?),
+        QueryMsg::KeeperRewards { address } => {
+            to_json_binary(&query::query_keeper_rewards(deps, address)?)
+        }
+        QueryMsg::FailedStakes { start_after, limit } => {
+            to_json_binary(&query::query_failed_stakes(deps, start_after, limit)?)
+        }
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use std::collections::HashMap;
-
-    use crate::execute::DENOM_TRACE_QUERY_TYPE;
-    use crate::state::{LSInfo, CURRENT_TX};
+    use crate::cw721::Cw721ExecuteMsg;
+    use crate::dexter::{AssetInfo, HopSwapRequest, RouterExecuteMsg};
+    use crate::msg::{
+        Batch, BatchConfig, ConfigChange, DenomConfig, DenomStatus, DepositBalanceResponse,
+        FailedStake, FailedStakesResponse, GovernanceInfoResponse, KeeperRewardsResponse,
+        LsProvider, Operator, OperatorsResponse, PendingChange, PendingChangesResponse, PostAction,
+        Receipt, ReferrerStatsResponse, Schedule, SchedulesResponse, StakeHistoryResponse,
+        StakeRecord, StatusResponse, SwapHop, SweepableBalancesResponse,
+    };
+    use crate::query::{query_denom_config, query_governance_info, query_ls_config, query_stake};
+    use crate::state::{
+        schedules, LSInfo, RetryInfo, SwapInfo, CURRENT_POST_ACTION, CURRENT_RECEIPT,
+        CURRENT_RETRY, CURRENT_SWAP, CURRENT_TX, LIABILITIES, RECEIPTS, REFERRERS,
+    };
+    use crate::testing::{
+        mock_dependencies, mock_host_chain, InstantiateMsgBuilder, LsConfigBuilder,
+        WasmMockQuerier, DEFAULT_BONDED_DENOM,
+    };
 
     use super::*;
-    use cosmwasm_std::testing::{mock_env, mock_info, MockApi, MockStorage};
+    use cosmwasm_std::testing::{mock_env, mock_info, MockApi, MockStorage, MOCK_CONTRACT_ADDR};
     use cosmwasm_std::{
-        attr, coins, from_json, Addr, BalanceResponse, BankMsg, BankQuery, Coin, ContractResult,
-        CosmosMsg, Empty, OwnedDeps, Querier, QuerierResult, QueryRequest, ReplyOn, SubMsg,
-        SubMsgResponse, SystemError, SystemResult, Uint128,
+        attr, coins, from_json, Addr, BankMsg, Coin, CosmosMsg, Decimal, Empty, OwnedDeps, ReplyOn,
+        StdError, SubMsg, SubMsgResponse, SubMsgResult, Uint128, WasmMsg,
     };
+    use cw2::ContractVersion;
+    use cw_utils::Expiration;
     use persistence_std::types::{
         cosmos::base::v1beta1::Coin as StdCoin,
-        ibc::applications::transfer::v1::{
-            DenomTrace, QueryDenomTraceRequest, QueryDenomTraceResponse,
-        },
-        pstake::liquidstakeibc::v1beta1::MsgLiquidStake,
+        pstake::liquidstakeibc::v1beta1::{ica_account::ChannelState, IcaAccount, MsgLiquidStake},
     };
 
-    use prost::Message;
-
     const NATIVE_IBC_DENOM: &str =
         "ibc/C8A74ABBE2AF892E15680D916A7C22130585CE5704F9B17A10F184A90D53BECA";
     const NATIVE_BASE_DENOM: &str = "uatom";
     const LIQUIDSTAKE_DENOM: &str = "stk/uatom";
-
-    pub struct WasmMockQuerier {
-        pub denom_trace: HashMap<String, QueryDenomTraceResponse>,
-    }
-
-    // Implements the Querier trait to be used as a MockQuery object
-    impl Querier for WasmMockQuerier {
-        fn raw_query(&self, bin_request: &[u8]) -> QuerierResult {
-            let request: QueryRequest<Empty> = match from_json(bin_request) {
-                Ok(v) => v,
-                Err(e) => {
-                    return SystemResult::Err(SystemError::InvalidRequest {
-                        error: format!("Parsing query request: {}", e),
-                        request: bin_request.into(),
-                    })
-                }
-            };
-            self.handle_query(&request)
-        }
-    }
-
-    impl WasmMockQuerier {
-        pub fn new() -> Self {
-            WasmMockQuerier {
-                denom_trace: HashMap::new(),
-            }
-        }
-
-        fn handle_query(&self, request: &QueryRequest<Empty>) -> QuerierResult {
-            match request {
-                QueryRequest::Stargate { path, data } => {
-                    if path == DENOM_TRACE_QUERY_TYPE {
-                        let query_denom_trace_request =
-                            QueryDenomTraceRequest::decode(data.as_slice()).unwrap();
-                        match self.denom_trace.get(&query_denom_trace_request.hash) {
-                            Some(resp) => SystemResult::Ok(to_json_binary(&resp).into()),
-                            None => SystemResult::Err(SystemError::Unknown {}),
-                        }
-                    } else {
-                        panic!("Mocked query not supported for stargate path {}", path);
-                    }
-                }
-                QueryRequest::Bank(BankQuery::Balance { address, denom }) => {
-                    if address == &Addr::unchecked("cosmos2contract")
-                        && denom == &LIQUIDSTAKE_DENOM.to_string()
-                    {
-                        let bank_res = BalanceResponse {
-                            amount: Coin {
-                                amount: Uint128::new(2000u128),
-                                denom: denom.to_string(),
-                            },
-                        };
-                        SystemResult::Ok(ContractResult::from(to_json_binary(&bank_res)))
-                    } else {
-                        unimplemented!()
-                    }
-                }
-                _ => panic!("DO NOT ENTER HERE"),
-            }
-        }
-
-        pub fn mock_denom_trace(&mut self, ibc_hash: String) {
-            self.denom_trace.insert(
-                ibc_hash,
-                QueryDenomTraceResponse {
-                    denom_trace: Some(DenomTrace {
-                        path: "transfer/channel-0".to_string(),
-                        base_denom: NATIVE_BASE_DENOM.to_string(),
-                    }),
-                },
-            );
-        }
-    }
+    const NATIVE_BOND_DENOM: &str = DEFAULT_BONDED_DENOM;
+    const HOST_CHAIN_ID: &str = "cosmoshub-4";
 
     // Helper function to instantiate the contract
     fn default_instantiate() -> (
@@ -185,18 +318,8 @@ mod tests {
         let env = mock_env();
         let info = mock_info("creator", &[]);
 
-        let custom_querier: WasmMockQuerier = WasmMockQuerier::new();
-
-        let mut deps = OwnedDeps {
-            storage: MockStorage::default(),
-            api: MockApi::default(),
-            querier: custom_querier,
-            custom_query_type: Default::default(),
-        };
-
-        let msg = InstantiateMsg {
-            ls_prefix: "stk/".to_string(),
-        };
+        let mut deps = mock_dependencies();
+        let msg = InstantiateMsgBuilder::default().build();
 
         let resp = instantiate(deps.as_mut(), env.clone(), info.clone(), msg).unwrap();
         assert_eq!(
@@ -206,16 +329,38 @@ mod tests {
                 attr("owner", "creator"),
                 attr("active", "true"),
                 attr("ls_prefix", "stk/"),
+                attr("protocol_fee", "0"),
+                attr("fee_recipient", "creator"),
+                attr("history_limit", DEFAULT_HISTORY_LIMIT.to_string()),
+                attr("config_timelock", "0"),
             ]
         );
 
-        // Mock each pool in the querier
-        deps.querier.mock_denom_trace(NATIVE_IBC_DENOM.to_string());
+        deps.querier
+            .mock_denom_trace(NATIVE_IBC_DENOM, "transfer/channel-0", NATIVE_BASE_DENOM);
+        deps.querier.set_host_chain(mock_host_chain(
+            HOST_CHAIN_ID,
+            NATIVE_BASE_DENOM,
+            "channel-0",
+        ));
+        deps.querier
+            .set_balance(MOCK_CONTRACT_ADDR, coins(2000, LIQUIDSTAKE_DENOM));
 
         (deps, env, info)
     }
 
+    fn ok_reply() -> Reply {
+        Reply {
+            id: LS_REPLY_ID,
+            result: cosmwasm_std::SubMsgResult::Ok(SubMsgResponse {
+                events: vec![],
+                data: None,
+            }),
+        }
+    }
+
     #[test]
+    #[allow(clippy::bool_assert_comparison)]
     fn proper_initialization() {
         let (deps, _env, _info) = default_instantiate();
 
@@ -223,15 +368,23 @@ mod tests {
         let res = query(deps.as_ref(), mock_env(), QueryMsg::LsConfig {}).unwrap();
         let value: LsConfig = from_json(&res).unwrap();
         assert_eq!(true, value.active);
+        assert_eq!(value, LsConfigBuilder::new("creator").build());
     }
 
     #[test]
+    #[allow(clippy::bool_assert_comparison)]
     fn update_config() {
         let (mut deps, _env, info) = default_instantiate();
 
         let msg = ExecuteMsg::UpdateConfig {
             active: Some(false),
             ls_prefix: Some("newprefix/".to_string()),
+            protocol_fee: Some(Decimal::percent(5)),
+            fee_recipient: Some("treasury".to_string()),
+            history_limit: Some(50),
+            swap_router: None,
+            verify_ls_prefix: None,
+            config_timelock: None,
         };
         let res = execute(deps.as_mut(), mock_env(), info, msg).unwrap();
         assert_eq!(
@@ -240,6 +393,9 @@ mod tests {
                 attr("method", "update_config"),
                 attr("active", "false"),
                 attr("ls_prefix", "newprefix/"),
+                attr("protocol_fee", "0.05"),
+                attr("fee_recipient", "treasury"),
+                attr("history_limit", "50"),
             ]
         );
 
@@ -248,6 +404,30 @@ mod tests {
         let value: LsConfig = from_json(&res).unwrap();
         assert_eq!(false, value.active);
         assert_eq!("newprefix/", value.ls_prefix);
+        assert_eq!(
+            value,
+            LsConfigBuilder::new("creator")
+                .active(false)
+                .ls_prefix("newprefix/")
+                .protocol_fee(Decimal::percent(5))
+                .fee_recipient("treasury")
+                .history_limit(50)
+                .build()
+        );
+
+        // fee can not take the whole minted amount
+        let msg = ExecuteMsg::UpdateConfig {
+            active: None,
+            ls_prefix: None,
+            protocol_fee: Some(Decimal::one()),
+            fee_recipient: None,
+            history_limit: None,
+            swap_router: None,
+            verify_ls_prefix: None,
+            config_timelock: None,
+        };
+        let err = execute(deps.as_mut(), mock_env(), mock_info("creator", &[]), msg).unwrap_err();
+        assert_eq!(err, ContractError::InvalidProtocolFee {});
     }
 
     #[test]
@@ -260,6 +440,8 @@ mod tests {
         let info = mock_info("anyone", &coins(deposit_amount.into(), NATIVE_IBC_DENOM));
         let msg = ExecuteMsg::LiquidStake {
             receiver: Addr::unchecked("persistencereceiver"),
+            referral: None,
+            post_action: None,
         };
         let res = execute(deps.as_mut(), mock_env(), info, msg).unwrap();
         assert_eq!(1, res.messages.len());
@@ -279,7 +461,7 @@ mod tests {
                     .into(),
                 },
                 gas_limit: None,
-                reply_on: ReplyOn::Success
+                reply_on: ReplyOn::Always
             }
         );
 
@@ -288,6 +470,7 @@ mod tests {
             res.attributes,
             vec![
                 attr("action", "liquid_stake"),
+                attr("provider", "liquidstakeibc"),
                 attr("sender", "anyone"),
                 attr("native_amount", deposit_amount.to_string()),
                 attr("native_ibc_denom", NATIVE_IBC_DENOM),
@@ -311,9 +494,16 @@ mod tests {
         };
 
         let current_tx = LSInfo {
+            sender: Addr::unchecked("sender"),
             receiver: Addr::unchecked("receiver"),
+            native_denom: NATIVE_IBC_DENOM.to_string(),
+            native_amount: Uint128::new(1000u128),
             ls_token_denom: LIQUIDSTAKE_DENOM.to_string(),
             prev_ls_token_balance: Uint128::new(1000u128),
+            referral: None,
+            post_action: None,
+            funding: StakeFunding::Funds,
+            keeper: None,
         };
         CURRENT_TX.save(deps.as_mut().storage, &current_tx).unwrap();
 
@@ -338,19 +528,2417 @@ mod tests {
             res.attributes,
             vec![
                 attr("method", "handle_ls_reply"),
+                attr("stake_id", "1"),
                 attr("minted_lst_amount", Uint128::new(1000u128).to_string()),
+                attr("fee", "0"),
                 attr("receiver", "receiver")
             ]
         );
+
+        // the stake is recorded in the history
+        let res = query(deps.as_ref(), mock_env(), QueryMsg::Stake { id: 1 }).unwrap();
+        let record: StakeRecord = from_json(&res).unwrap();
+        assert_eq!(
+            record,
+            StakeRecord {
+                id: 1,
+                sender: Addr::unchecked("sender"),
+                receiver: Addr::unchecked("receiver"),
+                native_denom: NATIVE_IBC_DENOM.to_string(),
+                native_amount: Uint128::new(1000u128),
+                ls_token_denom: LIQUIDSTAKE_DENOM.to_string(),
+                ls_token_amount: Uint128::new(1000u128),
+                fee: Uint128::zero(),
+                referral: None,
+                post_action: None,
+                block_height: mock_env().block.height,
+                block_time: mock_env().block.time,
+            }
+        );
+    }
+
+    #[test]
+    fn test_receipt() {
+        let (mut deps, _env, info) = default_instantiate();
+
+        let msg = ExecuteMsg::SetReceiptContract {
+            contract: Some("receipts".to_string()),
+        };
+        let err = execute(deps.as_mut(), mock_env(), info.clone(), msg.clone()).unwrap_err();
+        assert_eq!(
+            err,
+            ContractError::NotAContract {
+                address: "receipts".to_string()
+            }
+        );
+        deps.querier.set_contract("receipts");
+        execute(deps.as_mut(), mock_env(), info, msg).unwrap();
+
+        let current_tx = LSInfo {
+            sender: Addr::unchecked("sender"),
+            receiver: Addr::unchecked("receiver"),
+            native_denom: NATIVE_IBC_DENOM.to_string(),
+            native_amount: Uint128::new(1000u128),
+            ls_token_denom: LIQUIDSTAKE_DENOM.to_string(),
+            prev_ls_token_balance: Uint128::new(1000u128),
+            referral: None,
+            post_action: None,
+            funding: StakeFunding::Funds,
+            keeper: None,
+        };
+        CURRENT_TX.save(deps.as_mut().storage, &current_tx).unwrap();
+        let res = handle_ls_reply(deps.as_mut(), mock_env(), ok_reply()).unwrap();
+
+        let receipt = Receipt {
+            stake_id: 1,
+            sender: Addr::unchecked("sender"),
+            receiver: Addr::unchecked("receiver"),
+            native_denom: NATIVE_IBC_DENOM.to_string(),
+            native_amount: Uint128::new(1000u128),
+            ls_token_denom: LIQUIDSTAKE_DENOM.to_string(),
+            ls_token_amount: Uint128::new(1000u128),
+            fee: Uint128::zero(),
+            block_height: mock_env().block.height,
+            block_time: mock_env().block.time,
+        };
+        assert_eq!(
+            res.messages[1],
+            SubMsg::reply_always(
+                WasmMsg::Execute {
+                    contract_addr: "receipts".to_string(),
+                    msg: to_json_binary(&Cw721ExecuteMsg::Mint {
+                        token_id: "1".to_string(),
+                        owner: "receiver".to_string(),
+                        token_uri: None,
+                        extension: receipt.clone(),
+                    })
+                    .unwrap(),
+                    funds: vec![],
+                },
+                RECEIPT_REPLY_ID
+            )
+        );
+        assert_eq!(CURRENT_RECEIPT.load(&deps.storage).unwrap(), 1);
+        assert_eq!(RECEIPTS.load(&deps.storage, 1).unwrap().receipt, receipt);
+
+        // a failed mint drops the receipt but not the stake
+        let failed = Reply {
+            id: RECEIPT_REPLY_ID,
+            result: SubMsgResult::Err("minter only".to_string()),
+        };
+        let res = reply(deps.as_mut(), mock_env(), failed).unwrap();
+        assert_eq!(
+            res.attributes,
+            vec![
+                attr("method", "handle_receipt_reply"),
+                attr("stake_id", "1"),
+                attr("status", "failed"),
+                attr("error", "minter only"),
+            ]
+        );
+        assert!(CURRENT_RECEIPT.may_load(&deps.storage).unwrap().is_none());
+        assert!(!RECEIPTS.has(&deps.storage, 1));
+        query_stake(deps.as_ref(), 1).unwrap();
+    }
+
+    #[test]
+    fn test_ls_reply_balance_decrease() {
+        let (mut deps, _env, _info) = default_instantiate();
+
+        // more LS tokens before the stake than the 2000 held now
+        let current_tx = LSInfo {
+            sender: Addr::unchecked("sender"),
+            receiver: Addr::unchecked("receiver"),
+            native_denom: NATIVE_IBC_DENOM.to_string(),
+            native_amount: Uint128::new(1000u128),
+            ls_token_denom: LIQUIDSTAKE_DENOM.to_string(),
+            prev_ls_token_balance: Uint128::new(3000u128),
+            referral: None,
+            post_action: None,
+            funding: StakeFunding::Funds,
+            keeper: None,
+        };
+        CURRENT_TX.save(deps.as_mut().storage, &current_tx).unwrap();
+
+        let err = handle_ls_reply(deps.as_mut(), mock_env(), ok_reply()).unwrap_err();
+        assert!(matches!(err, ContractError::Std(StdError::Overflow { .. })));
+    }
+
+    #[test]
+    fn test_status() {
+        let (mut deps, _env, _info) = default_instantiate();
+
+        let info = mock_info("anyone", &coins(1000, NATIVE_IBC_DENOM));
+        let msg = ExecuteMsg::LiquidStake {
+            receiver: Addr::unchecked("persistencereceiver"),
+            referral: None,
+            post_action: None,
+        };
+        execute(deps.as_mut(), mock_env(), info, msg).unwrap();
+
+        let res = query(deps.as_ref(), mock_env(), QueryMsg::Status {}).unwrap();
+        let status: StatusResponse = from_json(&res).unwrap();
+        assert_eq!(
+            status.contract_version,
+            Some(ContractVersion {
+                contract: CONTRACT_NAME.to_string(),
+                version: CONTRACT_VERSION.to_string(),
+            })
+        );
+        assert_eq!(
+            status.stake_in_flight.unwrap().native_amount,
+            Uint128::new(1000)
+        );
+        // the IBC denom is only listed once it has been staked
+        assert_eq!(
+            status.denoms,
+            vec![DenomStatus {
+                denom: NATIVE_BOND_DENOM.to_string(),
+                provider: "liquidstake".to_string(),
+                native_balance: Uint128::zero(),
+                ls_token_denom: Some("stk/uxprt".to_string()),
+                ls_token_balance: Some(Uint128::zero()),
+                stake_count: 0,
+                native_volume: Uint128::zero(),
+                ls_token_minted: Uint128::zero(),
+            }]
+        );
+
+        // a configured IBC denom resolves its LS token through the denom trace
+        let msg = ExecuteMsg::SetDenomConfig {
+            denom: NATIVE_IBC_DENOM.to_string(),
+            config: Some(DenomConfig::default_for(NATIVE_IBC_DENOM)),
+        };
+        execute(deps.as_mut(), mock_env(), mock_info("creator", &[]), msg).unwrap();
+        let res = query(deps.as_ref(), mock_env(), QueryMsg::Status {}).unwrap();
+        let status: StatusResponse = from_json(&res).unwrap();
+        assert_eq!(status.denoms[0].denom, NATIVE_IBC_DENOM);
+        assert_eq!(
+            status.denoms[0].ls_token_denom,
+            Some(LIQUIDSTAKE_DENOM.to_string())
+        );
+        assert_eq!(status.denoms[0].stake_count, 0);
+
+        // the mint takes the balance from 1000 to 2000
+        CURRENT_TX
+            .update(deps.as_mut().storage, |mut tx| -> StdResult<_> {
+                tx.prev_ls_token_balance = Uint128::new(1000);
+                Ok(tx)
+            })
+            .unwrap();
+        handle_ls_reply(deps.as_mut(), mock_env(), ok_reply()).unwrap();
+
+        let res = query(deps.as_ref(), mock_env(), QueryMsg::Status {}).unwrap();
+        let status: StatusResponse = from_json(&res).unwrap();
+        assert_eq!(status.stake_in_flight, None);
+        assert_eq!(status.stake_count, 1);
+        assert_eq!(
+            status.denoms[0],
+            DenomStatus {
+                denom: NATIVE_IBC_DENOM.to_string(),
+                provider: "liquidstakeibc".to_string(),
+                native_balance: Uint128::zero(),
+                ls_token_denom: Some(LIQUIDSTAKE_DENOM.to_string()),
+                ls_token_balance: Some(Uint128::new(2000)),
+                stake_count: 1,
+                native_volume: Uint128::new(1000),
+                ls_token_minted: Uint128::new(1000),
+            }
+        );
+    }
+
+    #[test]
+    fn test_ls_reply_with_protocol_fee() {
+        let (mut deps, _env, info) = default_instantiate();
+
+        let msg = ExecuteMsg::UpdateConfig {
+            active: None,
+            ls_prefix: None,
+            protocol_fee: Some(Decimal::percent(5)),
+            fee_recipient: Some("treasury".to_string()),
+            history_limit: None,
+            swap_router: None,
+            verify_ls_prefix: None,
+            config_timelock: None,
+        };
+        execute(deps.as_mut(), mock_env(), info, msg).unwrap();
+
+        let current_tx = LSInfo {
+            sender: Addr::unchecked("sender"),
+            receiver: Addr::unchecked("receiver"),
+            native_denom: NATIVE_IBC_DENOM.to_string(),
+            native_amount: Uint128::new(1000u128),
+            ls_token_denom: LIQUIDSTAKE_DENOM.to_string(),
+            prev_ls_token_balance: Uint128::new(1000u128),
+            referral: None,
+            post_action: None,
+            funding: StakeFunding::Funds,
+            keeper: None,
+        };
+        CURRENT_TX.save(deps.as_mut().storage, &current_tx).unwrap();
+
+        let res = handle_ls_reply(deps.as_mut(), mock_env(), ok_reply()).unwrap();
+        assert_eq!(
+            res.messages,
+            vec![
+                SubMsg::new(BankMsg::Send {
+                    to_address: "receiver".to_string(),
+                    amount: coins(950, LIQUIDSTAKE_DENOM),
+                }),
+                SubMsg::new(BankMsg::Send {
+                    to_address: "treasury".to_string(),
+                    amount: coins(50, LIQUIDSTAKE_DENOM),
+                }),
+            ]
+        );
+
+        let record = query_stake(deps.as_ref(), 1).unwrap();
+        assert_eq!(Uint128::new(950u128), record.ls_token_amount);
+        assert_eq!(Uint128::new(50u128), record.fee);
+    }
+
+    #[test]
+    fn test_stake_history() {
+        let (mut deps, _env, info) = default_instantiate();
+
+        let msg = ExecuteMsg::UpdateConfig {
+            active: None,
+            ls_prefix: None,
+            protocol_fee: None,
+            fee_recipient: None,
+            history_limit: Some(3),
+            swap_router: None,
+            verify_ls_prefix: None,
+            config_timelock: None,
+        };
+        execute(deps.as_mut(), mock_env(), info, msg).unwrap();
+
+        // alice stakes for herself, bob stakes for alice and for himself
+        for (sender, receiver) in [
+            ("alice", "alice"),
+            ("bob", "alice"),
+            ("bob", "bob"),
+            ("alice", "alice"),
+        ] {
+            let current_tx = LSInfo {
+                sender: Addr::unchecked(sender),
+                receiver: Addr::unchecked(receiver),
+                native_denom: NATIVE_IBC_DENOM.to_string(),
+                native_amount: Uint128::new(1000u128),
+                ls_token_denom: LIQUIDSTAKE_DENOM.to_string(),
+                prev_ls_token_balance: Uint128::new(1000u128),
+                referral: None,
+                post_action: None,
+                funding: StakeFunding::Funds,
+                keeper: None,
+            };
+            CURRENT_TX.save(deps.as_mut().storage, &current_tx).unwrap();
+            handle_ls_reply(deps.as_mut(), mock_env(), ok_reply()).unwrap();
+        }
+
+        // the oldest record was pruned by the retention limit
+        query_stake(deps.as_ref(), 1).unwrap_err();
+
+        let ids = |address: &str, start_after: Option<u64>, limit: Option<u32>| {
+            let res = query(
+                deps.as_ref(),
+                mock_env(),
+                QueryMsg::StakeHistory {
+                    address: address.to_string(),
+                    start_after,
+                    limit,
+                },
+            )
+            .unwrap();
+            let res: StakeHistoryResponse = from_json(&res).unwrap();
+            res.stakes.into_iter().map(|r| r.id).collect::<Vec<_>>()
+        };
+        assert_eq!(vec![2, 4], ids("alice", None, None));
+        assert_eq!(vec![2, 3], ids("bob", None, None));
+        assert_eq!(vec![2], ids("bob", None, Some(1)));
+        assert_eq!(vec![3], ids("bob", Some(2), Some(1)));
+        assert!(ids("carol", None, None).is_empty());
+    }
+
+    #[test]
+    fn test_denom_config_providers() {
+        let (mut deps, _env, info) = default_instantiate();
+
+        let hub_msg = to_json_binary(&"liquid_stake").unwrap();
+        let configs = [
+            ("uxprt", LsProvider::LiquidStake {}),
+            (
+                "ibc/HUB",
+                LsProvider::WasmHub {
+                    contract: Addr::unchecked("hub"),
+                    execute_msg: hub_msg.clone(),
+                    ls_token_denom: "factory/hub/lst".to_string(),
+                },
+            ),
+        ];
+        for (denom, provider) in configs {
+            let msg = ExecuteMsg::SetDenomConfig {
+                denom: denom.to_string(),
+                config: Some(DenomConfig {
+                    provider,
+                    ls_token_denom: None,
+                    min_amount: None,
+                    max_amount: None,
+                    enforce_module_minimum: false,
+                    batch: None,
+                }),
+            };
+            let err = execute(
+                deps.as_mut(),
+                mock_env(),
+                mock_info("anyone", &[]),
+                msg.clone(),
+            )
+            .unwrap_err();
+            assert_eq!(err, ContractError::Unauthorized {});
+            execute(deps.as_mut(), mock_env(), info.clone(), msg).unwrap();
+        }
+
+        let stake = |deps: &mut OwnedDeps<_, _, _>, denom: &str| {
+            let msg = ExecuteMsg::LiquidStake {
+                receiver: Addr::unchecked("persistencereceiver"),
+                referral: None,
+                post_action: None,
+            };
+            let info = mock_info("anyone", &coins(1000, denom));
+            execute(deps.as_mut(), mock_env(), info, msg).unwrap()
+        };
+
+        // native XPRT skips the denom trace and uses the liquidstake module
+        let res = stake(&mut deps, "uxprt");
+        assert_eq!(
+            res.messages[0].msg,
+            CosmosMsg::Stargate {
+                type_url: "/pstake.liquidstake.v1beta1.MsgLiquidStake".to_string(),
+                value: persistence_std::types::pstake::liquidstake::v1beta1::MsgLiquidStake {
+                    delegator_address: "cosmos2contract".to_string(),
+                    amount: Some(StdCoin {
+                        denom: "uxprt".to_string(),
+                        amount: "1000".to_string(),
+                    }),
+                }
+                .into(),
+            }
+        );
+        assert_eq!(
+            CURRENT_TX.load(&deps.storage).unwrap().ls_token_denom,
+            "stk/uxprt"
+        );
+
+        // hub contracts receive the tokens as funds
+        let res = stake(&mut deps, "ibc/HUB");
+        assert_eq!(
+            res.messages[0].msg,
+            CosmosMsg::Wasm(WasmMsg::Execute {
+                contract_addr: "hub".to_string(),
+                msg: hub_msg,
+                funds: coins(1000, "ibc/HUB"),
+            })
+        );
+        assert_eq!(
+            CURRENT_TX.load(&deps.storage).unwrap().ls_token_denom,
+            "factory/hub/lst"
+        );
+
+        // removing the config falls back to liquidstakeibc
+        let msg = ExecuteMsg::SetDenomConfig {
+            denom: "ibc/HUB".to_string(),
+            config: None,
+        };
+        execute(deps.as_mut(), mock_env(), info, msg).unwrap();
+        let res = query(
+            deps.as_ref(),
+            mock_env(),
+            QueryMsg::DenomConfig {
+                denom: "ibc/HUB".to_string(),
+            },
+        )
+        .unwrap();
+        let value: DenomConfig = from_json(&res).unwrap();
+        assert_eq!(value.provider, LsProvider::LiquidStakeIbc {});
+    }
+
+    #[test]
+    fn test_liquid_stake_native_xprt() {
+        let (mut deps, _env, _info) = default_instantiate();
+
+        // native XPRT skips the denom trace and uses the liquidstake module
+        let info = mock_info("anyone", &coins(1000, NATIVE_BOND_DENOM));
+        let msg = ExecuteMsg::LiquidStake {
+            receiver: Addr::unchecked("persistencereceiver"),
+            referral: None,
+            post_action: None,
+        };
+        let res = execute(deps.as_mut(), mock_env(), info, msg).unwrap();
+        assert_eq!(
+            res.messages[0],
+            SubMsg::reply_always(
+                CosmosMsg::Stargate {
+                    type_url: "/pstake.liquidstake.v1beta1.MsgLiquidStake".to_string(),
+                    value: persistence_std::types::pstake::liquidstake::v1beta1::MsgLiquidStake {
+                        delegator_address: "cosmos2contract".to_string(),
+                        amount: Some(StdCoin {
+                            denom: NATIVE_BOND_DENOM.to_string(),
+                            amount: "1000".to_string(),
+                        }),
+                    }
+                    .into(),
+                },
+                LS_REPLY_ID,
+            )
+        );
+        assert_eq!(res.attributes[1], attr("provider", "liquidstake"));
+        assert_eq!(
+            CURRENT_TX.load(&deps.storage).unwrap().ls_token_denom,
+            "stk/uxprt"
+        );
+
+        // other native denoms can not be liquid staked
+        let info = mock_info("anyone", &coins(1000, "factory/someone/token"));
+        let msg = ExecuteMsg::LiquidStake {
+            receiver: Addr::unchecked("persistencereceiver"),
+            referral: None,
+            post_action: None,
+        };
+        let err = execute(deps.as_mut(), mock_env(), info, msg).unwrap_err();
+        assert_eq!(
+            err,
+            ContractError::InvalidDenom {
+                denom: "factory/someone/token".to_string()
+            }
+        );
+    }
+
+    #[test]
+    fn test_post_action() {
+        let (mut deps, _env, info) = default_instantiate();
+
+        let msg = ExecuteMsg::SetPostAction {
+            name: "dexter-stkatom".to_string(),
+            action: Some(PostAction::DexterJoinPool {
+                vault: Addr::unchecked("dextervault"),
+                pool_id: Uint128::new(7),
+            }),
+        };
+        let err = execute(
+            deps.as_mut(),
+            mock_env(),
+            mock_info("anyone", &[]),
+            msg.clone(),
+        )
+        .unwrap_err();
+        assert_eq!(err, ContractError::Unauthorized {});
+        execute(deps.as_mut(), mock_env(), info, msg).unwrap();
+
+        let stake_info = mock_info("anyone", &coins(1000, NATIVE_IBC_DENOM));
+        let msg = ExecuteMsg::LiquidStake {
+            receiver: Addr::unchecked("persistencereceiver"),
+            referral: None,
+            post_action: Some("unknown".to_string()),
+        };
+        let err = execute(deps.as_mut(), mock_env(), stake_info.clone(), msg).unwrap_err();
+        assert_eq!(
+            err,
+            ContractError::UnknownPostAction {
+                name: "unknown".to_string()
+            }
+        );
+
+        let msg = ExecuteMsg::LiquidStake {
+            receiver: Addr::unchecked("persistencereceiver"),
+            referral: None,
+            post_action: Some("dexter-stkatom".to_string()),
+        };
+        execute(deps.as_mut(), mock_env(), stake_info, msg).unwrap();
+
+        // the mock querier reports 2000, so pretend 1000 were minted
+        CURRENT_TX
+            .update(deps.as_mut().storage, |mut tx| -> StdResult<_> {
+                tx.prev_ls_token_balance = Uint128::new(1000u128);
+                Ok(tx)
+            })
+            .unwrap();
+
+        // minted tokens join the pool on behalf of the receiver
+        let res = handle_ls_reply(deps.as_mut(), mock_env(), ok_reply()).unwrap();
+        assert_eq!(
+            res.messages,
+            vec![SubMsg::reply_always(
+                WasmMsg::Execute {
+                    contract_addr: "dextervault".to_string(),
+                    msg: Binary::from(
+                        br#"{"join_pool":{"pool_id":"7","recipient":"persistencereceiver","assets":[{"info":{"native_token":{"denom":"stk/uatom"}},"amount":"1000"}],"min_lp_to_receive":null,"auto_stake":null}}"#
+                            .as_slice()
+                    ),
+                    funds: coins(1000, LIQUIDSTAKE_DENOM),
+                },
+                POST_ACTION_REPLY_ID,
+            )]
+        );
+        assert_eq!(
+            query_stake(deps.as_ref(), 1).unwrap().post_action,
+            Some("dexter-stkatom".to_string())
+        );
+
+        // a failed join falls back to delivering the LS tokens
+        let post_action_state = CURRENT_POST_ACTION.load(&deps.storage).unwrap();
+        let res = reply(
+            deps.as_mut(),
+            mock_env(),
+            Reply {
+                id: POST_ACTION_REPLY_ID,
+                result: SubMsgResult::Err("pool is paused".to_string()),
+            },
+        )
+        .unwrap();
+        assert_eq!(
+            res.messages,
+            vec![SubMsg::new(BankMsg::Send {
+                to_address: "persistencereceiver".to_string(),
+                amount: coins(1000, LIQUIDSTAKE_DENOM),
+            })]
+        );
+        assert_eq!(
+            res.attributes.last().unwrap(),
+            attr("error", "pool is paused")
+        );
+
+        // a successful join needs no further transfer
+        CURRENT_POST_ACTION
+            .save(deps.as_mut().storage, &post_action_state)
+            .unwrap();
+        let res = reply(
+            deps.as_mut(),
+            mock_env(),
+            Reply {
+                id: POST_ACTION_REPLY_ID,
+                result: SubMsgResult::Ok(SubMsgResponse {
+                    events: vec![],
+                    data: None,
+                }),
+            },
+        )
+        .unwrap();
+        assert!(res.messages.is_empty());
+        assert_eq!(res.attributes.last().unwrap(), attr("status", "success"));
+    }
+
+    #[test]
+    fn test_swap_and_liquid_stake() {
+        let (mut deps, _env, info) = default_instantiate();
+
+        let offer_info = mock_info("anyone", &coins(500, "uusdc"));
+        let route = vec![
+            SwapHop {
+                pool_id: Uint128::new(1),
+                ask_denom: "uxprt".to_string(),
+            },
+            SwapHop {
+                pool_id: Uint128::new(2),
+                ask_denom: NATIVE_IBC_DENOM.to_string(),
+            },
+        ];
+        let msg = ExecuteMsg::SwapAndLiquidStake {
+            receiver: Addr::unchecked("persistencereceiver"),
+            route: route.clone(),
+            min_out: Uint128::new(450),
+            referral: None,
+            post_action: None,
+        };
+        let err = execute(deps.as_mut(), mock_env(), offer_info.clone(), msg.clone()).unwrap_err();
+        assert_eq!(err, ContractError::SwapRouterNotSet {});
+
+        let update = ExecuteMsg::UpdateConfig {
+            active: None,
+            ls_prefix: None,
+            protocol_fee: None,
+            fee_recipient: None,
+            history_limit: None,
+            swap_router: Some("router".to_string()),
+            verify_ls_prefix: None,
+            config_timelock: None,
+        };
+        execute(deps.as_mut(), mock_env(), info, update).unwrap();
+
+        // swapping into the offered denom is not a route
+        let bad_route = ExecuteMsg::SwapAndLiquidStake {
+            receiver: Addr::unchecked("persistencereceiver"),
+            route: vec![SwapHop {
+                pool_id: Uint128::new(1),
+                ask_denom: "uusdc".to_string(),
+            }],
+            min_out: Uint128::new(450),
+            referral: None,
+            post_action: None,
+        };
+        let err = execute(deps.as_mut(), mock_env(), offer_info.clone(), bad_route).unwrap_err();
+        assert_eq!(err, ContractError::InvalidSwapRoute {});
+
+        let res = execute(deps.as_mut(), mock_env(), offer_info, msg).unwrap();
+        assert_eq!(
+            res.messages,
+            vec![SubMsg::reply_always(
+                WasmMsg::Execute {
+                    contract_addr: "router".to_string(),
+                    msg: to_json_binary(&RouterExecuteMsg::ExecuteMultihopSwap {
+                        requests: vec![
+                            HopSwapRequest {
+                                pool_id: Uint128::new(1),
+                                asset_in: AssetInfo::NativeToken {
+                                    denom: "uusdc".to_string()
+                                },
+                                asset_out: AssetInfo::NativeToken {
+                                    denom: "uxprt".to_string()
+                                },
+                            },
+                            HopSwapRequest {
+                                pool_id: Uint128::new(2),
+                                asset_in: AssetInfo::NativeToken {
+                                    denom: "uxprt".to_string()
+                                },
+                                asset_out: AssetInfo::NativeToken {
+                                    denom: NATIVE_IBC_DENOM.to_string()
+                                },
+                            },
+                        ],
+                        offer_amount: Uint128::new(500),
+                        recipient: None,
+                        minimum_receive: Some(Uint128::new(450)),
+                    })
+                    .unwrap(),
+                    funds: coins(500, "uusdc"),
+                },
+                SWAP_REPLY_ID,
+            )]
+        );
+
+        // a failed swap refunds the offered tokens to the receiver
+        let res = reply(
+            deps.as_mut(),
+            mock_env(),
+            Reply {
+                id: SWAP_REPLY_ID,
+                result: SubMsgResult::Err("slippage".to_string()),
+            },
+        )
+        .unwrap();
+        assert_eq!(
+            res.messages,
+            vec![SubMsg::new(BankMsg::Send {
+                to_address: "persistencereceiver".to_string(),
+                amount: coins(500, "uusdc"),
+            })]
+        );
+        assert!(CURRENT_SWAP.may_load(&deps.storage).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_referral() {
+        let (mut deps, _env, info) = default_instantiate();
+
+        let msg = ExecuteMsg::UpdateConfig {
+            active: None,
+            ls_prefix: None,
+            protocol_fee: Some(Decimal::percent(10)),
+            fee_recipient: Some("treasury".to_string()),
+            history_limit: None,
+            swap_router: None,
+            verify_ls_prefix: None,
+            config_timelock: None,
+        };
+        execute(deps.as_mut(), mock_env(), info.clone(), msg).unwrap();
+
+        // only admin can register referrers
+        let msg = ExecuteMsg::RegisterReferrer {
+            code: "partner-1".to_string(),
+            address: "partner".to_string(),
+            fee_share: Some(Decimal::percent(40)),
+        };
+        let err = execute(
+            deps.as_mut(),
+            mock_env(),
+            mock_info("anyone", &[]),
+            msg.clone(),
+        )
+        .unwrap_err();
+        assert_eq!(err, ContractError::Unauthorized {});
+        execute(deps.as_mut(), mock_env(), info.clone(), msg).unwrap();
+
+        let msg = ExecuteMsg::RegisterReferrer {
+            code: "not a code".to_string(),
+            address: "partner".to_string(),
+            fee_share: None,
+        };
+        let err = execute(deps.as_mut(), mock_env(), info, msg).unwrap_err();
+        assert_eq!(
+            err,
+            ContractError::InvalidReferralCode {
+                code: "not a code".to_string()
+            }
+        );
+
+        // unknown codes are rejected before staking
+        let stake_info = mock_info("anyone", &coins(1000, NATIVE_IBC_DENOM));
+        let msg = ExecuteMsg::LiquidStake {
+            receiver: Addr::unchecked("persistencereceiver"),
+            referral: Some("unknown".to_string()),
+            post_action: None,
+        };
+        let err = execute(deps.as_mut(), mock_env(), stake_info.clone(), msg).unwrap_err();
+        assert_eq!(
+            err,
+            ContractError::UnknownReferral {
+                code: "unknown".to_string()
+            }
+        );
+
+        let msg = ExecuteMsg::LiquidStake {
+            receiver: Addr::unchecked("persistencereceiver"),
+            referral: Some("partner-1".to_string()),
+            post_action: None,
+        };
+        let res = execute(deps.as_mut(), mock_env(), stake_info, msg).unwrap();
+        assert_eq!(
+            res.attributes.last().unwrap(),
+            attr("referral", "partner-1")
+        );
+
+        // the mock querier reports 2000, so pretend 1000 were minted
+        CURRENT_TX
+            .update(deps.as_mut().storage, |mut tx| -> StdResult<_> {
+                tx.prev_ls_token_balance = Uint128::new(1000u128);
+                Ok(tx)
+            })
+            .unwrap();
+
+        // minted 1000, fee 100 of which 40 go to the referrer
+        let res = handle_ls_reply(deps.as_mut(), mock_env(), ok_reply()).unwrap();
+        assert_eq!(
+            res.messages,
+            vec![
+                SubMsg::new(BankMsg::Send {
+                    to_address: "persistencereceiver".to_string(),
+                    amount: coins(900, LIQUIDSTAKE_DENOM),
+                }),
+                SubMsg::new(BankMsg::Send {
+                    to_address: "partner".to_string(),
+                    amount: coins(40, LIQUIDSTAKE_DENOM),
+                }),
+                SubMsg::new(BankMsg::Send {
+                    to_address: "treasury".to_string(),
+                    amount: coins(60, LIQUIDSTAKE_DENOM),
+                }),
+            ]
+        );
+
+        let res = query(
+            deps.as_ref(),
+            mock_env(),
+            QueryMsg::ReferrerStats {
+                code: "partner-1".to_string(),
+            },
+        )
+        .unwrap();
+        let stats: ReferrerStatsResponse = from_json(&res).unwrap();
+        assert_eq!(
+            stats,
+            ReferrerStatsResponse {
+                code: "partner-1".to_string(),
+                address: Addr::unchecked("partner"),
+                fee_share: Decimal::percent(40),
+                stake_count: 1,
+                volume: coins(1000, NATIVE_IBC_DENOM),
+                fees_paid: coins(40, LIQUIDSTAKE_DENOM),
+            }
+        );
+    }
+
+    #[test]
+    fn test_swap_reply_balance_decrease() {
+        let (mut deps, _env, _info) = default_instantiate();
+
+        deps.querier
+            .set_balance(MOCK_CONTRACT_ADDR, coins(500, NATIVE_BOND_DENOM));
+        CURRENT_SWAP
+            .save(
+                deps.as_mut().storage,
+                &SwapInfo {
+                    sender: Addr::unchecked("sender"),
+                    receiver: Addr::unchecked("persistencereceiver"),
+                    offer: Coin::new(300, NATIVE_IBC_DENOM),
+                    ask_denom: NATIVE_BOND_DENOM.to_string(),
+                    prev_ask_balance: Uint128::new(1000),
+                    referral: None,
+                    post_action: None,
+                },
+            )
+            .unwrap();
+
+        // a lower balance after the swap is an error, not an overflow
+        let reply = Reply {
+            id: SWAP_REPLY_ID,
+            result: SubMsgResult::Ok(SubMsgResponse {
+                events: vec![],
+                data: None,
+            }),
+        };
+        let err = handle_swap_reply(deps.as_mut(), mock_env(), reply).unwrap_err();
+        assert!(matches!(err, ContractError::Std(StdError::Overflow { .. })));
+    }
+
+    #[test]
+    fn test_sweep() {
+        let (mut deps, _env, info) = default_instantiate();
+
+        deps.querier.set_balance(
+            MOCK_CONTRACT_ADDR,
+            vec![
+                Coin::new(2000, LIQUIDSTAKE_DENOM),
+                Coin::new(50, "uusdc"),
+                Coin::new(300, NATIVE_IBC_DENOM),
+            ],
+        );
+        // owed to users: recorded liabilities and the offer of a swap in flight
+        LIABILITIES
+            .save(
+                deps.as_mut().storage,
+                LIQUIDSTAKE_DENOM,
+                &Uint128::new(1500),
+            )
+            .unwrap();
+        CURRENT_SWAP
+            .save(
+                deps.as_mut().storage,
+                &SwapInfo {
+                    sender: Addr::unchecked("sender"),
+                    receiver: Addr::unchecked("receiver"),
+                    offer: Coin::new(300, NATIVE_IBC_DENOM),
+                    ask_denom: NATIVE_BOND_DENOM.to_string(),
+                    prev_ask_balance: Uint128::zero(),
+                    referral: None,
+                    post_action: None,
+                },
+            )
+            .unwrap();
+
+        let res = query(deps.as_ref(), mock_env(), QueryMsg::SweepableBalances {}).unwrap();
+        let sweepable: SweepableBalancesResponse = from_json(&res).unwrap();
+        assert_eq!(
+            sweepable.balances,
+            vec![Coin::new(500, LIQUIDSTAKE_DENOM), Coin::new(50, "uusdc")]
+        );
+
+        let msg = ExecuteMsg::Sweep {
+            denoms: vec![
+                "uusdc".to_string(),
+                LIQUIDSTAKE_DENOM.to_string(),
+                NATIVE_IBC_DENOM.to_string(),
+            ],
+            to: "treasury".to_string(),
+        };
+        let err = execute(
+            deps.as_mut(),
+            mock_env(),
+            mock_info("anyone", &[]),
+            msg.clone(),
+        )
+        .unwrap_err();
+        assert_eq!(err, ContractError::Unauthorized {});
+
+        let res = execute(deps.as_mut(), mock_env(), info.clone(), msg).unwrap();
+        assert_eq!(
+            res.messages,
+            vec![SubMsg::new(BankMsg::Send {
+                to_address: "treasury".to_string(),
+                amount: vec![Coin::new(500, LIQUIDSTAKE_DENOM), Coin::new(50, "uusdc")],
+            })]
+        );
+
+        // the swap offer is never swept
+        let msg = ExecuteMsg::Sweep {
+            denoms: vec![NATIVE_IBC_DENOM.to_string()],
+            to: "treasury".to_string(),
+        };
+        let err = execute(deps.as_mut(), mock_env(), info, msg).unwrap_err();
+        assert_eq!(err, ContractError::NothingToSweep {});
+    }
+
+    #[test]
+    fn test_failed_stakes() {
+        let (mut deps, _env, _info) = default_instantiate();
+
+        let paused_reply = || Reply {
+            id: LS_REPLY_ID,
+            result: SubMsgResult::Err("unbonding epoch in progress".to_string()),
+        };
+        let liquid_stake = |deps: &mut OwnedDeps<_, _, _>| {
+            let msg = ExecuteMsg::LiquidStake {
+                receiver: Addr::unchecked("persistencereceiver"),
+                referral: None,
+                post_action: None,
+            };
+            let info = mock_info("sender", &coins(1000, NATIVE_IBC_DENOM));
+            execute(deps.as_mut(), mock_env(), info, msg).unwrap();
+        };
+
+        // a transient failure parks the deposit
+        liquid_stake(&mut deps);
+        let res = handle_ls_reply(deps.as_mut(), mock_env(), paused_reply()).unwrap();
+        assert!(res.messages.is_empty());
+        assert_eq!(
+            res.attributes,
+            vec![
+                attr("method", "handle_ls_reply"),
+                attr("status", "failed"),
+                attr("failed_stake_id", "1"),
+                attr("amount", format!("1000{NATIVE_IBC_DENOM}")),
+                attr("receiver", "persistencereceiver"),
+                attr("error", "Module is paused (codespace , code 0)"),
+            ]
+        );
+        let failed_stake = FailedStake {
+            id: 1,
+            sender: Addr::unchecked("sender"),
+            receiver: Addr::unchecked("persistencereceiver"),
+            coin: Coin::new(1000, NATIVE_IBC_DENOM),
+            referral: None,
+            post_action: None,
+            funding: StakeFunding::Funds,
+            error: "Module is paused (codespace , code 0)".to_string(),
+            failed_at: mock_env().block.time,
+        };
+        let msg = QueryMsg::FailedStakes {
+            start_after: None,
+            limit: None,
+        };
+        let res = query(deps.as_ref(), mock_env(), msg.clone()).unwrap();
+        let failed: FailedStakesResponse = from_json(&res).unwrap();
+        assert_eq!(failed.stakes, vec![failed_stake]);
+        assert_eq!(
+            LIABILITIES
+                .load(deps.as_ref().storage, NATIVE_IBC_DENOM)
+                .unwrap(),
+            Uint128::new(1000)
+        );
+
+        // anyone can retry, the stake is dispatched again
+        let res = execute(
+            deps.as_mut(),
+            mock_env(),
+            mock_info("keeper", &[]),
+            ExecuteMsg::RetryStake { id: 1 },
+        )
+        .unwrap();
+        assert_eq!(res.messages.len(), 1);
+        assert_eq!(res.messages[0].id, LS_REPLY_ID);
+        assert!(res.attributes.contains(&attr("failed_stake_id", "1")));
+        assert_eq!(
+            CURRENT_RETRY.load(deps.as_ref().storage).unwrap(),
+            RetryInfo {
+                id: 1,
+                keeper: Addr::unchecked("keeper"),
+            }
+        );
+        assert!(!LIABILITIES.has(deps.as_ref().storage, NATIVE_IBC_DENOM));
+        let res = query(deps.as_ref(), mock_env(), msg.clone()).unwrap();
+        let failed: FailedStakesResponse = from_json(&res).unwrap();
+        assert!(failed.stakes.is_empty());
+
+        // a retry failing again reverts instead of parking the stake twice
+        let err = handle_ls_reply(deps.as_mut(), mock_env(), paused_reply()).unwrap_err();
+        assert_eq!(
+            err,
+            ContractError::ModulePaused {
+                codespace: String::new(),
+                code: 0,
+            }
+        );
+
+        let err = execute(
+            deps.as_mut(),
+            mock_env(),
+            mock_info("keeper", &[]),
+            ExecuteMsg::RetryStake { id: 1 },
+        )
+        .unwrap_err();
+        assert_eq!(err, ContractError::UnknownFailedStake { id: 1 });
+
+        // only the sender or the receiver can cancel, the refund goes to the receiver
+        liquid_stake(&mut deps);
+        handle_ls_reply(deps.as_mut(), mock_env(), paused_reply()).unwrap();
+        let err = execute(
+            deps.as_mut(),
+            mock_env(),
+            mock_info("keeper", &[]),
+            ExecuteMsg::CancelStake { id: 2 },
+        )
+        .unwrap_err();
+        assert_eq!(err, ContractError::Unauthorized {});
+
+        let res = execute(
+            deps.as_mut(),
+            mock_env(),
+            mock_info("sender", &[]),
+            ExecuteMsg::CancelStake { id: 2 },
+        )
+        .unwrap();
+        assert_eq!(
+            res.messages,
+            vec![SubMsg::new(BankMsg::Send {
+                to_address: "persistencereceiver".to_string(),
+                amount: coins(1000, NATIVE_IBC_DENOM),
+            })]
+        );
+        assert!(!LIABILITIES.has(deps.as_ref().storage, NATIVE_IBC_DENOM));
+        let res = query(deps.as_ref(), mock_env(), msg).unwrap();
+        let failed: FailedStakesResponse = from_json(&res).unwrap();
+        assert!(failed.stakes.is_empty());
+    }
+
+    #[test]
+    fn test_keeper_tip() {
+        let (mut deps, _env, info) = default_instantiate();
+
+        let msg = ExecuteMsg::SetKeeperTip {
+            fee_share: Decimal::percent(50),
+            tips_per_block: 1,
+        };
+        let err = execute(
+            deps.as_mut(),
+            mock_env(),
+            mock_info("anyone", &[]),
+            msg.clone(),
+        )
+        .unwrap_err();
+        assert_eq!(err, ContractError::Unauthorized {});
+        let err = execute(
+            deps.as_mut(),
+            mock_env(),
+            info.clone(),
+            ExecuteMsg::SetKeeperTip {
+                fee_share: Decimal::percent(101),
+                tips_per_block: 1,
+            },
+        )
+        .unwrap_err();
+        assert_eq!(err, ContractError::InvalidFeeShare {});
+        execute(deps.as_mut(), mock_env(), info.clone(), msg).unwrap();
+        let msg = ExecuteMsg::UpdateConfig {
+            active: None,
+            ls_prefix: None,
+            protocol_fee: Some(Decimal::percent(10)),
+            fee_recipient: None,
+            history_limit: None,
+            swap_router: None,
+            verify_ls_prefix: None,
+            config_timelock: None,
+        };
+        execute(deps.as_mut(), mock_env(), info, msg).unwrap();
+        assert_eq!(
+            query_ls_config(deps.as_ref()).unwrap(),
+            LsConfigBuilder::new("creator")
+                .protocol_fee(Decimal::percent(10))
+                .keeper_tip(Decimal::percent(50), 1)
+                .build()
+        );
+
+        // park two stakes
+        for _ in 0..2 {
+            let msg = ExecuteMsg::LiquidStake {
+                receiver: Addr::unchecked("persistencereceiver"),
+                referral: None,
+                post_action: None,
+            };
+            let info = mock_info("sender", &coins(1000, NATIVE_IBC_DENOM));
+            execute(deps.as_mut(), mock_env(), info, msg).unwrap();
+            let reply = Reply {
+                id: LS_REPLY_ID,
+                result: SubMsgResult::Err("unbonding epoch in progress".to_string()),
+            };
+            handle_ls_reply(deps.as_mut(), mock_env(), reply).unwrap();
+        }
+
+        // the keeper gets half of the protocol fee of the retried stake
+        let keeper = mock_info("keeper", &[]);
+        execute(
+            deps.as_mut(),
+            mock_env(),
+            keeper.clone(),
+            ExecuteMsg::RetryStake { id: 1 },
+        )
+        .unwrap();
+        deps.querier
+            .set_balance(MOCK_CONTRACT_ADDR, coins(3000, LIQUIDSTAKE_DENOM));
+        let res = handle_ls_reply(deps.as_mut(), mock_env(), ok_reply()).unwrap();
+        assert!(res.attributes.contains(&attr("keeper", "keeper")));
+        assert!(res.attributes.contains(&attr("keeper_tip", "50")));
+        assert!(res.messages.contains(&SubMsg::new(BankMsg::Send {
+            to_address: "creator".to_string(),
+            amount: coins(50, LIQUIDSTAKE_DENOM),
+        })));
+        assert_eq!(
+            LIABILITIES
+                .load(deps.as_ref().storage, LIQUIDSTAKE_DENOM)
+                .unwrap(),
+            Uint128::new(50)
+        );
+
+        // the tips per block are used up, the second retry goes untipped
+        execute(
+            deps.as_mut(),
+            mock_env(),
+            keeper.clone(),
+            ExecuteMsg::RetryStake { id: 2 },
+        )
+        .unwrap();
+        deps.querier
+            .set_balance(MOCK_CONTRACT_ADDR, coins(4000, LIQUIDSTAKE_DENOM));
+        let res = handle_ls_reply(deps.as_mut(), mock_env(), ok_reply()).unwrap();
+        assert!(!res.attributes.contains(&attr("keeper", "keeper")));
+        assert!(res.messages.contains(&SubMsg::new(BankMsg::Send {
+            to_address: "creator".to_string(),
+            amount: coins(100, LIQUIDSTAKE_DENOM),
+        })));
+
+        let msg = QueryMsg::KeeperRewards {
+            address: "keeper".to_string(),
+        };
+        let res = query(deps.as_ref(), mock_env(), msg.clone()).unwrap();
+        let rewards: KeeperRewardsResponse = from_json(&res).unwrap();
+        assert_eq!(rewards.rewards, coins(50, LIQUIDSTAKE_DENOM));
+
+        let res = execute(
+            deps.as_mut(),
+            mock_env(),
+            keeper.clone(),
+            ExecuteMsg::ClaimKeeperRewards {},
+        )
+        .unwrap();
+        assert_eq!(
+            res.messages,
+            vec![SubMsg::new(BankMsg::Send {
+                to_address: "keeper".to_string(),
+                amount: coins(50, LIQUIDSTAKE_DENOM),
+            })]
+        );
+        assert!(!LIABILITIES.has(deps.as_ref().storage, LIQUIDSTAKE_DENOM));
+        let res = query(deps.as_ref(), mock_env(), msg).unwrap();
+        let rewards: KeeperRewardsResponse = from_json(&res).unwrap();
+        assert!(rewards.rewards.is_empty());
+
+        let err = execute(
+            deps.as_mut(),
+            mock_env(),
+            keeper,
+            ExecuteMsg::ClaimKeeperRewards {},
+        )
+        .unwrap_err();
+        assert_eq!(err, ContractError::NoKeeperRewards {});
+    }
+
+    #[test]
+    fn test_batch() {
+        let (mut deps, _env, info) = default_instantiate();
+
+        let set_batch = |max_deposits: u32, max_age: Option<u64>| ExecuteMsg::SetDenomConfig {
+            denom: NATIVE_IBC_DENOM.to_string(),
+            config: Some(DenomConfig {
+                batch: Some(BatchConfig {
+                    max_amount: None,
+                    max_deposits,
+                    max_age,
+                }),
+                ..DenomConfig::default_for(NATIVE_IBC_DENOM)
+            }),
+        };
+        for max_deposits in [0, 101] {
+            let err = execute(
+                deps.as_mut(),
+                mock_env(),
+                info.clone(),
+                set_batch(max_deposits, None),
+            )
+            .unwrap_err();
+            assert_eq!(err, ContractError::InvalidBatchSize { max: 100 });
+        }
+        for max_age in [0, u64::MAX] {
+            let err = execute(
+                deps.as_mut(),
+                mock_env(),
+                info.clone(),
+                set_batch(3, Some(max_age)),
+            )
+            .unwrap_err();
+            assert_eq!(
+                err,
+                ContractError::InvalidBatchAge {
+                    max: 30 * 24 * 60 * 60
+                }
+            );
+        }
+        execute(deps.as_mut(), mock_env(), info, set_batch(3, None)).unwrap();
+
+        let liquid_stake = |deps: &mut OwnedDeps<_, _, _>, receiver: &str, amount: u128| {
+            let msg = ExecuteMsg::LiquidStake {
+                receiver: Addr::unchecked(receiver),
+                referral: None,
+                post_action: None,
+            };
+            let info = mock_info("sender", &coins(amount, NATIVE_IBC_DENOM));
+            execute(deps.as_mut(), mock_env(), info, msg).unwrap()
+        };
+        let query_batch = |deps: &OwnedDeps<_, _, _>| -> Option<Batch> {
+            let msg = QueryMsg::Batch {
+                denom: NATIVE_IBC_DENOM.to_string(),
+            };
+            from_json(query(deps.as_ref(), mock_env(), msg).unwrap()).unwrap()
+        };
+
+        // deposits are queued without a stake
+        let res = liquid_stake(&mut deps, "persistencealice", 1000);
+        assert!(res.messages.is_empty());
+        assert_eq!(
+            res.attributes,
+            vec![
+                attr("action", "queue_deposit"),
+                attr("sender", "sender"),
+                attr("native_amount", "1000"),
+                attr("native_ibc_denom", NATIVE_IBC_DENOM),
+                attr("receiver", "persistencealice"),
+                attr("batch_size", "1"),
+            ]
+        );
+        liquid_stake(&mut deps, "persistencebob", 2000);
+        let batch = query_batch(&deps).unwrap();
+        assert_eq!(batch.total, Uint128::new(3000));
+        assert_eq!(batch.deposits.len(), 2);
+        assert_eq!(
+            LIABILITIES
+                .load(deps.as_ref().storage, NATIVE_IBC_DENOM)
+                .unwrap(),
+            Uint128::new(3000)
+        );
+
+        // anyone can dispatch the batch as one stake
+        let res = execute(
+            deps.as_mut(),
+            mock_env(),
+            mock_info("keeper", &[]),
+            ExecuteMsg::ProcessBatch {
+                denom: NATIVE_IBC_DENOM.to_string(),
+            },
+        )
+        .unwrap();
+        assert_eq!(res.messages.len(), 1);
+        assert_eq!(res.messages[0].id, BATCH_REPLY_ID);
+        assert_eq!(query_batch(&deps), None);
+        assert!(!LIABILITIES.has(deps.as_ref().storage, NATIVE_IBC_DENOM));
+
+        // a transient failure puts the deposits back in the queue
+        let reply = Reply {
+            id: BATCH_REPLY_ID,
+            result: SubMsgResult::Err("unbonding epoch in progress".to_string()),
+        };
+        let res = handle_batch_reply(deps.as_mut(), mock_env(), reply).unwrap();
+        assert!(res.attributes.contains(&attr("status", "requeued")));
+        assert_eq!(query_batch(&deps).unwrap().total, Uint128::new(3000));
+
+        // the third deposit hits the threshold and dispatches the batch
+        let res = liquid_stake(&mut deps, "persistencealice", 3000);
+        assert_eq!(res.messages.len(), 1);
+        assert!(res.attributes.contains(&attr("action", "process_batch")));
+        assert!(res.attributes.contains(&attr("native_amount", "6000")));
+        assert_eq!(query_batch(&deps), None);
+
+        // the mint is shared pro-rata
+        deps.querier
+            .set_balance(MOCK_CONTRACT_ADDR, coins(2000 + 6001, LIQUIDSTAKE_DENOM));
+        let reply = Reply {
+            id: BATCH_REPLY_ID,
+            result: ok_reply().result,
+        };
+        let res = handle_batch_reply(deps.as_mut(), mock_env(), reply).unwrap();
+        assert_eq!(
+            res.messages,
+            vec![
+                SubMsg::new(BankMsg::Send {
+                    to_address: "persistencealice".to_string(),
+                    amount: coins(1000, LIQUIDSTAKE_DENOM),
+                }),
+                SubMsg::new(BankMsg::Send {
+                    to_address: "persistencebob".to_string(),
+                    amount: coins(2000, LIQUIDSTAKE_DENOM),
+                }),
+                SubMsg::new(BankMsg::Send {
+                    to_address: "persistencealice".to_string(),
+                    amount: coins(3001, LIQUIDSTAKE_DENOM),
+                }),
+            ]
+        );
+        assert_eq!(
+            res.attributes,
+            vec![
+                attr("method", "handle_batch_reply"),
+                attr("denom", NATIVE_IBC_DENOM),
+                attr("deposits", "3"),
+                attr("minted_lst_amount", "6001"),
+                attr("fee", "0"),
+            ]
+        );
+        let record = query_stake(deps.as_ref(), 2).unwrap();
+        assert_eq!(record.receiver, Addr::unchecked("persistencebob"));
+        assert_eq!(record.native_amount, Uint128::new(2000));
+        assert_eq!(record.ls_token_amount, Uint128::new(2000));
+
+        let err = execute(
+            deps.as_mut(),
+            mock_env(),
+            mock_info("keeper", &[]),
+            ExecuteMsg::ProcessBatch {
+                denom: NATIVE_IBC_DENOM.to_string(),
+            },
+        )
+        .unwrap_err();
+        assert_eq!(
+            err,
+            ContractError::EmptyBatch {
+                denom: NATIVE_IBC_DENOM.to_string()
+            }
+        );
+    }
+
+    #[test]
+    fn test_deposit_balance() {
+        let (mut deps, _env, _info) = default_instantiate();
+
+        let owner = mock_info("owner", &[]);
+        let info = mock_info("owner", &coins(3000, NATIVE_IBC_DENOM));
+        execute(deps.as_mut(), mock_env(), info, ExecuteMsg::Deposit {}).unwrap();
+
+        // tokens that can not be liquid staked are not taken
+        let info = mock_info("owner", &coins(3000, "uusdc"));
+        let err = execute(deps.as_mut(), mock_env(), info, ExecuteMsg::Deposit {}).unwrap_err();
+        assert_eq!(
+            err,
+            ContractError::InvalidDenom {
+                denom: "uusdc".to_string()
+            }
+        );
+
+        let stake = |amount: u128| ExecuteMsg::LiquidStakeFromBalance {
+            amount: Coin::new(amount, NATIVE_IBC_DENOM),
+            receiver: Addr::unchecked("persistencereceiver"),
+        };
+        let res = execute(deps.as_mut(), mock_env(), owner.clone(), stake(1000)).unwrap();
+        assert_eq!(res.messages.len(), 1);
+        assert!(res.attributes.contains(&attr("sender", "owner")));
+
+        let err = execute(deps.as_mut(), mock_env(), owner.clone(), stake(0)).unwrap_err();
+        assert_eq!(err, ContractError::InvalidAmount {});
+        let err = execute(deps.as_mut(), mock_env(), owner.clone(), stake(2001)).unwrap_err();
+        assert_eq!(
+            err,
+            ContractError::InsufficientDeposit {
+                available: Uint128::new(2000)
+            }
+        );
+
+        let withdraw = |amount: u128| ExecuteMsg::Withdraw {
+            amount: Coin::new(amount, NATIVE_IBC_DENOM),
+        };
+        let res = execute(deps.as_mut(), mock_env(), owner.clone(), withdraw(1500)).unwrap();
+        assert_eq!(
+            res.messages,
+            vec![SubMsg::new(BankMsg::Send {
+                to_address: "owner".to_string(),
+                amount: coins(1500, NATIVE_IBC_DENOM),
+            })]
+        );
+        let err = execute(deps.as_mut(), mock_env(), owner, withdraw(501)).unwrap_err();
+        assert_eq!(
+            err,
+            ContractError::InsufficientDeposit {
+                available: Uint128::new(500)
+            }
+        );
+
+        let msg = QueryMsg::DepositBalance {
+            address: "owner".to_string(),
+        };
+        let res = query(deps.as_ref(), mock_env(), msg).unwrap();
+        let balance: DepositBalanceResponse = from_json(&res).unwrap();
+        assert_eq!(balance.balances, coins(500, NATIVE_IBC_DENOM));
+        assert_eq!(
+            LIABILITIES
+                .load(deps.as_ref().storage, NATIVE_IBC_DENOM)
+                .unwrap(),
+            Uint128::new(500)
+        );
+    }
+
+    #[test]
+    fn test_operators() {
+        let (mut deps, _env, _info) = default_instantiate();
+
+        let msg = ExecuteMsg::Deposit {};
+        let info = mock_info("owner", &coins(5000, NATIVE_IBC_DENOM));
+        execute(deps.as_mut(), mock_env(), info, msg).unwrap();
+        assert_eq!(
+            LIABILITIES
+                .load(deps.as_ref().storage, NATIVE_IBC_DENOM)
+                .unwrap(),
+            Uint128::new(5000)
+        );
+
+        let stake_from = |amount: u128, denom: &str| ExecuteMsg::LiquidStakeFrom {
+            owner: "owner".to_string(),
+            coin: Coin::new(amount, denom),
+            receiver: Addr::unchecked("persistencereceiver"),
+        };
+        let hot = mock_info("hot", &[]);
+        let err = execute(
+            deps.as_mut(),
+            mock_env(),
+            hot.clone(),
+            stake_from(1000, NATIVE_IBC_DENOM),
+        )
+        .unwrap_err();
+        assert_eq!(
+            err,
+            ContractError::OperatorNotApproved {
+                operator: "hot".to_string()
+            }
+        );
+
+        let expires = Expiration::AtHeight(mock_env().block.height + 10);
+        let msg = ExecuteMsg::ApproveOperator {
+            operator: "hot".to_string(),
+            expires: Some(expires),
+            max_amount: Some(Coin::new(3000, NATIVE_IBC_DENOM)),
+        };
+        execute(deps.as_mut(), mock_env(), mock_info("owner", &[]), msg).unwrap();
+
+        // the operator stakes from the owner's balance, the owner is the sender
+        let res = execute(
+            deps.as_mut(),
+            mock_env(),
+            hot.clone(),
+            stake_from(2000, NATIVE_IBC_DENOM),
+        )
+        .unwrap();
+        assert_eq!(res.messages.len(), 1);
+        assert!(res.attributes.contains(&attr("sender", "owner")));
+        assert!(res.attributes.contains(&attr("operator", "hot")));
+        assert_eq!(
+            LIABILITIES
+                .load(deps.as_ref().storage, NATIVE_IBC_DENOM)
+                .unwrap(),
+            Uint128::new(3000)
+        );
+
+        let msg = QueryMsg::Operators {
+            owner: "owner".to_string(),
+        };
+        let res = query(deps.as_ref(), mock_env(), msg.clone()).unwrap();
+        let operators: OperatorsResponse = from_json(&res).unwrap();
+        assert_eq!(
+            operators.operators,
+            vec![Operator {
+                operator: Addr::unchecked("hot"),
+                expires,
+                max_amount: Some(Coin::new(3000, NATIVE_IBC_DENOM)),
+                spent: Uint128::new(2000),
+                approval_id: 1,
+            }]
+        );
+
+        // spending is limited to the approved denom and amount
+        let err = execute(
+            deps.as_mut(),
+            mock_env(),
+            hot.clone(),
+            stake_from(2000, NATIVE_IBC_DENOM),
+        )
+        .unwrap_err();
+        assert_eq!(
+            err,
+            ContractError::OperatorLimitExceeded {
+                remaining: format!("1000{NATIVE_IBC_DENOM}")
+            }
+        );
+        let err = execute(
+            deps.as_mut(),
+            mock_env(),
+            hot.clone(),
+            stake_from(1000, NATIVE_BOND_DENOM),
+        )
+        .unwrap_err();
+        assert_eq!(
+            err,
+            ContractError::OperatorLimitExceeded {
+                remaining: format!("0{NATIVE_IBC_DENOM}")
+            }
+        );
+
+        let mut env = mock_env();
+        env.block.height += 10;
+        let err = execute(
+            deps.as_mut(),
+            env,
+            hot.clone(),
+            stake_from(1000, NATIVE_IBC_DENOM),
+        )
+        .unwrap_err();
+        assert_eq!(err, ContractError::OperatorExpired {});
+
+        // an unlimited operator is still bound by the deposit balance
+        let msg = ExecuteMsg::ApproveOperator {
+            operator: "cold".to_string(),
+            expires: None,
+            max_amount: None,
+        };
+        execute(deps.as_mut(), mock_env(), mock_info("owner", &[]), msg).unwrap();
+        let err = execute(
+            deps.as_mut(),
+            mock_env(),
+            mock_info("cold", &[]),
+            stake_from(4000, NATIVE_IBC_DENOM),
+        )
+        .unwrap_err();
+        assert_eq!(
+            err,
+            ContractError::InsufficientDeposit {
+                available: Uint128::new(3000)
+            }
+        );
+
+        let msg = ExecuteMsg::RevokeOperator {
+            operator: "hot".to_string(),
+        };
+        execute(
+            deps.as_mut(),
+            mock_env(),
+            mock_info("owner", &[]),
+            msg.clone(),
+        )
+        .unwrap();
+        let err = execute(deps.as_mut(), mock_env(), mock_info("owner", &[]), msg).unwrap_err();
+        assert_eq!(
+            err,
+            ContractError::OperatorNotApproved {
+                operator: "hot".to_string()
+            }
+        );
+        let res = query(
+            deps.as_ref(),
+            mock_env(),
+            QueryMsg::Operators {
+                owner: "owner".to_string(),
+            },
+        )
+        .unwrap();
+        let operators: OperatorsResponse = from_json(&res).unwrap();
+        assert_eq!(operators.operators.len(), 1);
+        assert_eq!(operators.operators[0].operator, Addr::unchecked("cold"));
+    }
+
+    #[test]
+    fn test_schedules() {
+        let (mut deps, _env, _info) = default_instantiate();
+
+        let owner = mock_info("owner", &[]);
+        let create = |amount: u128, interval: u64| ExecuteMsg::CreateSchedule {
+            denom: NATIVE_IBC_DENOM.to_string(),
+            amount_per_run: Uint128::new(amount),
+            interval,
+            receiver: Addr::unchecked("persistencereceiver"),
+        };
+        let err = execute(deps.as_mut(), mock_env(), owner.clone(), create(0, 60)).unwrap_err();
+        assert_eq!(err, ContractError::InvalidAmount {});
+        for interval in [0, 31_536_001] {
+            let msg = create(100, interval);
+            let err = execute(deps.as_mut(), mock_env(), owner.clone(), msg).unwrap_err();
+            assert_eq!(err, ContractError::InvalidInterval { max: 31_536_000 });
+        }
+        // a run must be covered by the deposit balance when the schedule is created
+        let err = execute(deps.as_mut(), mock_env(), owner.clone(), create(100, 60)).unwrap_err();
+        assert_eq!(
+            err,
+            ContractError::InsufficientDeposit {
+                available: Uint128::zero()
+            }
+        );
+        let info = mock_info("owner", &coins(100, NATIVE_IBC_DENOM));
+        execute(deps.as_mut(), mock_env(), info, ExecuteMsg::Deposit {}).unwrap();
+        execute(deps.as_mut(), mock_env(), owner.clone(), create(100, 60)).unwrap();
+
+        // the first run is due right away and dispatched to the contract itself
+        let run_due = ExecuteMsg::ExecuteDueSchedules { limit: None };
+        let anyone = mock_info("keeper", &[]);
+        let res = execute(deps.as_mut(), mock_env(), anyone.clone(), run_due.clone()).unwrap();
+        assert_eq!(
+            res.messages,
+            vec![SubMsg::reply_on_error(
+                WasmMsg::Execute {
+                    contract_addr: MOCK_CONTRACT_ADDR.to_string(),
+                    msg: to_json_binary(&ExecuteMsg::RunSchedule {
+                        id: 1,
+                        keeper: Addr::unchecked("keeper"),
+                    })
+                    .unwrap(),
+                    funds: vec![],
+                },
+                SCHEDULE_REPLY_ID,
+            )]
+        );
+        let res = execute(deps.as_mut(), mock_env(), anyone.clone(), run_due.clone()).unwrap();
+        assert!(res.messages.is_empty());
+
+        // missed runs are skipped
+        let mut env = mock_env();
+        env.block.time = env.block.time.plus_seconds(210);
+        let res = execute(deps.as_mut(), env.clone(), anyone, run_due).unwrap();
+        assert_eq!(res.messages.len(), 1);
+        let msg = QueryMsg::Schedules {
+            owner: "owner".to_string(),
+        };
+        let res: SchedulesResponse =
+            from_json(query(deps.as_ref(), mock_env(), msg.clone()).unwrap()).unwrap();
+        assert_eq!(
+            res.schedules[0].next_run,
+            mock_env().block.time.plus_seconds(240)
+        );
+
+        let run = ExecuteMsg::RunSchedule {
+            id: 1,
+            keeper: Addr::unchecked("keeper"),
+        };
+        let err = execute(deps.as_mut(), env.clone(), owner.clone(), run.clone()).unwrap_err();
+        assert_eq!(err, ContractError::Unauthorized {});
+        let contract = mock_info(MOCK_CONTRACT_ADDR, &[]);
+        let withdraw = ExecuteMsg::Withdraw {
+            amount: Coin::new(60, NATIVE_IBC_DENOM),
+        };
+        execute(deps.as_mut(), env.clone(), owner.clone(), withdraw).unwrap();
+        let err = execute(deps.as_mut(), env.clone(), contract.clone(), run.clone()).unwrap_err();
+        assert_eq!(
+            err,
+            ContractError::InsufficientDeposit {
+                available: Uint128::new(40)
+            }
+        );
+
+        let info = mock_info("owner", &coins(110, NATIVE_IBC_DENOM));
+        execute(deps.as_mut(), env.clone(), info, ExecuteMsg::Deposit {}).unwrap();
+        let res = execute(deps.as_mut(), env, contract, run).unwrap();
+        assert_eq!(res.messages.len(), 1);
+        assert!(res.attributes.contains(&attr("sender", "owner")));
+        assert!(res.attributes.contains(&attr("schedule_id", "1")));
+        assert_eq!(
+            LIABILITIES
+                .load(deps.as_ref().storage, NATIVE_IBC_DENOM)
+                .unwrap(),
+            Uint128::new(50)
+        );
+
+        let cancel = ExecuteMsg::CancelSchedule { id: 1 };
+        let err = execute(
+            deps.as_mut(),
+            mock_env(),
+            mock_info("other", &[]),
+            cancel.clone(),
+        )
+        .unwrap_err();
+        assert_eq!(err, ContractError::Unauthorized {});
+        execute(deps.as_mut(), mock_env(), owner.clone(), cancel.clone()).unwrap();
+        let err = execute(deps.as_mut(), mock_env(), owner.clone(), cancel).unwrap_err();
+        assert_eq!(err, ContractError::UnknownSchedule { id: 1 });
+        let res: SchedulesResponse =
+            from_json(query(deps.as_ref(), mock_env(), msg).unwrap()).unwrap();
+        assert!(res.schedules.is_empty());
+
+        // owners have a bounded number of schedules
+        for _ in 0..10 {
+            execute(deps.as_mut(), mock_env(), owner.clone(), create(10, 60)).unwrap();
+        }
+        let err = execute(deps.as_mut(), mock_env(), owner, create(10, 60)).unwrap_err();
+        assert_eq!(err, ContractError::TooManySchedules { max: 10 });
+
+        // a schedule whose next run overflows is cancelled, the others still run
+        let schedule = Schedule {
+            id: 100,
+            owner: Addr::unchecked("legacy"),
+            denom: NATIVE_IBC_DENOM.to_string(),
+            amount_per_run: Uint128::new(10),
+            interval: u64::MAX,
+            receiver: Addr::unchecked("persistencereceiver"),
+            next_run: mock_env().block.time,
+        };
+        schedules()
+            .save(deps.as_mut().storage, 100, &schedule)
+            .unwrap();
+        let run_due = ExecuteMsg::ExecuteDueSchedules { limit: Some(30) };
+        let res = execute(deps.as_mut(), mock_env(), mock_info("keeper", &[]), run_due).unwrap();
+        assert_eq!(res.messages.len(), 10);
+        assert!(res.attributes.contains(&attr("cancelled_schedule", "100")));
+        assert!(!schedules().has(deps.as_ref().storage, 100));
+    }
+
+    #[test]
+    fn test_governance() {
+        let (mut deps, _env, info) = default_instantiate();
+        deps.querier.set_contract("dao");
+
+        let set_governance =
+            |contract: Option<&str>, proposal_required: bool| ExecuteMsg::SetGovernance {
+                contract: contract.map(str::to_string),
+                proposal_required,
+            };
+        let sweep = ExecuteMsg::Sweep {
+            denoms: vec![LIQUIDSTAKE_DENOM.to_string()],
+            to: "treasury".to_string(),
+        };
+
+        let err = execute(
+            deps.as_mut(),
+            mock_env(),
+            mock_info("anyone", &[]),
+            set_governance(Some("dao"), false),
+        )
+        .unwrap_err();
+        assert_eq!(err, ContractError::Unauthorized {});
+        let err = execute(
+            deps.as_mut(),
+            mock_env(),
+            info.clone(),
+            set_governance(Some("wallet"), false),
+        )
+        .unwrap_err();
+        assert_eq!(
+            err,
+            ContractError::NotAContract {
+                address: "wallet".to_string()
+            }
+        );
+        let err = execute(
+            deps.as_mut(),
+            mock_env(),
+            info.clone(),
+            set_governance(None, true),
+        )
+        .unwrap_err();
+        assert_eq!(err, ContractError::GovernanceNotSet {});
+
+        // the admin key and the governance contract share the powers
+        let res = execute(
+            deps.as_mut(),
+            mock_env(),
+            info.clone(),
+            set_governance(Some("dao"), false),
+        )
+        .unwrap();
+        assert_eq!(
+            res.attributes,
+            vec![
+                attr("method", "set_governance"),
+                attr("contract", "dao"),
+                attr("proposal_required", "false"),
+            ]
+        );
+        let dao = mock_info("dao", &[]);
+        execute(deps.as_mut(), mock_env(), dao.clone(), sweep.clone()).unwrap();
+
+        // in proposal-required mode only the governance contract is privileged
+        execute(
+            deps.as_mut(),
+            mock_env(),
+            info.clone(),
+            set_governance(Some("dao"), true),
+        )
+        .unwrap();
+        let err = execute(deps.as_mut(), mock_env(), info.clone(), sweep.clone()).unwrap_err();
+        assert_eq!(err, ContractError::Unauthorized {});
+        execute(deps.as_mut(), mock_env(), dao.clone(), sweep).unwrap();
+
+        let res = query(deps.as_ref(), mock_env(), QueryMsg::GovernanceInfo {}).unwrap();
+        let value: GovernanceInfoResponse = from_json(&res).unwrap();
+        assert_eq!(
+            value,
+            GovernanceInfoResponse {
+                admin: Addr::unchecked("creator"),
+                contract: Some(Addr::unchecked("dao")),
+                proposal_required: true,
+            }
+        );
+
+        // governance can hand the powers back to the admin key
+        execute(deps.as_mut(), mock_env(), dao, set_governance(None, false)).unwrap();
+        let value = query_governance_info(deps.as_ref()).unwrap();
+        assert_eq!(value.contract, None);
+        assert!(!value.proposal_required);
+    }
+
+    #[test]
+    fn test_config_timelock() {
+        let mut deps = mock_dependencies();
+        let info = mock_info("creator", &[]);
+        let msg = InstantiateMsgBuilder::default()
+            .config_timelock(3600)
+            .build();
+        instantiate(deps.as_mut(), mock_env(), info.clone(), msg).unwrap();
+
+        let update =
+            |active: Option<bool>, protocol_fee: Option<Decimal>| ExecuteMsg::UpdateConfig {
+                active,
+                ls_prefix: None,
+                protocol_fee,
+                fee_recipient: None,
+                history_limit: None,
+                swap_router: None,
+                verify_ls_prefix: None,
+                config_timelock: None,
+            };
+
+        // sensitive fields are queued, pausing applies instantly
+        let res = execute(
+            deps.as_mut(),
+            mock_env(),
+            info.clone(),
+            update(Some(false), Some(Decimal::percent(5))),
+        )
+        .unwrap();
+        let executable_at = mock_env().block.time.plus_seconds(3600);
+        assert_eq!(
+            res.attributes,
+            vec![
+                attr("method", "update_config"),
+                attr("active", "false"),
+                attr("pending_change_id", "1"),
+                attr("executable_at", executable_at.to_string()),
+            ]
+        );
+        let res = query(deps.as_ref(), mock_env(), QueryMsg::LsConfig {}).unwrap();
+        let value: LsConfig = from_json(&res).unwrap();
+        assert_eq!(
+            value,
+            LsConfigBuilder::new("creator")
+                .active(false)
+                .config_timelock(3600)
+                .build()
+        );
+
+        let res = query(deps.as_ref(), mock_env(), QueryMsg::PendingChanges {}).unwrap();
+        let value: PendingChangesResponse = from_json(&res).unwrap();
+        assert_eq!(
+            value.changes,
+            vec![PendingChange {
+                id: 1,
+                change: ConfigChange {
+                    protocol_fee: Some(Decimal::percent(5)),
+                    ..ConfigChange::default()
+                },
+                executable_at,
+            }]
+        );
+
+        // invalid values are rejected when queued
+        let err = execute(
+            deps.as_mut(),
+            mock_env(),
+            info.clone(),
+            update(None, Some(Decimal::one())),
+        )
+        .unwrap_err();
+        assert_eq!(err, ContractError::InvalidProtocolFee {});
+        let msg = ExecuteMsg::UpdateConfig {
+            active: None,
+            ls_prefix: None,
+            protocol_fee: None,
+            fee_recipient: None,
+            history_limit: None,
+            swap_router: None,
+            verify_ls_prefix: None,
+            config_timelock: Some(u64::MAX),
+        };
+        let err = execute(deps.as_mut(), mock_env(), info.clone(), msg).unwrap_err();
+        assert_eq!(err, ContractError::InvalidConfigTimelock { max: 2_592_000 });
+
+        let msg = ExecuteMsg::ExecutePendingChange { id: 1 };
+        let err = execute(
+            deps.as_mut(),
+            mock_env(),
+            mock_info("anyone", &[]),
+            msg.clone(),
+        )
+        .unwrap_err();
+        assert_eq!(err, ContractError::Unauthorized {});
+        let err = execute(deps.as_mut(), mock_env(), info.clone(), msg.clone()).unwrap_err();
+        assert_eq!(err, ContractError::TimelockNotExpired { executable_at });
+
+        let mut env = mock_env();
+        env.block.time = executable_at;
+        let res = execute(deps.as_mut(), env.clone(), info.clone(), msg.clone()).unwrap();
+        assert_eq!(
+            res.attributes,
+            vec![
+                attr("method", "execute_pending_change"),
+                attr("id", "1"),
+                attr("protocol_fee", "0.05"),
+            ]
+        );
+        let value = query_ls_config(deps.as_ref()).unwrap();
+        assert_eq!(value.protocol_fee, Decimal::percent(5));
+
+        // executed changes are removed
+        let err = execute(deps.as_mut(), env.clone(), info.clone(), msg).unwrap_err();
+        assert_eq!(err, ContractError::UnknownPendingChange { id: 1 });
+
+        // cancelled changes can not be executed
+        execute(
+            deps.as_mut(),
+            env.clone(),
+            info.clone(),
+            update(None, Some(Decimal::percent(10))),
+        )
+        .unwrap();
+        let msg = ExecuteMsg::CancelPendingChange { id: 2 };
+        execute(deps.as_mut(), env.clone(), info.clone(), msg.clone()).unwrap();
+        let err = execute(deps.as_mut(), env.clone(), info.clone(), msg).unwrap_err();
+        assert_eq!(err, ContractError::UnknownPendingChange { id: 2 });
+        let res = query(deps.as_ref(), env, QueryMsg::PendingChanges {}).unwrap();
+        let value: PendingChangesResponse = from_json(&res).unwrap();
+        assert!(value.changes.is_empty());
+    }
+
+    #[test]
+    fn test_timelocked_fee_shares() {
+        let mut deps = mock_dependencies();
+        let info = mock_info("creator", &[]);
+        let msg = InstantiateMsgBuilder::default()
+            .config_timelock(3600)
+            .build();
+        instantiate(deps.as_mut(), mock_env(), info.clone(), msg).unwrap();
+        let executable_at = mock_env().block.time.plus_seconds(3600);
+
+        // the tip rate limit applies instantly, the fee share is queued
+        let msg = ExecuteMsg::SetKeeperTip {
+            fee_share: Decimal::percent(10),
+            tips_per_block: 3,
+        };
+        let res = execute(deps.as_mut(), mock_env(), info.clone(), msg).unwrap();
+        assert_eq!(
+            res.attributes,
+            vec![
+                attr("method", "set_keeper_tip"),
+                attr("pending_change_id", "1"),
+                attr("executable_at", executable_at.to_string()),
+                attr("tips_per_block", "3"),
+            ]
+        );
+        let value = query_ls_config(deps.as_ref()).unwrap();
+        assert_eq!(value.keeper_fee_share, Decimal::zero());
+        assert_eq!(value.keeper_tips_per_block, 3);
+
+        // a referrer without a fee share is registered instantly
+        let register = |code: &str, fee_share: u64| ExecuteMsg::RegisterReferrer {
+            code: code.to_string(),
+            address: "partner".to_string(),
+            fee_share: Some(Decimal::percent(fee_share)),
+        };
+        execute(deps.as_mut(), mock_env(), info.clone(), register("free", 0)).unwrap();
+        assert!(REFERRERS.has(deps.as_ref().storage, "free"));
+        let res = execute(
+            deps.as_mut(),
+            mock_env(),
+            info.clone(),
+            register("wallet", 50),
+        )
+        .unwrap();
+        assert!(res.attributes.contains(&attr("pending_change_id", "2")));
+        let referrer = REFERRERS.load(deps.as_ref().storage, "wallet").unwrap();
+        assert_eq!(referrer.fee_share, Decimal::zero());
+
+        // the address applies instantly and survives the pending fee share
+        let msg = ExecuteMsg::RegisterReferrer {
+            code: "wallet".to_string(),
+            address: "partner2".to_string(),
+            fee_share: None,
+        };
+        let res = execute(deps.as_mut(), mock_env(), info.clone(), msg).unwrap();
+        assert!(!res
+            .attributes
+            .iter()
+            .any(|attr| attr.key == "pending_change_id"));
+
+        // only a changed provider or LS token override is queued
+        let config = |provider: LsProvider, ls_token_denom: Option<&str>, min_amount: u128| {
+            ExecuteMsg::SetDenomConfig {
+                denom: NATIVE_IBC_DENOM.to_string(),
+                config: Some(DenomConfig {
+                    provider,
+                    ls_token_denom: ls_token_denom.map(|denom| denom.to_string()),
+                    min_amount: Some(Uint128::new(min_amount)),
+                    ..DenomConfig::default_for(NATIVE_IBC_DENOM)
+                }),
+            }
+        };
+        let res = execute(
+            deps.as_mut(),
+            mock_env(),
+            info.clone(),
+            config(LsProvider::LiquidStakeIbc {}, None, 10),
+        )
+        .unwrap();
+        assert!(!res
+            .attributes
+            .iter()
+            .any(|attr| attr.key == "pending_change_id"));
+        let res = execute(
+            deps.as_mut(),
+            mock_env(),
+            info.clone(),
+            config(LsProvider::LiquidStakeIbc {}, Some("stk/custom"), 20),
+        )
+        .unwrap();
+        assert!(res.attributes.contains(&attr("pending_change_id", "3")));
+        let hub = LsProvider::WasmHub {
+            contract: Addr::unchecked("hub"),
+            execute_msg: Binary::default(),
+            ls_token_denom: "stk/hub".to_string(),
+        };
+        let res = execute(
+            deps.as_mut(),
+            mock_env(),
+            info.clone(),
+            config(hub.clone(), Some("stk/custom"), 30),
+        )
+        .unwrap();
+        assert!(res.attributes.contains(&attr("pending_change_id", "4")));
+        let value = query_denom_config(deps.as_ref(), NATIVE_IBC_DENOM.to_string()).unwrap();
+        assert_eq!(value.provider, LsProvider::LiquidStakeIbc {});
+        assert_eq!(value.ls_token_denom, None);
+        assert_eq!(value.min_amount, Some(Uint128::new(30)));
+
+        let mut env = mock_env();
+        env.block.time = executable_at;
+        for id in 1..=3 {
+            let msg = ExecuteMsg::ExecutePendingChange { id };
+            execute(deps.as_mut(), env.clone(), info.clone(), msg).unwrap();
+        }
+        let value = query_ls_config(deps.as_ref()).unwrap();
+        assert_eq!(value.keeper_fee_share, Decimal::percent(10));
+        let referrer = REFERRERS.load(deps.as_ref().storage, "wallet").unwrap();
+        assert_eq!(referrer.address, Addr::unchecked("partner2"));
+        assert_eq!(referrer.fee_share, Decimal::percent(50));
+        // the pending changes only touch the provider and the override
+        let value = query_denom_config(deps.as_ref(), NATIVE_IBC_DENOM.to_string()).unwrap();
+        assert_eq!(value.provider, LsProvider::LiquidStakeIbc {});
+        assert_eq!(value.ls_token_denom, Some("stk/custom".to_string()));
+        assert_eq!(value.min_amount, Some(Uint128::new(30)));
+
+        let msg = ExecuteMsg::ExecutePendingChange { id: 4 };
+        execute(deps.as_mut(), env, info, msg).unwrap();
+        let value = query_denom_config(deps.as_ref(), NATIVE_IBC_DENOM.to_string()).unwrap();
+        assert_eq!(value.provider, hub);
+        assert_eq!(value.min_amount, Some(Uint128::new(30)));
+    }
+
+    #[test]
+    fn test_ls_prefix_validation() {
+        for ls_prefix in ["", " stk/", "stk /", "1stk/"] {
+            let msg = InstantiateMsgBuilder::default()
+                .ls_prefix(ls_prefix)
+                .build();
+            let err = instantiate(
+                mock_dependencies().as_mut(),
+                mock_env(),
+                mock_info("creator", &[]),
+                msg,
+            )
+            .unwrap_err();
+            assert_eq!(
+                err,
+                ContractError::InvalidLsPrefix {
+                    prefix: ls_prefix.to_string()
+                }
+            );
+        }
+
+        let (mut deps, _env, info) = default_instantiate();
+        let update = |ls_prefix: &str, verify: bool| ExecuteMsg::UpdateConfig {
+            active: None,
+            ls_prefix: Some(ls_prefix.to_string()),
+            protocol_fee: None,
+            fee_recipient: None,
+            history_limit: None,
+            swap_router: None,
+            verify_ls_prefix: Some(verify),
+            config_timelock: None,
+        };
+        let err = execute(
+            deps.as_mut(),
+            mock_env(),
+            info.clone(),
+            update("stk /", false),
+        )
+        .unwrap_err();
+        assert!(matches!(err, ContractError::InvalidLsPrefix { .. }));
+
+        // the dry run needs the LS token of the bonded denom to exist
+        let err = execute(
+            deps.as_mut(),
+            mock_env(),
+            info.clone(),
+            update("stk/", true),
+        )
+        .unwrap_err();
+        assert_eq!(
+            err,
+            ContractError::UnknownLsTokenDenom {
+                denom: "stk/uxprt".to_string()
+            }
+        );
+        deps.querier.set_balance("holder", coins(1, "stk/uxprt"));
+        execute(
+            deps.as_mut(),
+            mock_env(),
+            info.clone(),
+            update("stk/", true),
+        )
+        .unwrap();
+
+        // configured IBC denoms are checked through their denom trace
+        let msg = ExecuteMsg::SetDenomConfig {
+            denom: NATIVE_IBC_DENOM.to_string(),
+            config: Some(DenomConfig::default_for(NATIVE_IBC_DENOM)),
+        };
+        execute(deps.as_mut(), mock_env(), info.clone(), msg).unwrap();
+        let err =
+            execute(deps.as_mut(), mock_env(), info.clone(), update("ls/", true)).unwrap_err();
+        assert_eq!(
+            err,
+            ContractError::UnknownLsTokenDenom {
+                denom: "ls/uatom".to_string()
+            }
+        );
+
+        // without the dry run the prefix is accepted as is
+        execute(deps.as_mut(), mock_env(), info, update("ls/", false)).unwrap();
+        let res = query(deps.as_ref(), mock_env(), QueryMsg::LsConfig {}).unwrap();
+        let value: LsConfig = from_json(&res).unwrap();
+        assert_eq!(value.ls_prefix, "ls/");
+    }
+
+    #[test]
+    fn test_host_chain_checks() {
+        let (mut deps, _env, _info) = default_instantiate();
+
+        let stake = |deps: &mut OwnedDeps<_, _, _>, denom: &str| {
+            let msg = ExecuteMsg::LiquidStake {
+                receiver: Addr::unchecked("persistencereceiver"),
+                referral: None,
+                post_action: None,
+            };
+            let info = mock_info("anyone", &coins(1000, denom));
+            execute(deps.as_mut(), mock_env(), info, msg)
+        };
+
+        // atom over another channel is not the host chain's
+        deps.querier
+            .mock_denom_trace("ibc/OTHER", "transfer/channel-5", NATIVE_BASE_DENOM);
+        let err = stake(&mut deps, "ibc/OTHER").unwrap_err();
+        assert_eq!(
+            err,
+            ContractError::HostChainNotFound {
+                denom: "ibc/OTHER".to_string()
+            }
+        );
+
+        let chain_id = HOST_CHAIN_ID.to_string();
+        let mut host_chain = mock_host_chain(HOST_CHAIN_ID, NATIVE_BASE_DENOM, "channel-0");
+        host_chain.active = false;
+        deps.querier.set_host_chain(host_chain);
+        let err = stake(&mut deps, NATIVE_IBC_DENOM).unwrap_err();
+        assert_eq!(
+            err,
+            ContractError::HostChainInactive {
+                chain_id: chain_id.clone()
+            }
+        );
+
+        let mut host_chain = mock_host_chain(HOST_CHAIN_ID, NATIVE_BASE_DENOM, "channel-0");
+        host_chain.delegation_account = Some(IcaAccount {
+            channel_state: ChannelState::IcaChannelCreating as i32,
+            ..Default::default()
+        });
+        deps.querier.set_host_chain(host_chain);
+        let err = stake(&mut deps, NATIVE_IBC_DENOM).unwrap_err();
+        assert_eq!(
+            err,
+            ContractError::IcaChannelClosed {
+                chain_id: chain_id.clone()
+            }
+        );
+
+        deps.querier.set_host_chain(mock_host_chain(
+            HOST_CHAIN_ID,
+            NATIVE_BASE_DENOM,
+            "channel-0",
+        ));
+        deps.querier
+            .set_c_value(HOST_CHAIN_ID, Decimal::percent(80));
+        let err = stake(&mut deps, NATIVE_IBC_DENOM).unwrap_err();
+        assert_eq!(err, ContractError::CValueOutOfLimits { chain_id });
+
+        deps.querier
+            .set_c_value(HOST_CHAIN_ID, Decimal::percent(90));
+        stake(&mut deps, NATIVE_IBC_DENOM).unwrap();
+
+        // native XPRT goes to liquidstake, which has no host chain
+        stake(&mut deps, NATIVE_BOND_DENOM).unwrap();
+    }
+
+    #[test]
+    fn test_stake_limits() {
+        let (mut deps, _env, info) = default_instantiate();
+        let mut host_chain = mock_host_chain(HOST_CHAIN_ID, NATIVE_BASE_DENOM, "channel-0");
+        host_chain.minimum_deposit = "500".to_string();
+        deps.querier.set_host_chain(host_chain);
+
+        let set_limits = |min_amount: u128, max_amount: u128, enforce_module_minimum: bool| {
+            ExecuteMsg::SetDenomConfig {
+                denom: NATIVE_IBC_DENOM.to_string(),
+                config: Some(DenomConfig {
+                    min_amount: Some(Uint128::new(min_amount)),
+                    max_amount: Some(Uint128::new(max_amount)),
+                    enforce_module_minimum,
+                    ..DenomConfig::default_for(NATIVE_IBC_DENOM)
+                }),
+            }
+        };
+        let stake = |deps: &mut OwnedDeps<_, _, _>, amount: u128| {
+            let msg = ExecuteMsg::LiquidStake {
+                receiver: Addr::unchecked("persistencereceiver"),
+                referral: None,
+                post_action: None,
+            };
+            let info = mock_info("anyone", &coins(amount, NATIVE_IBC_DENOM));
+            execute(deps.as_mut(), mock_env(), info, msg)
+        };
+
+        let err = execute(
+            deps.as_mut(),
+            mock_env(),
+            info.clone(),
+            set_limits(200, 100, false),
+        )
+        .unwrap_err();
+        assert_eq!(err, ContractError::InvalidStakeLimits {});
+
+        execute(
+            deps.as_mut(),
+            mock_env(),
+            info.clone(),
+            set_limits(100, 10_000, false),
+        )
+        .unwrap();
+        let err = stake(&mut deps, 99).unwrap_err();
+        assert_eq!(
+            err,
+            ContractError::BelowMinimum {
+                min: Uint128::new(100)
+            }
+        );
+        let err = stake(&mut deps, 10_001).unwrap_err();
+        assert_eq!(
+            err,
+            ContractError::AboveMaximum {
+                max: Uint128::new(10_000)
+            }
+        );
+        stake(&mut deps, 100).unwrap();
+        stake(&mut deps, 10_000).unwrap();
+
+        // the host chain minimum deposit raises the configured minimum
+        execute(
+            deps.as_mut(),
+            mock_env(),
+            info,
+            set_limits(100, 10_000, true),
+        )
+        .unwrap();
+        let err = stake(&mut deps, 499).unwrap_err();
+        assert_eq!(
+            err,
+            ContractError::BelowMinimum {
+                min: Uint128::new(500)
+            }
+        );
+        stake(&mut deps, 500).unwrap();
+    }
+
+    #[test]
+    fn test_ls_token_denom_override() {
+        let (mut deps, _env, info) = default_instantiate();
+
+        let set_override = |ls_token_denom: &str| ExecuteMsg::SetDenomConfig {
+            denom: NATIVE_IBC_DENOM.to_string(),
+            config: Some(DenomConfig {
+                provider: LsProvider::LiquidStakeIbc {},
+                ls_token_denom: Some(ls_token_denom.to_string()),
+                min_amount: None,
+                max_amount: None,
+                enforce_module_minimum: false,
+                batch: None,
+            }),
+        };
+        for invalid in ["x", " stkatom", "stk atom"] {
+            let err = execute(
+                deps.as_mut(),
+                mock_env(),
+                info.clone(),
+                set_override(invalid),
+            )
+            .unwrap_err();
+            assert!(
+                matches!(err, ContractError::InvalidDenom { .. }),
+                "{invalid}"
+            );
+        }
+        execute(deps.as_mut(), mock_env(), info, set_override("stkatom")).unwrap();
+
+        let msg = ExecuteMsg::LiquidStake {
+            receiver: Addr::unchecked("persistencereceiver"),
+            referral: None,
+            post_action: None,
+        };
+        let info = mock_info("anyone", &coins(1000, NATIVE_IBC_DENOM));
+        execute(deps.as_mut(), mock_env(), info, msg).unwrap();
+        assert_eq!(
+            CURRENT_TX.load(&deps.storage).unwrap().ls_token_denom,
+            "stkatom"
+        );
     }
 
     #[test]
+    #[allow(clippy::bool_assert_comparison)]
     fn test_deactivate() {
         let (mut deps, _env, info) = default_instantiate();
 
         let msg = ExecuteMsg::UpdateConfig {
             active: Some(false),
             ls_prefix: None,
+            protocol_fee: None,
+            fee_recipient: None,
+            history_limit: None,
+            swap_router: None,
+            verify_ls_prefix: None,
+            config_timelock: None,
         };
         let res = execute(deps.as_mut(), mock_env(), info, msg).unwrap();
         assert_eq!(
@@ -368,6 +2956,8 @@ mod tests {
         let info = mock_info("anyone", &coins(deposit_amount.into(), NATIVE_IBC_DENOM));
         let msg = ExecuteMsg::LiquidStake {
             receiver: Addr::unchecked("persistencereceiver"),
+            referral: None,
+            post_action: None,
         };
 
         let res = execute(deps.as_mut(), mock_env(), info, msg);
@@ -0,0 +1,42 @@
+//! Subset of the cw721 messages used to mint and verify deposit receipts
+use cosmwasm_schema::cw_serde;
+use cw_utils::Expiration;
+
+#[cw_serde]
+pub enum Cw721ExecuteMsg<T> {
+    Mint {
+        token_id: String,
+        owner: String,
+        token_uri: Option<String>,
+        extension: T,
+    },
+}
+
+#[cw_serde]
+pub enum Cw721QueryMsg {
+    OwnerOf {
+        token_id: String,
+        include_expired: Option<bool>,
+    },
+    NftInfo {
+        token_id: String,
+    },
+}
+
+#[cw_serde]
+pub struct Approval {
+    pub spender: String,
+    pub expires: Expiration,
+}
+
+#[cw_serde]
+pub struct OwnerOfResponse {
+    pub owner: String,
+    pub approvals: Vec<Approval>,
+}
+
+#[cw_serde]
+pub struct NftInfoResponse<T> {
+    pub token_uri: Option<String>,
+    pub extension: T,
+}
@@ -0,0 +1,42 @@
+//! Subset of the Dexter vault and router messages used by the contract
+use cosmwasm_schema::cw_serde;
+use cosmwasm_std::Uint128;
+
+#[cw_serde]
+pub enum AssetInfo {
+    NativeToken { denom: String },
+}
+
+#[cw_serde]
+pub struct Asset {
+    pub info: AssetInfo,
+    pub amount: Uint128,
+}
+
+#[cw_serde]
+pub enum VaultExecuteMsg {
+    JoinPool {
+        pool_id: Uint128,
+        recipient: Option<String>,
+        assets: Option<Vec<Asset>>,
+        min_lp_to_receive: Option<Uint128>,
+        auto_stake: Option<bool>,
+    },
+}
+
+#[cw_serde]
+pub struct HopSwapRequest {
+    pub pool_id: Uint128,
+    pub asset_in: AssetInfo,
+    pub asset_out: AssetInfo,
+}
+
+#[cw_serde]
+pub enum RouterExecuteMsg {
+    ExecuteMultihopSwap {
+        requests: Vec<HopSwapRequest>,
+        offer_amount: Uint128,
+        recipient: Option<String>,
+        minimum_receive: Option<Uint128>,
+    },
+}
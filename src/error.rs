@@ -1,4 +1,4 @@
-use cosmwasm_std::{OverflowError, StdError};
+use cosmwasm_std::{OverflowError, StdError, Timestamp, Uint128};
 use cw_utils::PaymentError;
 use thiserror::Error;
 
@@ -46,10 +46,240 @@ pub enum ContractError {
     #[error("Subcall error: {0}")]
     SubcallError(String),
 
+    #[error("Stake is below the module minimum deposit (codespace {codespace}, code {code})")]
+    ModuleMinimumDeposit { codespace: String, code: u32 },
+
+    #[error("Denom rejected by the module (codespace {codespace}, code {code})")]
+    ModuleInvalidDenom { codespace: String, code: u32 },
+
+    #[error("Host chain is disabled in the module (codespace {codespace}, code {code})")]
+    HostChainDisabled { codespace: String, code: u32 },
+
+    #[error("Module is paused (codespace {codespace}, code {code})")]
+    ModulePaused { codespace: String, code: u32 },
+
+    #[error("Module error (codespace {codespace}, code {code}): {log}")]
+    ModuleError {
+        codespace: String,
+        code: u32,
+        log: String,
+    },
+
+    #[error("Protocol fee must be lower than 100%")]
+    InvalidProtocolFee {},
+
+    #[error("Invalid referral code: {code}")]
+    InvalidReferralCode { code: String },
+
+    #[error("Unknown referral code: {code}")]
+    UnknownReferral { code: String },
+
+    #[error("Fee share must not exceed 100%")]
+    InvalidFeeShare {},
+
+    #[error("Unknown post action: {name}")]
+    UnknownPostAction { name: String },
+
+    #[error("Swap router is not configured")]
+    SwapRouterNotSet {},
+
+    #[error("Invalid swap route")]
+    InvalidSwapRoute {},
+
+    #[error("No pstake host chain for {denom}")]
+    HostChainNotFound { denom: String },
+
+    #[error("Host chain {chain_id} is inactive")]
+    HostChainInactive { chain_id: String },
+
+    #[error("Delegation ICA channel of {chain_id} is closed")]
+    IcaChannelClosed { chain_id: String },
+
+    #[error("c-value of {chain_id} is outside the module limits, deposits are paused")]
+    CValueOutOfLimits { chain_id: String },
+
+    #[error("Stake is below the minimum of {min}")]
+    BelowMinimum { min: Uint128 },
+
+    #[error("Stake is above the maximum of {max}")]
+    AboveMaximum { max: Uint128 },
+
+    #[error("Minimum stake amount must not exceed the maximum")]
+    InvalidStakeLimits {},
+
+    #[error("Invalid LS token prefix: {prefix}")]
+    InvalidLsPrefix { prefix: String },
+
+    #[error("LS token {denom} has no supply")]
+    UnknownLsTokenDenom { denom: String },
+
+    #[error("Config timelock must not exceed {max} seconds")]
+    InvalidConfigTimelock { max: u64 },
+
+    #[error("Unknown pending change: {id}")]
+    UnknownPendingChange { id: u64 },
+
+    #[error("Batch allocation does not match the minted amount")]
+    InvalidAllocation {},
+
+    #[error("Unknown schedule: {id}")]
+    UnknownSchedule { id: u64 },
+
+    #[error("Schedule interval must be between 1 and {max} seconds")]
+    InvalidInterval { max: u64 },
+
+    #[error("An owner can have at most {max} schedules")]
+    TooManySchedules { max: u32 },
+
+    #[error("Insufficient deposit balance: {available}")]
+    InsufficientDeposit { available: Uint128 },
+
+    #[error("{operator} is not an approved operator")]
+    OperatorNotApproved { operator: String },
+
+    #[error("Operator approval expired")]
+    OperatorExpired {},
+
+    #[error("Operator spending limit exceeded, remaining: {remaining}")]
+    OperatorLimitExceeded { remaining: String },
+
+    #[error("No deposits queued for {denom}")]
+    EmptyBatch { denom: String },
+
+    #[error("No deposits of the sender queued for {denom}")]
+    NoQueuedDeposits { denom: String },
+
+    #[error("Batch size must be between 1 and {max} deposits")]
+    InvalidBatchSize { max: u32 },
+
+    #[error("Batch age must be between 1 and {max} seconds")]
+    InvalidBatchAge { max: u64 },
+
+    #[error("Batch of {denom} is full")]
+    BatchFull { denom: String },
+
+    #[error("Unknown failed stake: {id}")]
+    UnknownFailedStake { id: u64 },
+
+    #[error("Pending change is executable at {executable_at}")]
+    TimelockNotExpired { executable_at: Timestamp },
+
+    #[error("{address} is not a contract")]
+    NotAContract { address: String },
+
+    #[error("Proposal-required mode needs a governance contract")]
+    GovernanceNotSet {},
+
+    #[error("Nothing to sweep")]
+    NothingToSweep {},
+
+    #[error("No keeper rewards to claim")]
+    NoKeeperRewards {},
+
     #[error("No claimable tokens")]
     NoClaimableTokens {},
 }
 
+/// What to do with the deposit of a liquid stake the module rejected
+#[derive(Debug, PartialEq)]
+pub enum SubcallAction {
+    /// The deposit can never be staked as is, return it
+    Refund,
+    /// The module may accept the deposit later
+    Retry,
+    /// Unknown failure, revert the transaction
+    Fail,
+}
+
+/// Cosmos SDK root codespace, see `types/errors` of the SDK
+const SDK_CODESPACE: &str = "sdk";
+const SDK_CODE_INVALID_COINS: u32 = 10;
+
+/// pstake `liquidstakeibc` codespace, see `x/liquidstakeibc/types/errors.go` of pstake-native
+const LIQUIDSTAKEIBC_CODESPACE: &str = "liquidstakeibc";
+const LIQUIDSTAKEIBC_CODE_INVALID_DENOM: u32 = 2004;
+const LIQUIDSTAKEIBC_CODE_INVALID_HOST_CHAIN: u32 = 2005;
+const LIQUIDSTAKEIBC_CODE_MIN_DEPOSIT: u32 = 2006;
+const LIQUIDSTAKEIBC_CODE_HOST_CHAIN_INACTIVE: u32 = 2016;
+
+/// pstake `liquidstake` codespace, see `x/liquidstake/types/errors.go` of pstake-native
+const LIQUIDSTAKE_CODESPACE: &str = "liquidstake";
+const LIQUIDSTAKE_CODE_INVALID_DENOM: u32 = 3;
+const LIQUIDSTAKE_CODE_INVALID_BOND_DENOM: u32 = 4;
+const LIQUIDSTAKE_CODE_LESS_THAN_MIN_AMOUNT: u32 = 7;
+const LIQUIDSTAKE_CODE_TOO_SMALL_AMOUNT: u32 = 12;
+
+impl ContractError {
+    /// Decodes the error of a failed provider subcall. wasmd redacts module errors to
+    /// `codespace: {codespace}, code: {code}`, which are mapped by their registered code.
+    /// Unredacted logs, as returned by test chains, are matched on the module's message.
+    pub fn from_subcall_error(err: String) -> Self {
+        if let Some((codespace, code)) = parse_redacted_error(&err) {
+            return match (codespace.as_str(), code) {
+                (LIQUIDSTAKEIBC_CODESPACE, LIQUIDSTAKEIBC_CODE_MIN_DEPOSIT)
+                | (
+                    LIQUIDSTAKE_CODESPACE,
+                    LIQUIDSTAKE_CODE_LESS_THAN_MIN_AMOUNT | LIQUIDSTAKE_CODE_TOO_SMALL_AMOUNT,
+                ) => ContractError::ModuleMinimumDeposit { codespace, code },
+                (SDK_CODESPACE, SDK_CODE_INVALID_COINS)
+                | (
+                    LIQUIDSTAKEIBC_CODESPACE,
+                    LIQUIDSTAKEIBC_CODE_INVALID_DENOM | LIQUIDSTAKEIBC_CODE_INVALID_HOST_CHAIN,
+                )
+                | (
+                    LIQUIDSTAKE_CODESPACE,
+                    LIQUIDSTAKE_CODE_INVALID_DENOM | LIQUIDSTAKE_CODE_INVALID_BOND_DENOM,
+                ) => ContractError::ModuleInvalidDenom { codespace, code },
+                (LIQUIDSTAKEIBC_CODESPACE, LIQUIDSTAKEIBC_CODE_HOST_CHAIN_INACTIVE) => {
+                    ContractError::HostChainDisabled { codespace, code }
+                }
+                _ => ContractError::ModuleError {
+                    codespace,
+                    code,
+                    log: err,
+                },
+            };
+        }
+
+        let log = err.to_lowercase();
+        let matches = |patterns: &[&str]| patterns.iter().any(|pattern| log.contains(pattern));
+        let (codespace, code) = (String::new(), 0);
+        if matches(&["less than minimum deposit", "min_liquid_staking_amount"]) {
+            ContractError::ModuleMinimumDeposit { codespace, code }
+        } else if matches(&["invalid token denom", "invalid denom"]) {
+            ContractError::ModuleInvalidDenom { codespace, code }
+        } else if matches(&["host chain is not active", "host chain inactive"]) {
+            ContractError::HostChainDisabled { codespace, code }
+        } else if matches(&["module is paused", "module paused", "epoch in progress"]) {
+            ContractError::ModulePaused { codespace, code }
+        } else {
+            ContractError::SubcallError(err)
+        }
+    }
+
+    /// How the deposit of a stake failing with this error is handled
+    pub fn subcall_action(&self) -> SubcallAction {
+        match self {
+            ContractError::ModuleMinimumDeposit { .. }
+            | ContractError::ModuleInvalidDenom { .. } => SubcallAction::Refund,
+            ContractError::HostChainDisabled { .. } | ContractError::ModulePaused { .. } => {
+                SubcallAction::Retry
+            }
+            _ => SubcallAction::Fail,
+        }
+    }
+}
+
+/// Codespace and code of an error redacted by wasmd
+fn parse_redacted_error(err: &str) -> Option<(String, u32)> {
+    let (_, rest) = err.split_once("codespace: ")?;
+    let (codespace, rest) = rest.split_once(", code: ")?;
+    let end = rest
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(rest.len());
+    Some((codespace.to_string(), rest[..end].parse().ok()?))
+}
+
 impl From<OverflowError> for ContractError {
     fn from(o: OverflowError) -> Self {
         StdError::from(o).into()
@@ -89,6 +319,94 @@ mod tests {
     }
 
     #[test]
+    fn test_subcall_error() {
+        let cases = [
+            (
+                "codespace: sdk, code: 10",
+                ContractError::ModuleInvalidDenom {
+                    codespace: "sdk".to_string(),
+                    code: 10,
+                },
+                SubcallAction::Refund,
+            ),
+            (
+                "dispatch: submessages: deposit amount less than minimum deposit",
+                ContractError::ModuleMinimumDeposit {
+                    codespace: String::new(),
+                    code: 0,
+                },
+                SubcallAction::Refund,
+            ),
+            (
+                "host chain is not active",
+                ContractError::HostChainDisabled {
+                    codespace: String::new(),
+                    code: 0,
+                },
+                SubcallAction::Retry,
+            ),
+            (
+                "unbonding epoch in progress",
+                ContractError::ModulePaused {
+                    codespace: String::new(),
+                    code: 0,
+                },
+                SubcallAction::Retry,
+            ),
+            (
+                "dispatch: submessages: codespace: liquidstakeibc, code: 2006",
+                ContractError::ModuleMinimumDeposit {
+                    codespace: "liquidstakeibc".to_string(),
+                    code: 2006,
+                },
+                SubcallAction::Refund,
+            ),
+            (
+                "codespace: liquidstakeibc, code: 2016",
+                ContractError::HostChainDisabled {
+                    codespace: "liquidstakeibc".to_string(),
+                    code: 2016,
+                },
+                SubcallAction::Retry,
+            ),
+            (
+                "codespace: liquidstake, code: 7",
+                ContractError::ModuleMinimumDeposit {
+                    codespace: "liquidstake".to_string(),
+                    code: 7,
+                },
+                SubcallAction::Refund,
+            ),
+            (
+                "codespace: liquidstakeibc, code: 2007",
+                ContractError::ModuleError {
+                    codespace: "liquidstakeibc".to_string(),
+                    code: 2007,
+                    log: "codespace: liquidstakeibc, code: 2007".to_string(),
+                },
+                SubcallAction::Fail,
+            ),
+            // only the module's own messages are matched, not any log mentioning the words
+            (
+                "validator is inactive for this epoch",
+                ContractError::SubcallError("validator is inactive for this epoch".to_string()),
+                SubcallAction::Fail,
+            ),
+            (
+                "out of gas",
+                ContractError::SubcallError("out of gas".to_string()),
+                SubcallAction::Fail,
+            ),
+        ];
+        for (err, expected, action) in cases {
+            let decoded = ContractError::from_subcall_error(err.to_string());
+            assert_eq!(decoded, expected, "{err}");
+            assert_eq!(decoded.subcall_action(), action, "{err}");
+        }
+    }
+
+    #[test]
+    #[allow(clippy::useless_conversion)]
     fn test_parse_reply_error() {
         let err = ContractError::ParseReplyError("parse error".to_string());
         let contract_err: ContractError = err.into();
@@ -1,76 +1,120 @@
+use std::{collections::BTreeSet, str::FromStr};
+
 use cosmwasm_std::{
-    Addr, Coin, CosmosMsg, DepsMut, Env, MessageInfo, QueryRequest, Response, SubMsg,
+    attr, ensure, to_json_binary, Addr, Attribute, BankMsg, Coin, Decimal, Deps, DepsMut, Env,
+    MessageInfo, Order, QueryRequest, Response, StdError, StdResult, Storage, SubMsg, Timestamp,
+    Uint128, Uint64, WasmMsg,
 };
+use cw_storage_plus::Bound;
+use cw_utils::Expiration;
 use persistence_std::types::{
-    cosmos::base::v1beta1::Coin as StdCoin,
-    ibc::applications::transfer::v1::{QueryDenomTraceRequest, QueryDenomTraceResponse},
-    pstake::liquidstakeibc::v1beta1::MsgLiquidStake,
+    ibc::applications::transfer::v1::{
+        DenomTrace, QueryDenomTraceRequest, QueryDenomTraceResponse,
+    },
+    pstake::liquidstakeibc::v1beta1::{
+        ica_account::ChannelState, HostChain, QueryHostChainsRequest, QueryHostChainsResponse,
+        QueryParamsRequest, QueryParamsResponse,
+    },
 };
 
 use crate::{
-    contract::LS_REPLY_ID,
-    state::{LSInfo, CURRENT_TX, LS_CONFIG},
+    contract::{BATCH_REPLY_ID, LS_REPLY_ID, SCHEDULE_REPLY_ID, SWAP_REPLY_ID},
+    dexter::{AssetInfo, HopSwapRequest, RouterExecuteMsg},
+    msg::{
+        Batch, BatchConfig, BatchDeposit, ConfigChange, DenomConfig, DenomConfigChange, ExecuteMsg,
+        LsConfig, LsProvider, Operator, PendingChange, PostAction, ReferrerChange, Schedule,
+        StakeFunding, SwapHop,
+    },
+    provider::is_ibc_denom,
+    state::{
+        add_liability, owed_amount, remove_liability, schedules, BatchInfo, Governance, LSInfo,
+        Referrer, RetryInfo, SwapInfo, APPROVAL_SEQ, BATCHES, CURRENT_BATCH, CURRENT_RETRY,
+        CURRENT_SWAP, CURRENT_TX, DENOM_CONFIGS, DENOM_STATS, DEPOSITS, FAILED_STAKES, GOVERNANCE,
+        KEEPER_REWARDS, LS_CONFIG, OPERATORS, PENDING_CHANGES, PENDING_CHANGE_SEQ, POST_ACTIONS,
+        RECEIPT_CONTRACT, REFERRERS, SCHEDULE_SEQ,
+    },
     ContractError,
 };
 
 pub const DENOM_TRACE_QUERY_TYPE: &str = "/ibc.applications.transfer.v1.Query/DenomTrace";
+pub const HOST_CHAINS_QUERY_TYPE: &str = "/pstake.liquidstakeibc.v1beta1.Query/HostChains";
+pub const LIQUIDSTAKEIBC_PARAMS_QUERY_TYPE: &str = "/pstake.liquidstakeibc.v1beta1.Query/Params";
 const PERSISTENCE_ADDRESS_PREFIX: &str = "persistence";
+const MAX_REFERRAL_CODE_LENGTH: usize = 32;
+const MAX_CONFIG_TIMELOCK: u64 = 30 * 24 * 60 * 60;
+const DEFAULT_SCHEDULE_LIMIT: u32 = 10;
+const MAX_SCHEDULE_LIMIT: u32 = 30;
+const MAX_SCHEDULE_INTERVAL: u64 = 365 * 24 * 60 * 60;
+const MAX_SCHEDULES_PER_OWNER: u32 = 10;
+const MAX_BATCH_DEPOSITS: u32 = 100;
+const MAX_BATCH_AGE: u64 = 30 * 24 * 60 * 60;
+const MAX_LS_PREFIX_LENGTH: usize = 64;
+const MIN_DENOM_LENGTH: usize = 3;
+const MAX_DENOM_LENGTH: usize = 128;
 
+#[allow(clippy::too_many_arguments)]
 pub fn try_liquid_staking(
     deps: DepsMut,
     env: Env,
     coin: Coin,
     sender: Addr,
     mut receiver: Addr,
+    referral: Option<String>,
+    post_action: Option<String>,
+    funding: StakeFunding,
 ) -> Result<Response, ContractError> {
     deps.api.debug("WASMDEBUG: ls execute");
 
     let config = LS_CONFIG.load(deps.storage)?;
-    if !config.active {
-        return Err(ContractError::NotActive {});
-    }
-
-    // validate receiver address
-    receiver = match deps.api.addr_validate(receiver.as_str()) {
-        Ok(v) => v,
-        Err(_) => {
-            return Err(ContractError::InvalidReceiverAddress {
-                receiver: receiver.to_string(),
-            });
-        }
-    };
-
-    // receiver address must have prefix "persistence"
-    if !receiver.to_string().starts_with(PERSISTENCE_ADDRESS_PREFIX) {
-        return Err(ContractError::InvalidReceiverAddress {
-            receiver: receiver.to_string(),
-        });
-    }
+    receiver = validate_stake_request(
+        deps.as_ref(),
+        &config,
+        receiver,
+        referral.as_deref(),
+        post_action.as_deref(),
+    )?;
 
     let native_ibc_denom = coin.denom.clone();
     let native_amount = coin.amount;
 
-    // get base denom by querying denom trace
-    let query_denom_trace_request = QueryDenomTraceRequest {
-        hash: native_ibc_denom.clone(),
+    // route the denom to its configured provider
+    let denom_config = load_denom_config(deps.as_ref(), &native_ibc_denom)?;
+    let provider = &denom_config.provider;
+
+    let denom_trace = if provider.requires_denom_trace() {
+        Some(query_denom_trace(deps.as_ref(), &native_ibc_denom)?)
+    } else {
+        None
     };
-    let query_denom_trace_response: QueryDenomTraceResponse =
-        deps.querier.query(&QueryRequest::Stargate {
-            path: DENOM_TRACE_QUERY_TYPE.to_string(),
-            data: query_denom_trace_request.into(),
-        })?;
+    let native_base_denom = denom_trace.as_ref().map_or_else(
+        || native_ibc_denom.clone(),
+        |trace| trace.base_denom.clone(),
+    );
 
-    let native_base_denom = match query_denom_trace_response.denom_trace {
-        Some(denom_trace) => denom_trace.base_denom,
-        None => {
-            return Err(ContractError::InvalidDenom {
-                denom: native_ibc_denom,
-            });
+    // liquidstakeibc fails with an opaque error on chains that can not take deposits
+    let host_chain = denom_trace
+        .as_ref()
+        .map(|trace| query_ready_host_chain(deps.as_ref(), &native_ibc_denom, trace))
+        .transpose()?;
+
+    check_stake_amount(&denom_config, host_chain.as_ref(), native_amount)?;
+
+    // retries are staked directly to keep their tip and revert on a second failure
+    if let Some(batch_config) = &denom_config.batch {
+        if post_action.is_none() && !CURRENT_RETRY.exists(deps.storage) {
+            let deposit = BatchDeposit {
+                sender,
+                receiver,
+                amount: native_amount,
+                referral,
+                funding,
+            };
+            return queue_deposit(deps, env, batch_config, native_ibc_denom, deposit);
         }
-    };
+    }
 
     // get ls token denom
-    let ls_token_denom = format!("{}{}", config.ls_prefix, native_base_denom);
+    let ls_token_denom = denom_config.ls_token_denom(&config.ls_prefix, &native_base_denom);
 
     // get contract balance of ls asset
     let contract_ls_token_balance = deps
@@ -79,69 +123,1712 @@ pub fn try_liquid_staking(
 
     // save interim state
     let current_tx = LSInfo {
+        sender: sender.clone(),
         receiver: receiver.clone(),
+        native_denom: native_ibc_denom.clone(),
+        native_amount,
         ls_token_denom: ls_token_denom.clone(),
         prev_ls_token_balance: contract_ls_token_balance.amount,
+        referral: referral.clone(),
+        post_action: post_action.clone(),
+        funding,
+        keeper: None,
     };
     CURRENT_TX.save(deps.storage, &current_tx)?;
 
     // create the message for liquid staking
-    let msg_liquid_stake = MsgLiquidStake {
-        amount: Some(StdCoin {
-            denom: native_ibc_denom.clone(),
-            amount: native_amount.to_string(),
-        }),
-        delegator_address: env.contract.address.to_string(),
-    };
+    let msg_liquid_stake = provider.liquid_stake_msg(&env.contract.address, coin);
 
-    let res = Response::new()
-        .add_submessage(SubMsg::reply_on_success(
-            CosmosMsg::Stargate {
-                type_url: "/pstake.liquidstakeibc.v1beta1.MsgLiquidStake".to_string(),
-                value: msg_liquid_stake.into(),
-            },
-            LS_REPLY_ID,
-        ))
+    let mut res = Response::new()
+        .add_submessage(SubMsg::reply_always(msg_liquid_stake, LS_REPLY_ID))
         .add_attribute("action", "liquid_stake")
+        .add_attribute("provider", provider.name())
         .add_attribute("sender", sender.to_string())
         .add_attribute("native_amount", native_amount.to_string())
         .add_attribute("native_ibc_denom", native_ibc_denom)
         .add_attribute("native_base_denom", native_base_denom)
         .add_attribute("ls_token_denom", ls_token_denom)
         .add_attribute("receiver", receiver.to_string());
+    if let Some(code) = referral {
+        res = res.add_attribute("referral", code);
+    }
+    if let Some(name) = post_action {
+        res = res.add_attribute("post_action", name);
+    }
     Ok(res)
 }
 
-pub fn update_config(
+/// Configuration of a denom that can be liquid staked, the default one if not configured
+fn load_denom_config(deps: Deps, denom: &str) -> Result<DenomConfig, ContractError> {
+    if let Some(denom_config) = DENOM_CONFIGS.may_load(deps.storage, denom)? {
+        return Ok(denom_config);
+    }
+    // the only native denom liquidstake accepts is the staking token
+    if !is_ibc_denom(denom) && denom != deps.querier.query_bonded_denom()? {
+        return Err(ContractError::InvalidDenom {
+            denom: denom.to_string(),
+        });
+    }
+    Ok(DenomConfig::default_for(denom))
+}
+
+pub fn deposit(
+    deps: DepsMut,
+    _env: Env,
+    info: MessageInfo,
+    coin: Coin,
+) -> Result<Response, ContractError> {
+    // only tokens that can be liquid staked are taken
+    load_denom_config(deps.as_ref(), &coin.denom)?;
+
+    credit_deposit(deps.storage, &info.sender, &coin)?;
+
+    Ok(Response::new()
+        .add_attribute("method", "deposit")
+        .add_attribute("owner", info.sender.to_string())
+        .add_attribute("amount", coin.to_string()))
+}
+
+/// Credit `coin` to the deposit balance of `owner`, the contract holds the tokens for it
+fn credit_deposit(storage: &mut dyn Storage, owner: &Addr, coin: &Coin) -> StdResult<()> {
+    DEPOSITS.update(storage, (owner, &coin.denom), |balance| -> StdResult<_> {
+        Ok(balance.unwrap_or_default() + coin.amount)
+    })?;
+    add_liability(storage, coin)
+}
+
+/// Debit `coin` from the deposit balance of `owner`, the tokens are no longer owed to it
+fn take_deposit(storage: &mut dyn Storage, owner: &Addr, coin: &Coin) -> Result<(), ContractError> {
+    let available = DEPOSITS
+        .may_load(storage, (owner, &coin.denom))?
+        .unwrap_or_default();
+    ensure!(
+        coin.amount <= available,
+        ContractError::InsufficientDeposit { available }
+    );
+    let balance = available - coin.amount;
+    if balance.is_zero() {
+        DEPOSITS.remove(storage, (owner, &coin.denom));
+    } else {
+        DEPOSITS.save(storage, (owner, &coin.denom), &balance)?;
+    }
+    remove_liability(storage, coin)?;
+    Ok(())
+}
+
+/// Return the native tokens of a stake that did not go through to where they came from.
+///
+/// Funds sent with the stake go to the receiver. A stake from a deposit balance is credited
+/// back to it and the amount no longer counts against the limit of the operator that staked it.
+pub fn refund_stake(
+    storage: &mut dyn Storage,
+    funding: &StakeFunding,
+    receiver: &Addr,
+    coin: Coin,
+) -> Result<Response, ContractError> {
+    let (owner, operator, approval_id) = match funding {
+        StakeFunding::Funds => {
+            return Ok(Response::new()
+                .add_message(BankMsg::Send {
+                    to_address: receiver.to_string(),
+                    amount: vec![coin],
+                })
+                .add_attribute("receiver", receiver.to_string()))
+        }
+        StakeFunding::Deposit {
+            owner,
+            operator,
+            approval_id,
+        } => (owner, operator, approval_id),
+    };
+    credit_deposit(storage, owner, &coin)?;
+    if let Some(operator) = operator {
+        // a revoked or renewed approval has nothing to roll back
+        if let Some(mut approval) = OPERATORS.may_load(storage, (owner, operator))? {
+            if approval.approval_id == *approval_id
+                && approval
+                    .max_amount
+                    .as_ref()
+                    .is_some_and(|max_amount| max_amount.denom == coin.denom)
+            {
+                approval.spent = approval.spent.saturating_sub(coin.amount);
+                OPERATORS.save(storage, (owner, operator), &approval)?;
+            }
+        }
+    }
+    Ok(Response::new().add_attribute("deposit_owner", owner.to_string()))
+}
+
+pub fn liquid_stake_from_balance(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    amount: Coin,
+    receiver: Addr,
+) -> Result<Response, ContractError> {
+    ensure!(!amount.amount.is_zero(), ContractError::InvalidAmount {});
+    take_deposit(deps.storage, &info.sender, &amount)?;
+
+    let funding = StakeFunding::Deposit {
+        owner: info.sender.clone(),
+        operator: None,
+        approval_id: 0,
+    };
+    try_liquid_staking(
+        deps,
+        env,
+        amount,
+        info.sender,
+        receiver,
+        None,
+        None,
+        funding,
+    )
+}
+
+pub fn withdraw(
+    deps: DepsMut,
+    _env: Env,
+    info: MessageInfo,
+    amount: Coin,
+) -> Result<Response, ContractError> {
+    ensure!(!amount.amount.is_zero(), ContractError::InvalidAmount {});
+    take_deposit(deps.storage, &info.sender, &amount)?;
+
+    Ok(Response::new()
+        .add_message(BankMsg::Send {
+            to_address: info.sender.to_string(),
+            amount: vec![amount.clone()],
+        })
+        .add_attribute("method", "withdraw")
+        .add_attribute("owner", info.sender.to_string())
+        .add_attribute("amount", amount.to_string()))
+}
+
+pub fn approve_operator(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    operator: String,
+    expires: Option<Expiration>,
+    max_amount: Option<Coin>,
+) -> Result<Response, ContractError> {
+    let operator = deps.api.addr_validate(&operator)?;
+    let expires = expires.unwrap_or_default();
+    ensure!(
+        !expires.is_expired(&env.block),
+        ContractError::OperatorExpired {}
+    );
+
+    // approving again starts a new spending limit
+    let approval_id = APPROVAL_SEQ.may_load(deps.storage)?.unwrap_or_default() + 1;
+    APPROVAL_SEQ.save(deps.storage, &approval_id)?;
+    OPERATORS.save(
+        deps.storage,
+        (&info.sender, &operator),
+        &Operator {
+            operator: operator.clone(),
+            expires,
+            max_amount: max_amount.clone(),
+            spent: Uint128::zero(),
+            approval_id,
+        },
+    )?;
+
+    Ok(Response::new()
+        .add_attribute("method", "approve_operator")
+        .add_attribute("owner", info.sender.to_string())
+        .add_attribute("operator", operator.to_string())
+        .add_attribute("expires", expires.to_string())
+        .add_attribute(
+            "max_amount",
+            max_amount.map_or_else(|| "none".to_string(), |coin| coin.to_string()),
+        ))
+}
+
+pub fn revoke_operator(
+    deps: DepsMut,
+    _env: Env,
+    info: MessageInfo,
+    operator: String,
+) -> Result<Response, ContractError> {
+    let operator = deps.api.addr_validate(&operator)?;
+    if !OPERATORS.has(deps.storage, (&info.sender, &operator)) {
+        return Err(ContractError::OperatorNotApproved {
+            operator: operator.to_string(),
+        });
+    }
+    OPERATORS.remove(deps.storage, (&info.sender, &operator));
+
+    Ok(Response::new()
+        .add_attribute("method", "revoke_operator")
+        .add_attribute("owner", info.sender.to_string())
+        .add_attribute("operator", operator.to_string()))
+}
+
+pub fn liquid_stake_from(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    owner: String,
+    coin: Coin,
+    receiver: Addr,
+) -> Result<Response, ContractError> {
+    let owner = deps.api.addr_validate(&owner)?;
+    let mut operator = OPERATORS
+        .may_load(deps.storage, (&owner, &info.sender))?
+        .ok_or_else(|| ContractError::OperatorNotApproved {
+            operator: info.sender.to_string(),
+        })?;
+    ensure!(
+        !operator.expires.is_expired(&env.block),
+        ContractError::OperatorExpired {}
+    );
+    ensure!(!coin.amount.is_zero(), ContractError::InvalidAmount {});
+
+    // the limit only covers its own denom, other denoms can not be spent
+    if let Some(max_amount) = &operator.max_amount {
+        let remaining = if max_amount.denom == coin.denom {
+            max_amount.amount - operator.spent
+        } else {
+            Uint128::zero()
+        };
+        ensure!(
+            coin.amount <= remaining,
+            ContractError::OperatorLimitExceeded {
+                remaining: Coin::new(remaining.u128(), &max_amount.denom).to_string(),
+            }
+        );
+        operator.spent += coin.amount;
+        OPERATORS.save(deps.storage, (&owner, &info.sender), &operator)?;
+    }
+    take_deposit(deps.storage, &owner, &coin)?;
+
+    let funding = StakeFunding::Deposit {
+        owner: owner.clone(),
+        operator: Some(info.sender.clone()),
+        approval_id: operator.approval_id,
+    };
+    let res = try_liquid_staking(deps, env, coin, owner, receiver, None, None, funding)?;
+    Ok(res.add_attribute("operator", info.sender.to_string()))
+}
+
+pub fn create_schedule(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    denom: String,
+    amount_per_run: Uint128,
+    interval: u64,
+    receiver: Addr,
+) -> Result<Response, ContractError> {
+    let config = LS_CONFIG.load(deps.storage)?;
+    let receiver = validate_stake_request(deps.as_ref(), &config, receiver, None, None)?;
+    load_denom_config(deps.as_ref(), &denom)?;
+    ensure!(!amount_per_run.is_zero(), ContractError::InvalidAmount {});
+    ensure!(
+        (1..=MAX_SCHEDULE_INTERVAL).contains(&interval),
+        ContractError::InvalidInterval {
+            max: MAX_SCHEDULE_INTERVAL
+        }
+    );
+    // every schedule is a sub message of `ExecuteDueSchedules`, so owners get a bounded number
+    let count = schedules()
+        .idx
+        .owner
+        .prefix(info.sender.clone())
+        .keys_raw(deps.storage, None, None, Order::Ascending)
+        .count();
+    ensure!(
+        count < MAX_SCHEDULES_PER_OWNER as usize,
+        ContractError::TooManySchedules {
+            max: MAX_SCHEDULES_PER_OWNER
+        }
+    );
+    // the first run is due right away, so the balance has to cover it
+    let available = DEPOSITS
+        .may_load(deps.storage, (&info.sender, &denom))?
+        .unwrap_or_default();
+    ensure!(
+        amount_per_run <= available,
+        ContractError::InsufficientDeposit { available }
+    );
+
+    let id = SCHEDULE_SEQ.may_load(deps.storage)?.unwrap_or_default() + 1;
+    SCHEDULE_SEQ.save(deps.storage, &id)?;
+    // the first run is due right away
+    schedules().save(
+        deps.storage,
+        id,
+        &Schedule {
+            id,
+            owner: info.sender.clone(),
+            denom: denom.clone(),
+            amount_per_run,
+            interval,
+            receiver: receiver.clone(),
+            next_run: env.block.time,
+        },
+    )?;
+
+    Ok(Response::new()
+        .add_attribute("method", "create_schedule")
+        .add_attribute("id", id.to_string())
+        .add_attribute("owner", info.sender.to_string())
+        .add_attribute("receiver", receiver.to_string())
+        .add_attribute(
+            "amount_per_run",
+            Coin::new(amount_per_run.u128(), denom).to_string(),
+        )
+        .add_attribute("interval", interval.to_string()))
+}
+
+pub fn cancel_schedule(
     deps: DepsMut,
     _env: Env,
     info: MessageInfo,
+    id: u64,
+) -> Result<Response, ContractError> {
+    let schedule = schedules()
+        .may_load(deps.storage, id)?
+        .ok_or(ContractError::UnknownSchedule { id })?;
+    ensure!(
+        schedule.owner == info.sender,
+        ContractError::Unauthorized {}
+    );
+    schedules().remove(deps.storage, id)?;
+
+    Ok(Response::new()
+        .add_attribute("method", "cancel_schedule")
+        .add_attribute("id", id.to_string())
+        .add_attribute("owner", info.sender.to_string()))
+}
+
+/// Dispatch a `RunSchedule` call to the contract itself for every due schedule.
+///
+/// Each run is its own sub message, so every liquid stake gets its own reply and a run that
+/// fails, for example on an empty deposit balance, is skipped without reverting the others.
+/// A schedule whose next run can not be represented is cancelled instead of failing the call.
+pub fn execute_due_schedules(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    limit: Option<u32>,
+) -> Result<Response, ContractError> {
+    let config = LS_CONFIG.load(deps.storage)?;
+    ensure!(config.active, ContractError::NotActive {});
+
+    let now = env.block.time;
+    let limit = limit
+        .unwrap_or(DEFAULT_SCHEDULE_LIMIT)
+        .min(MAX_SCHEDULE_LIMIT) as usize;
+    let due = schedules()
+        .idx
+        .next_run
+        .range(
+            deps.storage,
+            None,
+            Some(Bound::inclusive((now.seconds(), u64::MAX))),
+            Order::Ascending,
+        )
+        .take(limit)
+        .map(|item| item.map(|(_, schedule)| schedule))
+        .collect::<StdResult<Vec<_>>>()?;
+
+    let mut res = Response::new();
+    for mut schedule in due {
+        // missed runs are not made up, the schedule moves to its first run after now
+        let periods = (now.seconds() - schedule.next_run.seconds()) / schedule.interval + 1;
+        let next_run = Uint64::new(periods)
+            .checked_mul(Uint64::new(schedule.interval))
+            .map_err(ContractError::from)
+            .and_then(|seconds| checked_plus_seconds(schedule.next_run, seconds.u64()));
+        let Ok(next_run) = next_run else {
+            schedules().remove(deps.storage, schedule.id)?;
+            res = res.add_attribute("cancelled_schedule", schedule.id.to_string());
+            continue;
+        };
+        schedule.next_run = next_run;
+        schedules().save(deps.storage, schedule.id, &schedule)?;
+
+        res = res.add_submessage(SubMsg::reply_on_error(
+            WasmMsg::Execute {
+                contract_addr: env.contract.address.to_string(),
+                msg: to_json_binary(&ExecuteMsg::RunSchedule {
+                    id: schedule.id,
+                    keeper: info.sender.clone(),
+                })?,
+                funds: vec![],
+            },
+            SCHEDULE_REPLY_ID,
+        ));
+    }
+
+    let count = res.messages.len();
+    Ok(res
+        .add_attribute("method", "execute_due_schedules")
+        .add_attribute("schedules", count.to_string()))
+}
+
+/// Liquid stake one run of a schedule from the owner's deposit balance
+pub fn run_schedule(
+    mut deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    id: u64,
+    keeper: Addr,
+) -> Result<Response, ContractError> {
+    ensure!(
+        info.sender == env.contract.address,
+        ContractError::Unauthorized {}
+    );
+    let schedule = schedules()
+        .may_load(deps.storage, id)?
+        .ok_or(ContractError::UnknownSchedule { id })?;
+    let coin = Coin {
+        denom: schedule.denom,
+        amount: schedule.amount_per_run,
+    };
+    take_deposit(deps.storage, &schedule.owner, &coin)?;
+
+    let funding = StakeFunding::Deposit {
+        owner: schedule.owner.clone(),
+        operator: None,
+        approval_id: 0,
+    };
+    let res = try_liquid_staking(
+        deps.branch(),
+        env,
+        coin,
+        schedule.owner,
+        schedule.receiver,
+        None,
+        None,
+        funding,
+    )?;
+    // a run queued in a batch tips the keeper of the batch instead
+    if let Some(mut current_tx) = CURRENT_TX.may_load(deps.storage)? {
+        current_tx.keeper = Some(keeper);
+        CURRENT_TX.save(deps.storage, &current_tx)?;
+    }
+    Ok(res.add_attribute("schedule_id", id.to_string()))
+}
+
+/// Add the deposit to the batch of the denom, dispatching the batch once it is due
+fn queue_deposit(
+    mut deps: DepsMut,
+    env: Env,
+    batch_config: &BatchConfig,
+    denom: String,
+    deposit: BatchDeposit,
+) -> Result<Response, ContractError> {
+    let mut batch = BATCHES
+        .may_load(deps.storage, &denom)?
+        .unwrap_or_else(|| Batch {
+            denom: denom.clone(),
+            deposits: vec![],
+            total: Uint128::zero(),
+            opened_at: env.block.time,
+        });
+    ensure!(
+        batch.deposits.len() < batch_config.max_deposits as usize,
+        ContractError::BatchFull { denom }
+    );
+    batch.total += deposit.amount;
+    add_liability(
+        deps.storage,
+        &Coin {
+            denom: denom.clone(),
+            amount: deposit.amount,
+        },
+    )?;
+
+    let mut res = Response::new()
+        .add_attribute("action", "queue_deposit")
+        .add_attribute("sender", deposit.sender.to_string())
+        .add_attribute("native_amount", deposit.amount.to_string())
+        .add_attribute("native_ibc_denom", denom.clone())
+        .add_attribute("receiver", deposit.receiver.to_string());
+    batch.deposits.push(deposit);
+
+    if batch_config.is_due(&batch, env.block.time) {
+        match dispatch_batch(deps.branch(), env, batch.clone(), None) {
+            Ok(dispatch) => {
+                BATCHES.remove(deps.storage, &denom);
+                return Ok(res
+                    .add_submessages(dispatch.messages)
+                    .add_attributes(dispatch.attributes));
+            }
+            Err(err) => res = res.add_attribute("dispatch_error", err.to_string()),
+        }
+    }
+    BATCHES.save(deps.storage, &denom, &batch)?;
+    Ok(res.add_attribute("batch_size", batch.deposits.len().to_string()))
+}
+
+pub fn process_batch(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    denom: String,
+) -> Result<Response, ContractError> {
+    let batch = BATCHES
+        .may_load(deps.storage, &denom)?
+        .ok_or(ContractError::EmptyBatch {
+            denom: denom.clone(),
+        })?;
+    BATCHES.remove(deps.storage, &denom);
+    dispatch_batch(deps, env, batch, Some(info.sender))
+}
+
+/// Take the deposits sent by or for the sender out of the batch of `denom`, they are refunded
+/// like a cancelled stake
+pub fn withdraw_queued(
+    deps: DepsMut,
+    _env: Env,
+    info: MessageInfo,
+    denom: String,
+) -> Result<Response, ContractError> {
+    let mut batch = BATCHES
+        .may_load(deps.storage, &denom)?
+        .ok_or(ContractError::EmptyBatch {
+            denom: denom.clone(),
+        })?;
+    let (withdrawn, queued): (Vec<_>, Vec<_>) = batch
+        .deposits
+        .into_iter()
+        .partition(|deposit| deposit.sender == info.sender || deposit.receiver == info.sender);
+    ensure!(
+        !withdrawn.is_empty(),
+        ContractError::NoQueuedDeposits {
+            denom: denom.clone(),
+        }
+    );
+    let amount: Uint128 = withdrawn.iter().map(|deposit| deposit.amount).sum();
+    batch.total -= amount;
+    batch.deposits = queued;
+    if batch.deposits.is_empty() {
+        BATCHES.remove(deps.storage, &denom);
+    } else {
+        BATCHES.save(deps.storage, &denom, &batch)?;
+    }
+    remove_liability(
+        deps.storage,
+        &Coin {
+            denom: denom.clone(),
+            amount,
+        },
+    )?;
+
+    let refund = refund_batch_deposits(deps.storage, &denom, withdrawn)?;
+    Ok(Response::new()
+        .add_submessages(refund.messages)
+        .add_attribute("method", "withdraw_queued")
+        .add_attribute("owner", info.sender.to_string())
+        .add_attribute("refund", Coin::new(amount.u128(), denom).to_string()))
+}
+
+/// Refund deposits taken out of a batch, the caller releases their liability
+pub fn refund_batch_deposits(
+    storage: &mut dyn Storage,
+    denom: &str,
+    deposits: Vec<BatchDeposit>,
+) -> Result<Response, ContractError> {
+    let mut res = Response::new();
+    for deposit in deposits {
+        let refund = refund_stake(
+            storage,
+            &deposit.funding,
+            &deposit.receiver,
+            Coin {
+                denom: denom.to_string(),
+                amount: deposit.amount,
+            },
+        )?;
+        res = res.add_submessages(refund.messages);
+    }
+    Ok(res)
+}
+
+/// Liquid stake the total of the batch in one message, the reply distributes the mint.
+///
+/// The total is checked like a single stake, a batch that can not be dispatched stays queued
+/// until it can or its deposits are withdrawn. Nothing is written before the checks pass.
+fn dispatch_batch(
+    deps: DepsMut,
+    env: Env,
+    batch: Batch,
+    keeper: Option<Addr>,
+) -> Result<Response, ContractError> {
+    let config = LS_CONFIG.load(deps.storage)?;
+    ensure!(config.active, ContractError::NotActive {});
+    let denom_config = load_denom_config(deps.as_ref(), &batch.denom)?;
+    let provider = &denom_config.provider;
+
+    let denom_trace = if provider.requires_denom_trace() {
+        Some(query_denom_trace(deps.as_ref(), &batch.denom)?)
+    } else {
+        None
+    };
+    let base_denom = denom_trace
+        .as_ref()
+        .map_or_else(|| batch.denom.clone(), |trace| trace.base_denom.clone());
+    let host_chain = denom_trace
+        .as_ref()
+        .map(|trace| query_ready_host_chain(deps.as_ref(), &batch.denom, trace))
+        .transpose()?;
+    check_stake_amount(&denom_config, host_chain.as_ref(), batch.total)?;
+
+    let ls_token_denom = denom_config.ls_token_denom(&config.ls_prefix, &base_denom);
+    let contract_ls_token_balance = deps
+        .querier
+        .query_balance(env.contract.address.clone(), ls_token_denom.clone())?;
+
+    let coin = Coin {
+        denom: batch.denom.clone(),
+        amount: batch.total,
+    };
+    remove_liability(deps.storage, &coin)?;
+
+    let res = Response::new()
+        .add_submessage(SubMsg::reply_always(
+            provider.liquid_stake_msg(&env.contract.address, coin),
+            BATCH_REPLY_ID,
+        ))
+        .add_attribute("action", "process_batch")
+        .add_attribute("provider", provider.name())
+        .add_attribute("native_ibc_denom", batch.denom.clone())
+        .add_attribute("native_amount", batch.total.to_string())
+        .add_attribute("deposits", batch.deposits.len().to_string())
+        .add_attribute("ls_token_denom", ls_token_denom.clone());
+    CURRENT_BATCH.save(
+        deps.storage,
+        &BatchInfo {
+            batch,
+            ls_token_denom,
+            prev_ls_token_balance: contract_ls_token_balance.amount,
+            keeper,
+        },
+    )?;
+    Ok(res)
+}
+
+/// Base denom staked by the provider, resolved through the denom trace for IBC denoms
+pub fn query_base_denom(
+    deps: Deps,
+    provider: &LsProvider,
+    denom: &str,
+) -> Result<String, ContractError> {
+    if !provider.requires_denom_trace() {
+        return Ok(denom.to_string());
+    }
+    Ok(query_denom_trace(deps, denom)?.base_denom)
+}
+
+fn query_denom_trace(deps: Deps, denom: &str) -> Result<DenomTrace, ContractError> {
+    let query_denom_trace_request = QueryDenomTraceRequest {
+        hash: denom.to_string(),
+    };
+    let query_denom_trace_response: QueryDenomTraceResponse =
+        deps.querier.query(&QueryRequest::Stargate {
+            path: DENOM_TRACE_QUERY_TYPE.to_string(),
+            data: query_denom_trace_request.into(),
+        })?;
+
+    query_denom_trace_response
+        .denom_trace
+        .ok_or_else(|| ContractError::InvalidDenom {
+            denom: denom.to_string(),
+        })
+}
+
+/// Host chain the IBC denom was transferred from, matched on the base denom and the channel
+/// of a single hop trace
+pub fn query_host_chain(
+    deps: Deps,
+    denom_trace: &DenomTrace,
+) -> Result<Option<HostChain>, ContractError> {
+    let response: QueryHostChainsResponse = deps.querier.query(&QueryRequest::Stargate {
+        path: HOST_CHAINS_QUERY_TYPE.to_string(),
+        data: QueryHostChainsRequest {}.into(),
+    })?;
+    Ok(response.host_chains.into_iter().find(|host_chain| {
+        host_chain.host_denom == denom_trace.base_denom
+            && denom_trace.path == format!("{}/{}", host_chain.port_id, host_chain.channel_id)
+    }))
+}
+
+/// Host chain of `denom`, checked to accept deposits: active, with an open delegation ICA
+/// and a c-value within the module limits
+fn query_ready_host_chain(
+    deps: Deps,
+    denom: &str,
+    denom_trace: &DenomTrace,
+) -> Result<HostChain, ContractError> {
+    let host_chain =
+        query_host_chain(deps, denom_trace)?.ok_or_else(|| ContractError::HostChainNotFound {
+            denom: denom.to_string(),
+        })?;
+    let chain_id = host_chain.chain_id.clone();
+
+    ensure!(
+        host_chain.active,
+        ContractError::HostChainInactive { chain_id }
+    );
+    let ica_open = host_chain
+        .delegation_account
+        .as_ref()
+        .is_some_and(|ica| ica.channel_state == ChannelState::IcaChannelCreated as i32);
+    ensure!(ica_open, ContractError::IcaChannelClosed { chain_id });
+
+    let response: QueryParamsResponse = deps.querier.query(&QueryRequest::Stargate {
+        path: LIQUIDSTAKEIBC_PARAMS_QUERY_TYPE.to_string(),
+        data: QueryParamsRequest {}.into(),
+    })?;
+    let params = response
+        .params
+        .ok_or_else(|| StdError::not_found("liquidstakeibc params"))?;
+    let c_value = Decimal::from_str(&host_chain.c_value)?;
+    ensure!(
+        Decimal::from_str(&params.lower_c_value_limit)? <= c_value
+            && c_value <= Decimal::from_str(&params.upper_c_value_limit)?,
+        ContractError::CValueOutOfLimits { chain_id }
+    );
+
+    Ok(host_chain)
+}
+
+/// Rejects stakes outside the limits of the denom, the host chain minimum deposit raising
+/// the minimum when enforced
+fn check_stake_amount(
+    denom_config: &DenomConfig,
+    host_chain: Option<&HostChain>,
+    amount: Uint128,
+) -> Result<(), ContractError> {
+    let mut min = denom_config.min_amount.unwrap_or_default();
+    if let Some(host_chain) = host_chain.filter(|_| denom_config.enforce_module_minimum) {
+        min = min.max(Uint128::from_str(&host_chain.minimum_deposit)?);
+    }
+    ensure!(amount >= min, ContractError::BelowMinimum { min });
+
+    if let Some(max) = denom_config.max_amount {
+        ensure!(amount <= max, ContractError::AboveMaximum { max });
+    }
+    Ok(())
+}
+
+#[allow(clippy::too_many_arguments)]
+pub fn swap_and_liquid_stake(
+    deps: DepsMut,
+    env: Env,
+    offer: Coin,
+    sender: Addr,
+    receiver: Addr,
+    route: Vec<SwapHop>,
+    min_out: Uint128,
+    referral: Option<String>,
+    post_action: Option<String>,
+) -> Result<Response, ContractError> {
+    deps.api.debug("WASMDEBUG: swap and ls execute");
+
+    let config = LS_CONFIG.load(deps.storage)?;
+    let receiver = validate_stake_request(
+        deps.as_ref(),
+        &config,
+        receiver,
+        referral.as_deref(),
+        post_action.as_deref(),
+    )?;
+    let router = config
+        .swap_router
+        .ok_or(ContractError::SwapRouterNotSet {})?;
+
+    let ask_denom = match route.last() {
+        Some(hop) if hop.ask_denom != offer.denom => hop.ask_denom.clone(),
+        _ => return Err(ContractError::InvalidSwapRoute {}),
+    };
+
+    // each hop offers what the previous one asked for
+    let mut offer_denom = offer.denom.clone();
+    let requests = route
+        .into_iter()
+        .map(|hop| HopSwapRequest {
+            pool_id: hop.pool_id,
+            asset_in: AssetInfo::NativeToken {
+                denom: std::mem::replace(&mut offer_denom, hop.ask_denom.clone()),
+            },
+            asset_out: AssetInfo::NativeToken {
+                denom: hop.ask_denom,
+            },
+        })
+        .collect();
+
+    // get contract balance of the swap output
+    let prev_ask_balance = deps
+        .querier
+        .query_balance(env.contract.address, ask_denom.clone())?;
+
+    CURRENT_SWAP.save(
+        deps.storage,
+        &SwapInfo {
+            sender: sender.clone(),
+            receiver: receiver.clone(),
+            offer: offer.clone(),
+            ask_denom: ask_denom.clone(),
+            prev_ask_balance: prev_ask_balance.amount,
+            referral,
+            post_action,
+        },
+    )?;
+
+    let swap_msg = WasmMsg::Execute {
+        contract_addr: router.to_string(),
+        msg: to_json_binary(&RouterExecuteMsg::ExecuteMultihopSwap {
+            requests,
+            offer_amount: offer.amount,
+            recipient: None,
+            minimum_receive: Some(min_out),
+        })?,
+        funds: vec![offer.clone()],
+    };
+
+    Ok(Response::new()
+        .add_submessage(SubMsg::reply_always(swap_msg, SWAP_REPLY_ID))
+        .add_attribute("action", "swap_and_liquid_stake")
+        .add_attribute("sender", sender.to_string())
+        .add_attribute("offer", offer.to_string())
+        .add_attribute("ask_denom", ask_denom)
+        .add_attribute("min_out", min_out.to_string())
+        .add_attribute("receiver", receiver.to_string()))
+}
+
+/// Checks shared by the liquid stake entry points, returns the validated receiver
+fn validate_stake_request(
+    deps: Deps,
+    config: &LsConfig,
+    receiver: Addr,
+    referral: Option<&str>,
+    post_action: Option<&str>,
+) -> Result<Addr, ContractError> {
+    if !config.active {
+        return Err(ContractError::NotActive {});
+    }
+
+    // validate receiver address
+    let receiver = match deps.api.addr_validate(receiver.as_str()) {
+        Ok(v) => v,
+        Err(_) => {
+            return Err(ContractError::InvalidReceiverAddress {
+                receiver: receiver.to_string(),
+            });
+        }
+    };
+
+    // receiver address must have prefix "persistence"
+    if !receiver.to_string().starts_with(PERSISTENCE_ADDRESS_PREFIX) {
+        return Err(ContractError::InvalidReceiverAddress {
+            receiver: receiver.to_string(),
+        });
+    }
+
+    // referral code must belong to a registered partner
+    if let Some(code) = referral {
+        if !REFERRERS.has(deps.storage, code) {
+            return Err(ContractError::UnknownReferral {
+                code: code.to_string(),
+            });
+        }
+    }
+
+    if let Some(name) = post_action {
+        if !POST_ACTIONS.has(deps.storage, name) {
+            return Err(ContractError::UnknownPostAction {
+                name: name.to_string(),
+            });
+        }
+    }
+
+    Ok(receiver)
+}
+
+#[allow(clippy::too_many_arguments)]
+pub fn update_config(
+    mut deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
     active: Option<bool>,
     ls_prefix: Option<String>,
+    protocol_fee: Option<Decimal>,
+    fee_recipient: Option<String>,
+    history_limit: Option<u64>,
+    swap_router: Option<String>,
+    verify_ls_prefix: Option<bool>,
+    config_timelock: Option<u64>,
 ) -> Result<Response, ContractError> {
     deps.api.debug("WASMDEBUG: update config");
 
     let mut ls_config = LS_CONFIG.load(deps.storage)?;
 
     // only admin can update config
-    if info.sender != ls_config.admin {
-        return Err(ContractError::Unauthorized {});
-    }
+    assert_admin(deps.storage, &ls_config, &info.sender)?;
 
     let mut res = Response::new().add_attribute("method", "update_config");
 
-    // update contract config
+    // pausing stays instant for emergencies
     if let Some(active) = active {
         ls_config.active = active;
 
         res = res.add_attribute("active", ls_config.active.to_string());
     }
-    if let Some(ls_prefix) = ls_prefix {
+
+    let change = ConfigChange {
+        ls_prefix: ls_prefix.map(validate_ls_prefix).transpose()?,
+        protocol_fee: protocol_fee.map(validate_protocol_fee).transpose()?,
+        fee_recipient: fee_recipient
+            .map(|fee_recipient| deps.api.addr_validate(&fee_recipient))
+            .transpose()?,
+        swap_router: swap_router
+            .map(|swap_router| deps.api.addr_validate(&swap_router))
+            .transpose()?,
+        config_timelock: config_timelock.map(validate_config_timelock).transpose()?,
+        verify_ls_prefix: verify_ls_prefix.unwrap_or_default(),
+        ..ConfigChange::default()
+    };
+    if change.is_empty() {
+        if change.verify_ls_prefix {
+            verify_ls_token_supply(deps.as_ref(), &ls_config.ls_prefix)?;
+        }
+    } else if ls_config.config_timelock == 0 {
+        let attributes = apply_config_change(deps.branch(), &mut ls_config, change)?;
+        res = res.add_attributes(attributes);
+    } else {
+        let attributes = queue_config_change(deps.storage, &env, &ls_config, change)?;
+        res = res.add_attributes(attributes);
+    }
+
+    if let Some(history_limit) = history_limit {
+        ls_config.history_limit = history_limit;
+
+        res = res.add_attribute("history_limit", ls_config.history_limit.to_string());
+    }
+    LS_CONFIG.save(deps.storage, &ls_config)?;
+
+    Ok(res)
+}
+
+/// Saves `change` as a pending change executable once the timelock expired. Fields deciding where
+/// funds go, like fee shares or the provider of a denom, wait for it, the other fields of the same
+/// message apply right away.
+fn queue_config_change(
+    storage: &mut dyn Storage,
+    env: &Env,
+    ls_config: &LsConfig,
+    change: ConfigChange,
+) -> Result<Vec<Attribute>, ContractError> {
+    let id = PENDING_CHANGE_SEQ.may_load(storage)?.unwrap_or_default() + 1;
+    PENDING_CHANGE_SEQ.save(storage, &id)?;
+
+    let executable_at = checked_plus_seconds(env.block.time, ls_config.config_timelock)?;
+    PENDING_CHANGES.save(
+        storage,
+        id,
+        &PendingChange {
+            id,
+            change,
+            executable_at,
+        },
+    )?;
+
+    Ok(vec![
+        attr("pending_change_id", id.to_string()),
+        attr("executable_at", executable_at.to_string()),
+    ])
+}
+
+/// Applies validated sensitive fields to `ls_config`, returning the changed values as attributes
+fn apply_config_change(
+    deps: DepsMut,
+    ls_config: &mut LsConfig,
+    change: ConfigChange,
+) -> Result<Vec<Attribute>, ContractError> {
+    let mut attributes = vec![];
+    if let Some(ls_prefix) = change.ls_prefix {
+        attributes.push(attr("ls_prefix", &ls_prefix));
         ls_config.ls_prefix = ls_prefix;
+    }
+    if let Some(protocol_fee) = change.protocol_fee {
+        ls_config.protocol_fee = protocol_fee;
+        attributes.push(attr("protocol_fee", protocol_fee.to_string()));
+    }
+    if let Some(fee_recipient) = change.fee_recipient {
+        attributes.push(attr("fee_recipient", &fee_recipient));
+        ls_config.fee_recipient = fee_recipient;
+    }
+    if let Some(swap_router) = change.swap_router {
+        attributes.push(attr("swap_router", &swap_router));
+        ls_config.swap_router = Some(swap_router);
+    }
+    if let Some(config_timelock) = change.config_timelock {
+        ls_config.config_timelock = config_timelock;
+        attributes.push(attr("config_timelock", config_timelock.to_string()));
+    }
+    if let Some(keeper_fee_share) = change.keeper_fee_share {
+        ls_config.keeper_fee_share = keeper_fee_share;
+        attributes.push(attr("keeper_fee_share", keeper_fee_share.to_string()));
+    }
+    if let Some(change) = change.referrer {
+        attributes.push(attr("referrer", &change.code));
+        attributes.push(attr("referrer_fee_share", change.fee_share.to_string()));
+        let mut referrer = REFERRERS.load(deps.storage, &change.code)?;
+        referrer.fee_share = change.fee_share;
+        REFERRERS.save(deps.storage, &change.code, &referrer)?;
+    }
+    if let Some(change) = change.denom_config {
+        // the rest of the config may have changed while the change was pending
+        let mut config = DENOM_CONFIGS
+            .may_load(deps.storage, &change.denom)?
+            .unwrap_or_else(|| DenomConfig::default_for(&change.denom));
+        attributes.push(attr("denom", &change.denom));
+        attributes.push(attr("provider", change.provider.name()));
+        attributes.push(attr(
+            "ls_token_denom",
+            change.ls_token_denom.as_deref().unwrap_or("none"),
+        ));
+        config.provider = change.provider;
+        config.ls_token_denom = change.ls_token_denom;
+        DENOM_CONFIGS.save(deps.storage, &change.denom, &config)?;
+    }
+    if change.verify_ls_prefix {
+        verify_ls_token_supply(deps.as_ref(), &ls_config.ls_prefix)?;
+    }
+    Ok(attributes)
+}
 
-        res = res.add_attribute("ls_prefix", ls_config.clone().ls_prefix);
+pub fn execute_pending_change(
+    mut deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    id: u64,
+) -> Result<Response, ContractError> {
+    let mut ls_config = LS_CONFIG.load(deps.storage)?;
+    assert_admin(deps.storage, &ls_config, &info.sender)?;
+
+    let pending = PENDING_CHANGES
+        .may_load(deps.storage, id)?
+        .ok_or(ContractError::UnknownPendingChange { id })?;
+    ensure!(
+        env.block.time >= pending.executable_at,
+        ContractError::TimelockNotExpired {
+            executable_at: pending.executable_at
+        }
+    );
+    PENDING_CHANGES.remove(deps.storage, id);
+
+    let attributes = apply_config_change(deps.branch(), &mut ls_config, pending.change)?;
+    LS_CONFIG.save(deps.storage, &ls_config)?;
+
+    Ok(Response::new()
+        .add_attribute("method", "execute_pending_change")
+        .add_attribute("id", id.to_string())
+        .add_attributes(attributes))
+}
+
+pub fn cancel_pending_change(
+    deps: DepsMut,
+    _env: Env,
+    info: MessageInfo,
+    id: u64,
+) -> Result<Response, ContractError> {
+    let ls_config = LS_CONFIG.load(deps.storage)?;
+    assert_admin(deps.storage, &ls_config, &info.sender)?;
+
+    if !PENDING_CHANGES.has(deps.storage, id) {
+        return Err(ContractError::UnknownPendingChange { id });
     }
+    PENDING_CHANGES.remove(deps.storage, id);
+
+    Ok(Response::new()
+        .add_attribute("method", "cancel_pending_change")
+        .add_attribute("id", id.to_string()))
+}
+
+pub fn retry_stake(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    id: u64,
+) -> Result<Response, ContractError> {
+    let stake = FAILED_STAKES
+        .may_load(deps.storage, id)?
+        .ok_or(ContractError::UnknownFailedStake { id })?;
+    FAILED_STAKES.remove(deps.storage, id);
+    remove_liability(deps.storage, &stake.coin)?;
+
+    // a transient failure of the retry reverts it and keeps the stake parked under its id
+    CURRENT_RETRY.save(
+        deps.storage,
+        &RetryInfo {
+            id,
+            keeper: info.sender,
+        },
+    )?;
+
+    let res = try_liquid_staking(
+        deps,
+        env,
+        stake.coin,
+        stake.sender,
+        stake.receiver,
+        stake.referral,
+        stake.post_action,
+        stake.funding,
+    )?;
+    Ok(res.add_attribute("failed_stake_id", id.to_string()))
+}
+
+pub fn cancel_stake(
+    deps: DepsMut,
+    _env: Env,
+    info: MessageInfo,
+    id: u64,
+) -> Result<Response, ContractError> {
+    let stake = FAILED_STAKES
+        .may_load(deps.storage, id)?
+        .ok_or(ContractError::UnknownFailedStake { id })?;
+    // the owner of a deposit balance is the sender of its stakes
+    ensure!(
+        info.sender == stake.sender || info.sender == stake.receiver,
+        ContractError::Unauthorized {}
+    );
+    FAILED_STAKES.remove(deps.storage, id);
+    remove_liability(deps.storage, &stake.coin)?;
+
+    let res = Response::new()
+        .add_attribute("method", "cancel_stake")
+        .add_attribute("id", id.to_string())
+        .add_attribute("refund", stake.coin.to_string());
+    let refund = refund_stake(deps.storage, &stake.funding, &stake.receiver, stake.coin)?;
+    Ok(res
+        .add_submessages(refund.messages)
+        .add_attributes(refund.attributes))
+}
+
+pub fn set_keeper_tip(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    fee_share: Decimal,
+    tips_per_block: u32,
+) -> Result<Response, ContractError> {
+    let mut ls_config = LS_CONFIG.load(deps.storage)?;
+    assert_admin(deps.storage, &ls_config, &info.sender)?;
+
+    if fee_share > Decimal::one() {
+        return Err(ContractError::InvalidFeeShare {});
+    }
+    let mut res = Response::new().add_attribute("method", "set_keeper_tip");
+
+    if ls_config.config_timelock == 0 || fee_share == ls_config.keeper_fee_share {
+        ls_config.keeper_fee_share = fee_share;
+        res = res.add_attribute("fee_share", fee_share.to_string());
+    } else {
+        let change = ConfigChange {
+            keeper_fee_share: Some(fee_share),
+            ..ConfigChange::default()
+        };
+        let attributes = queue_config_change(deps.storage, &env, &ls_config, change)?;
+        res = res.add_attributes(attributes);
+    }
+    ls_config.keeper_tips_per_block = tips_per_block;
     LS_CONFIG.save(deps.storage, &ls_config)?;
 
+    Ok(res.add_attribute("tips_per_block", tips_per_block.to_string()))
+}
+
+pub fn claim_keeper_rewards(
+    deps: DepsMut,
+    _env: Env,
+    info: MessageInfo,
+) -> Result<Response, ContractError> {
+    let rewards = KEEPER_REWARDS
+        .prefix(&info.sender)
+        .range(deps.storage, None, None, Order::Ascending)
+        .map(|item| item.map(|(denom, amount)| Coin { denom, amount }))
+        .collect::<StdResult<Vec<_>>>()?;
+    if rewards.is_empty() {
+        return Err(ContractError::NoKeeperRewards {});
+    }
+    for coin in &rewards {
+        KEEPER_REWARDS.remove(deps.storage, (&info.sender, &coin.denom));
+        remove_liability(deps.storage, coin)?;
+    }
+
+    Ok(Response::new()
+        .add_message(BankMsg::Send {
+            to_address: info.sender.to_string(),
+            amount: rewards.clone(),
+        })
+        .add_attribute("method", "claim_keeper_rewards")
+        .add_attribute("keeper", info.sender.to_string())
+        .add_attribute(
+            "rewards",
+            rewards
+                .iter()
+                .map(Coin::to_string)
+                .collect::<Vec<_>>()
+                .join(","),
+        ))
+}
+
+/// Denoms start with a letter followed by letters, digits or `/:._-`, as in the bank module
+fn is_denom_like(value: &str) -> bool {
+    let mut chars = value.chars();
+    matches!(chars.next(), Some(c) if c.is_ascii_alphabetic())
+        && chars.all(|c| c.is_ascii_alphanumeric() || "/:._-".contains(c))
+}
+
+pub fn validate_ls_prefix(ls_prefix: String) -> Result<String, ContractError> {
+    if ls_prefix.len() > MAX_LS_PREFIX_LENGTH || !is_denom_like(&ls_prefix) {
+        return Err(ContractError::InvalidLsPrefix { prefix: ls_prefix });
+    }
+    Ok(ls_prefix)
+}
+
+/// Dry run of `ls_prefix`: the LS token of the bonded denom, and of every configured or
+/// previously staked denom deriving its LS token from the prefix, must exist in the bank
+fn verify_ls_token_supply(deps: Deps, ls_prefix: &str) -> Result<(), ContractError> {
+    let mut denoms = BTreeSet::from([deps.querier.query_bonded_denom()?]);
+    denoms.extend(
+        DENOM_CONFIGS
+            .keys(deps.storage, None, None, Order::Ascending)
+            .collect::<StdResult<Vec<_>>>()?,
+    );
+    denoms.extend(
+        DENOM_STATS
+            .keys(deps.storage, None, None, Order::Ascending)
+            .collect::<StdResult<Vec<_>>>()?,
+    );
+
+    for denom in denoms {
+        let denom_config = DENOM_CONFIGS
+            .may_load(deps.storage, &denom)?
+            .unwrap_or_else(|| DenomConfig::default_for(&denom));
+        if !denom_config.uses_ls_prefix() {
+            continue;
+        }
+        let base_denom = query_base_denom(deps, &denom_config.provider, &denom)?;
+        let ls_token_denom = denom_config.ls_token_denom(ls_prefix, &base_denom);
+        if deps.querier.query_supply(&ls_token_denom)?.amount.is_zero() {
+            return Err(ContractError::UnknownLsTokenDenom {
+                denom: ls_token_denom,
+            });
+        }
+    }
+    Ok(())
+}
+
+/// The timelock is capped so that pending changes always get a valid `executable_at`
+pub fn validate_config_timelock(config_timelock: u64) -> Result<u64, ContractError> {
+    ensure!(
+        config_timelock <= MAX_CONFIG_TIMELOCK,
+        ContractError::InvalidConfigTimelock {
+            max: MAX_CONFIG_TIMELOCK
+        }
+    );
+    Ok(config_timelock)
+}
+
+/// `time` moved by `seconds`, an overflow is an error instead of a panic
+fn checked_plus_seconds(time: Timestamp, seconds: u64) -> Result<Timestamp, ContractError> {
+    let nanos = Uint64::new(seconds)
+        .checked_mul(Uint64::new(1_000_000_000))?
+        .checked_add(Uint64::new(time.nanos()))?;
+    Ok(Timestamp::from_nanos(nanos.u64()))
+}
+
+pub fn validate_protocol_fee(protocol_fee: Decimal) -> Result<Decimal, ContractError> {
+    if protocol_fee >= Decimal::one() {
+        return Err(ContractError::InvalidProtocolFee {});
+    }
+    Ok(protocol_fee)
+}
+
+pub fn register_referrer(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    code: String,
+    address: String,
+    fee_share: Option<Decimal>,
+) -> Result<Response, ContractError> {
+    deps.api.debug("WASMDEBUG: register referrer");
+
+    let ls_config = LS_CONFIG.load(deps.storage)?;
+    assert_admin(deps.storage, &ls_config, &info.sender)?;
+
+    if code.is_empty()
+        || code.len() > MAX_REFERRAL_CODE_LENGTH
+        || !code
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+    {
+        return Err(ContractError::InvalidReferralCode { code });
+    }
+
+    let fee_share = fee_share.unwrap_or_default();
+    if fee_share > Decimal::one() {
+        return Err(ContractError::InvalidFeeShare {});
+    }
+
+    let address = deps.api.addr_validate(&address)?;
+
+    let mut res = Response::new()
+        .add_attribute("method", "register_referrer")
+        .add_attribute("code", code.clone())
+        .add_attribute("address", address.to_string())
+        .add_attribute("fee_share", fee_share.to_string());
+
+    let mut applied_fee_share = fee_share;
+    let current_fee_share = REFERRERS
+        .may_load(deps.storage, &code)?
+        .map(|referrer| referrer.fee_share)
+        .unwrap_or_default();
+    if ls_config.config_timelock > 0 && fee_share != current_fee_share {
+        let change = ConfigChange {
+            referrer: Some(ReferrerChange {
+                code: code.clone(),
+                fee_share,
+            }),
+            ..ConfigChange::default()
+        };
+        let attributes = queue_config_change(deps.storage, &env, &ls_config, change)?;
+        res = res.add_attributes(attributes);
+        applied_fee_share = current_fee_share;
+    }
+    save_referrer(deps.storage, &code, address, applied_fee_share)?;
+
     Ok(res)
 }
+
+fn save_referrer(
+    storage: &mut dyn Storage,
+    code: &str,
+    address: Addr,
+    fee_share: Decimal,
+) -> StdResult<()> {
+    // re-registering keeps the accumulated stats of the code
+    let stake_count = REFERRERS
+        .may_load(storage, code)?
+        .map(|referrer| referrer.stake_count)
+        .unwrap_or_default();
+    REFERRERS.save(
+        storage,
+        code,
+        &Referrer {
+            address,
+            fee_share,
+            stake_count,
+        },
+    )
+}
+
+pub fn set_denom_config(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    denom: String,
+    mut config: Option<DenomConfig>,
+) -> Result<Response, ContractError> {
+    deps.api.debug("WASMDEBUG: set denom config");
+
+    let ls_config = LS_CONFIG.load(deps.storage)?;
+    assert_admin(deps.storage, &ls_config, &info.sender)?;
+
+    if let Some(config) = &mut config {
+        validate_denom_config(deps.as_ref(), config)?;
+    }
+    let mut res = Response::new()
+        .add_attribute("method", "set_denom_config")
+        .add_attribute("denom", denom.clone())
+        .add_attribute(
+            "provider",
+            config
+                .as_ref()
+                .map_or("default", |config| config.provider.name()),
+        );
+
+    // the rest of the config applies right away on the current provider and override
+    let current = DENOM_CONFIGS
+        .may_load(deps.storage, &denom)?
+        .unwrap_or_else(|| DenomConfig::default_for(&denom));
+    let new = config
+        .clone()
+        .unwrap_or_else(|| DenomConfig::default_for(&denom));
+    if ls_config.config_timelock > 0
+        && (new.provider != current.provider || new.ls_token_denom != current.ls_token_denom)
+    {
+        let change = ConfigChange {
+            denom_config: Some(DenomConfigChange {
+                denom: denom.clone(),
+                provider: new.provider,
+                ls_token_denom: new.ls_token_denom,
+            }),
+            ..ConfigChange::default()
+        };
+        let attributes = queue_config_change(deps.storage, &env, &ls_config, change)?;
+        config = Some(DenomConfig {
+            provider: current.provider,
+            ls_token_denom: current.ls_token_denom,
+            ..new
+        });
+        res = res.add_attributes(attributes);
+    }
+    let saved = save_denom_config(deps.storage, &denom, config)?;
+
+    Ok(res
+        .add_submessages(saved.messages)
+        .add_attributes(saved.attributes))
+}
+
+/// Save or remove the config of `denom`. Deposits queued for a denom that stops batching are
+/// refunded, nothing would dispatch them anymore.
+fn save_denom_config(
+    storage: &mut dyn Storage,
+    denom: &str,
+    config: Option<DenomConfig>,
+) -> Result<Response, ContractError> {
+    let batching = config.as_ref().is_some_and(|config| config.batch.is_some());
+    match config {
+        Some(config) => DENOM_CONFIGS.save(storage, denom, &config)?,
+        None => DENOM_CONFIGS.remove(storage, denom),
+    }
+    if batching {
+        return Ok(Response::new());
+    }
+    let Some(batch) = BATCHES.may_load(storage, denom)? else {
+        return Ok(Response::new());
+    };
+    BATCHES.remove(storage, denom);
+    remove_liability(
+        storage,
+        &Coin {
+            denom: denom.to_string(),
+            amount: batch.total,
+        },
+    )?;
+    let count = batch.deposits.len();
+    let res = refund_batch_deposits(storage, denom, batch.deposits)?;
+    Ok(res.add_attribute("refunded_deposits", count.to_string()))
+}
+
+fn validate_denom_config(deps: Deps, config: &mut DenomConfig) -> Result<(), ContractError> {
+    if let Some(ls_token_denom) = &config.ls_token_denom {
+        if !(MIN_DENOM_LENGTH..=MAX_DENOM_LENGTH).contains(&ls_token_denom.len())
+            || !is_denom_like(ls_token_denom)
+        {
+            return Err(ContractError::InvalidDenom {
+                denom: ls_token_denom.clone(),
+            });
+        }
+    }
+
+    if let (Some(min_amount), Some(max_amount)) = (config.min_amount, config.max_amount) {
+        ensure!(
+            min_amount <= max_amount,
+            ContractError::InvalidStakeLimits {}
+        );
+    }
+
+    if let Some(batch) = &config.batch {
+        ensure!(
+            (1..=MAX_BATCH_DEPOSITS).contains(&batch.max_deposits),
+            ContractError::InvalidBatchSize {
+                max: MAX_BATCH_DEPOSITS
+            }
+        );
+        if let Some(max_age) = batch.max_age {
+            ensure!(
+                (1..=MAX_BATCH_AGE).contains(&max_age),
+                ContractError::InvalidBatchAge { max: MAX_BATCH_AGE }
+            );
+        }
+    }
+
+    if let LsProvider::WasmHub {
+        contract,
+        ls_token_denom,
+        ..
+    } = &mut config.provider
+    {
+        *contract = deps.api.addr_validate(contract.as_str())?;
+        if ls_token_denom.is_empty() {
+            return Err(ContractError::InvalidDenom {
+                denom: ls_token_denom.clone(),
+            });
+        }
+    }
+    Ok(())
+}
+
+pub fn set_post_action(
+    deps: DepsMut,
+    _env: Env,
+    info: MessageInfo,
+    name: String,
+    action: Option<PostAction>,
+) -> Result<Response, ContractError> {
+    deps.api.debug("WASMDEBUG: set post action");
+
+    let ls_config = LS_CONFIG.load(deps.storage)?;
+    assert_admin(deps.storage, &ls_config, &info.sender)?;
+
+    let res = Response::new()
+        .add_attribute("method", "set_post_action")
+        .add_attribute("name", name.clone());
+
+    let Some(mut action) = action else {
+        POST_ACTIONS.remove(deps.storage, &name);
+        return Ok(res.add_attribute("vault", "none"));
+    };
+
+    match &mut action {
+        PostAction::DexterJoinPool { vault, .. } | PostAction::VaultDeposit { vault } => {
+            *vault = deps.api.addr_validate(vault.as_str())?;
+        }
+    }
+    POST_ACTIONS.save(deps.storage, &name, &action)?;
+
+    Ok(res.add_attribute("vault", action.vault().to_string()))
+}
+
+pub fn sweep(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    denoms: Vec<String>,
+    to: String,
+) -> Result<Response, ContractError> {
+    deps.api.debug("WASMDEBUG: sweep");
+
+    let ls_config = LS_CONFIG.load(deps.storage)?;
+    assert_admin(deps.storage, &ls_config, &info.sender)?;
+    let to = deps.api.addr_validate(&to)?;
+
+    let mut amount = vec![];
+    for denom in denoms.into_iter().collect::<BTreeSet<_>>() {
+        let balance = deps
+            .querier
+            .query_balance(env.contract.address.clone(), denom.clone())?;
+        let sweepable = balance
+            .amount
+            .saturating_sub(owed_amount(deps.storage, &denom)?);
+        if !sweepable.is_zero() {
+            amount.push(Coin {
+                denom,
+                amount: sweepable,
+            });
+        }
+    }
+    ensure!(!amount.is_empty(), ContractError::NothingToSweep {});
+
+    let swept = amount
+        .iter()
+        .map(Coin::to_string)
+        .collect::<Vec<_>>()
+        .join(",");
+    Ok(Response::new()
+        .add_message(BankMsg::Send {
+            to_address: to.to_string(),
+            amount,
+        })
+        .add_attribute("method", "sweep")
+        .add_attribute("to", to.to_string())
+        .add_attribute("amount", swept))
+}
+
+pub fn set_governance(
+    deps: DepsMut,
+    _env: Env,
+    info: MessageInfo,
+    contract: Option<String>,
+    proposal_required: bool,
+) -> Result<Response, ContractError> {
+    let ls_config = LS_CONFIG.load(deps.storage)?;
+    assert_admin(deps.storage, &ls_config, &info.sender)?;
+
+    let mut res = Response::new().add_attribute("method", "set_governance");
+    match contract {
+        Some(contract) => {
+            let contract = validate_contract(deps.as_ref(), &contract)?;
+
+            res = res
+                .add_attribute("contract", contract.to_string())
+                .add_attribute("proposal_required", proposal_required.to_string());
+            GOVERNANCE.save(
+                deps.storage,
+                &Governance {
+                    contract,
+                    proposal_required,
+                },
+            )?;
+        }
+        None => {
+            // the admin key must keep its powers without a governance contract
+            ensure!(!proposal_required, ContractError::GovernanceNotSet {});
+            GOVERNANCE.remove(deps.storage);
+
+            res = res.add_attribute("contract", "none");
+        }
+    }
+
+    Ok(res)
+}
+
+pub fn set_receipt_contract(
+    deps: DepsMut,
+    _env: Env,
+    info: MessageInfo,
+    contract: Option<String>,
+) -> Result<Response, ContractError> {
+    let ls_config = LS_CONFIG.load(deps.storage)?;
+    assert_admin(deps.storage, &ls_config, &info.sender)?;
+
+    let res = Response::new().add_attribute("method", "set_receipt_contract");
+    match contract {
+        Some(contract) => {
+            let contract = validate_contract(deps.as_ref(), &contract)?;
+            RECEIPT_CONTRACT.save(deps.storage, &contract)?;
+            Ok(res.add_attribute("contract", contract.to_string()))
+        }
+        None => {
+            RECEIPT_CONTRACT.remove(deps.storage);
+            Ok(res.add_attribute("contract", "none"))
+        }
+    }
+}
+
+/// Address of an instantiated contract, checked through the wasm module
+fn validate_contract(deps: Deps, address: &str) -> Result<Addr, ContractError> {
+    let contract = deps.api.addr_validate(address)?;
+    deps.querier
+        .query_wasm_contract_info(&contract)
+        .map_err(|_| ContractError::NotAContract {
+            address: contract.to_string(),
+        })?;
+    Ok(contract)
+}
+
+/// Privileged messages come from the admin or the governance contract, and only from the
+/// governance contract in proposal-required mode
+fn assert_admin(
+    storage: &dyn Storage,
+    ls_config: &LsConfig,
+    sender: &Addr,
+) -> Result<(), ContractError> {
+    match GOVERNANCE.may_load(storage)? {
+        Some(governance) if governance.contract == *sender => Ok(()),
+        Some(governance) if governance.proposal_required => Err(ContractError::Unauthorized {}),
+        _ if *sender == ls_config.admin => Ok(()),
+        _ => Err(ContractError::Unauthorized {}),
+    }
+}
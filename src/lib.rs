@@ -1,9 +1,16 @@
 pub mod contract;
+mod cw721;
+mod dexter;
 mod error;
 pub mod execute;
 pub mod msg;
+pub mod post_action;
+pub mod provider;
 pub mod query;
 pub mod reply;
+mod shares;
 pub mod state;
+#[cfg(any(test, feature = "testing"))]
+pub mod testing;
 
 pub use crate::error::ContractError;
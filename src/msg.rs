@@ -1,11 +1,26 @@
 use cosmwasm_schema::{cw_serde, QueryResponses};
-use cosmwasm_std::Addr;
+use cosmwasm_std::{Addr, Binary, Coin, Decimal, Timestamp, Uint128};
+use cw2::ContractVersion;
+use cw_utils::Expiration;
+
+use crate::state::LSInfo;
 
 #[cw_serde]
 pub struct InstantiateMsg {
     /// LS token prefix used to identify LS tokens
     /// e.g. "stk/"
     pub ls_prefix: String,
+    /// Share of the minted LS tokens kept as protocol fee, defaults to zero
+    pub protocol_fee: Option<Decimal>,
+    /// Receiver of the protocol fee, defaults to the admin
+    pub fee_recipient: Option<String>,
+    /// Number of stake records kept in the history, defaults to 10000
+    pub history_limit: Option<u64>,
+    /// Persistence DEX router used by `SwapAndLiquidStake`
+    pub swap_router: Option<String>,
+    /// Delay in seconds before changes to sensitive config fields can be executed,
+    /// defaults to zero which applies them instantly
+    pub config_timelock: Option<u64>,
 }
 
 #[cw_serde]
@@ -16,14 +31,219 @@ pub struct LsConfig {
     pub active: bool,
     /// LS token prefix
     pub ls_prefix: String,
+    /// Share of the minted LS tokens kept as protocol fee, recorded as the `fee` of each
+    /// stake in the history
+    pub protocol_fee: Decimal,
+    /// Receiver of the protocol fee
+    pub fee_recipient: Addr,
+    /// Number of stake records kept in the history
+    pub history_limit: u64,
+    /// Persistence DEX router used by `SwapAndLiquidStake`
+    pub swap_router: Option<Addr>,
+    /// Delay in seconds before changes to `ls_prefix`, fee parameters and shares, LS token
+    /// denom overrides, `swap_router` or the timelock itself can be executed
+    #[serde(default)]
+    pub config_timelock: u64,
+    /// Share of the protocol fee of a retried stake, a processed batch or a schedule run paid
+    /// to the keeper that called it
+    #[serde(default)]
+    pub keeper_fee_share: Decimal,
+    /// Number of keeper tips paid per block, further calls in the block go untipped
+    #[serde(default)]
+    pub keeper_tips_per_block: u32,
+}
+
+/// Sensitive config fields, applied through the timelock
+#[cw_serde]
+#[derive(Default)]
+pub struct ConfigChange {
+    pub ls_prefix: Option<String>,
+    pub protocol_fee: Option<Decimal>,
+    pub fee_recipient: Option<Addr>,
+    pub swap_router: Option<Addr>,
+    pub config_timelock: Option<u64>,
+    pub keeper_fee_share: Option<Decimal>,
+    /// New fee share of a registered referrer
+    pub referrer: Option<ReferrerChange>,
+    /// New provider or LS token denom override of a denom
+    pub denom_config: Option<DenomConfigChange>,
+    /// Dry run the resulting prefix when the change is applied
+    pub verify_ls_prefix: bool,
+}
+
+impl ConfigChange {
+    /// No field changes, `verify_ls_prefix` alone does not make a change
+    pub fn is_empty(&self) -> bool {
+        self.ls_prefix.is_none()
+            && self.protocol_fee.is_none()
+            && self.fee_recipient.is_none()
+            && self.swap_router.is_none()
+            && self.config_timelock.is_none()
+            && self.keeper_fee_share.is_none()
+            && self.referrer.is_none()
+            && self.denom_config.is_none()
+    }
+}
+
+#[cw_serde]
+pub struct ReferrerChange {
+    pub code: String,
+    pub fee_share: Decimal,
+}
+
+/// Provider and LS token denom override of a denom, merged into its current config
+#[cw_serde]
+pub struct DenomConfigChange {
+    pub denom: String,
+    pub provider: LsProvider,
+    pub ls_token_denom: Option<String>,
+}
+
+/// Config change waiting for the timelock to expire
+#[cw_serde]
+pub struct PendingChange {
+    pub id: u64,
+    pub change: ConfigChange,
+    pub executable_at: Timestamp,
+}
+
+/// Liquid staking provider a denom is routed to
+#[cw_serde]
+pub enum LsProvider {
+    /// pstake liquidstakeibc module, for IBC tokens of registered host chains
+    LiquidStakeIbc {},
+    /// pstake liquidstake module, for native XPRT
+    LiquidStake {},
+    /// CosmWasm LST hub contract, executed with the staked tokens as funds
+    WasmHub {
+        contract: Addr,
+        /// Execute message sent to the hub
+        execute_msg: Binary,
+        /// Native denom of the LS token minted by the hub
+        ls_token_denom: String,
+    },
+}
+
+/// Per denom liquid staking configuration
+#[cw_serde]
+pub struct DenomConfig {
+    pub provider: LsProvider,
+    /// LS token denom for providers not following the `{ls_prefix}{base_denom}` convention
+    pub ls_token_denom: Option<String>,
+    /// Smallest amount accepted per stake
+    pub min_amount: Option<Uint128>,
+    /// Largest amount accepted per stake
+    pub max_amount: Option<Uint128>,
+    /// Also enforce the minimum deposit of the liquidstakeibc host chain, ignored by other
+    /// providers
+    #[serde(default)]
+    pub enforce_module_minimum: bool,
+    /// Queue deposits and liquid stake them in one batch without receipts, deposits with a post
+    /// action and retries are staked directly
+    pub batch: Option<BatchConfig>,
+}
+
+/// Thresholds dispatching a batch when a deposit is queued, unset thresholds are ignored
+#[cw_serde]
+pub struct BatchConfig {
+    /// Total native amount of the batch
+    pub max_amount: Option<Uint128>,
+    /// Number of queued deposits, bounds the batch so it is dispatched and refunded in one
+    /// transaction
+    pub max_deposits: u32,
+    /// Seconds since the first deposit of the batch
+    pub max_age: Option<u64>,
+}
+
+impl BatchConfig {
+    /// Whether the batch reached one of the thresholds
+    pub fn is_due(&self, batch: &Batch, now: Timestamp) -> bool {
+        self.max_amount.is_some_and(|max| batch.total >= max)
+            || batch.deposits.len() >= self.max_deposits as usize
+            || self
+                .max_age
+                .is_some_and(|max| now.seconds() >= batch.opened_at.seconds().saturating_add(max))
+    }
+}
+
+/// Where the native tokens of a stake came from, a refund returns them there
+#[cw_serde]
+#[derive(Default)]
+pub enum StakeFunding {
+    /// Sent with the stake, refunded to the receiver
+    #[default]
+    Funds,
+    /// Debited from the deposit balance of `owner`, by `operator` under the approval
+    /// `approval_id` if an approved operator staked it, refunded to that balance
+    Deposit {
+        owner: Addr,
+        operator: Option<Addr>,
+        #[serde(default)]
+        approval_id: u64,
+    },
+}
+
+/// Deposit waiting in a batch
+#[cw_serde]
+pub struct BatchDeposit {
+    pub sender: Addr,
+    pub receiver: Addr,
+    pub amount: Uint128,
+    pub referral: Option<String>,
+    #[serde(default)]
+    pub funding: StakeFunding,
+}
+
+/// Deposits of a denom waiting to be liquid staked together
+#[cw_serde]
+pub struct Batch {
+    pub denom: String,
+    pub deposits: Vec<BatchDeposit>,
+    pub total: Uint128,
+    pub opened_at: Timestamp,
+}
+
+/// Action executed with the minted LS tokens on behalf of the receiver
+#[cw_serde]
+pub enum PostAction {
+    /// Join a Dexter pool, LP tokens are minted to the receiver
+    DexterJoinPool { vault: Addr, pool_id: Uint128 },
+    /// Deposit into a vault contract with the receiver as recipient
+    VaultDeposit { vault: Addr },
+}
+
+/// Swap through a single DEX pool
+#[cw_serde]
+pub struct SwapHop {
+    pub pool_id: Uint128,
+    /// Denom received from the pool
+    pub ask_denom: String,
 }
 
 #[cw_serde]
 pub enum ExecuteMsg {
     /// Liquid stake tokens
     LiquidStake {
-        /// Receiver of the liquid staked tokens on Persistence chain
+        /// Receiver of the liquid staked tokens on Persistence chain, also refunded if the
+        /// module rejects the deposit
+        receiver: Addr,
+        /// Referral code of a registered partner
+        referral: Option<String>,
+        /// Name of a configured post action run with the minted LS tokens
+        post_action: Option<String>,
+    },
+    /// Swap the sent tokens on the DEX router, then liquid stake the output
+    SwapAndLiquidStake {
+        /// Receiver of the liquid staked tokens on Persistence chain, also refunded if the swap fails
         receiver: Addr,
+        /// Pools to swap through, the last ask denom is liquid staked
+        route: Vec<SwapHop>,
+        /// Minimum amount received from the swap
+        min_out: Uint128,
+        /// Referral code of a registered partner
+        referral: Option<String>,
+        /// Name of a configured post action run with the minted LS tokens
+        post_action: Option<String>,
     },
     /// Update the contract configuration
     UpdateConfig {
@@ -31,7 +251,115 @@ pub enum ExecuteMsg {
         active: Option<bool>,
         /// LS token prefix
         ls_prefix: Option<String>,
+        /// Share of the minted LS tokens kept as protocol fee
+        protocol_fee: Option<Decimal>,
+        /// Receiver of the protocol fee
+        fee_recipient: Option<String>,
+        /// Number of stake records kept in the history
+        history_limit: Option<u64>,
+        /// Persistence DEX router used by `SwapAndLiquidStake`
+        swap_router: Option<String>,
+        /// Reject the update unless the LS token of every known denom has a bank supply
+        /// under the resulting prefix
+        verify_ls_prefix: Option<bool>,
+        /// Delay in seconds before changes to sensitive fields can be executed
+        config_timelock: Option<u64>,
+    },
+    /// Apply a pending config change once its timelock expired
+    ExecutePendingChange { id: u64 },
+    /// Drop a pending config change
+    CancelPendingChange { id: u64 },
+    /// Register or update a referral partner
+    RegisterReferrer {
+        /// Referral code passed by users on liquid stake
+        code: String,
+        /// Address receiving the referrer's share of the protocol fee
+        address: String,
+        /// Share of the protocol fee paid to the referrer, defaults to zero
+        fee_share: Option<Decimal>,
+    },
+    /// Set or remove (with `None`) the liquid staking configuration of a denom
+    SetDenomConfig {
+        denom: String,
+        config: Option<DenomConfig>,
+    },
+    /// Set or remove (with `None`) a named post action
+    SetPostAction {
+        name: String,
+        action: Option<PostAction>,
+    },
+    /// Set or remove (with `None`) a cw3 multisig or DAO contract sharing the admin powers
+    SetGovernance {
+        contract: Option<String>,
+        /// Only accept privileged messages from the governance contract, disabling the admin key
+        proposal_required: bool,
+    },
+    /// Set or remove (with `None`) the cw721 contract minting a receipt for every stake,
+    /// the contract must allow this contract to mint
+    SetReceiptContract { contract: Option<String> },
+    /// Dispatch a liquid stake that failed on a transient provider error again, callable by anyone
+    RetryStake { id: u64 },
+    /// Refund a failed liquid stake, callable by its sender or receiver. Sent funds go to the
+    /// receiver, a stake from a deposit balance is credited back to it
+    CancelStake { id: u64 },
+    /// Credit the sent IBC tokens to the sender's deposit balance
+    Deposit {},
+    /// Liquid stake from the sender's deposit balance
+    LiquidStakeFromBalance {
+        amount: Coin,
+        /// Receiver of the liquid staked tokens on Persistence chain
+        receiver: Addr,
+    },
+    /// Send tokens from the sender's deposit balance back to the sender
+    Withdraw { amount: Coin },
+    /// Liquid stake `amount_per_run` from the sender's deposit balance every `interval` seconds
+    CreateSchedule {
+        denom: String,
+        amount_per_run: Uint128,
+        interval: u64,
+        /// Receiver of the liquid staked tokens on Persistence chain
+        receiver: Addr,
+    },
+    /// Stop a schedule of the sender
+    CancelSchedule { id: u64 },
+    /// Run up to `limit` schedules that are due, callable by anyone
+    ExecuteDueSchedules { limit: Option<u32> },
+    /// Run one schedule for the keeper that called `ExecuteDueSchedules`, only callable by the
+    /// contract itself
+    RunSchedule { id: u64, keeper: Addr },
+    /// Allow `operator` to liquid stake from the sender's deposit balance
+    ApproveOperator {
+        operator: String,
+        /// Defaults to never
+        expires: Option<Expiration>,
+        /// Total the operator can stake, any denom without a limit when unset
+        max_amount: Option<Coin>,
+    },
+    /// Remove the approval of `operator`
+    RevokeOperator { operator: String },
+    /// Liquid stake from the deposit balance of `owner`, callable by its approved operators
+    LiquidStakeFrom {
+        owner: String,
+        coin: Coin,
+        /// Receiver of the liquid staked tokens on Persistence chain
+        receiver: Addr,
     },
+    /// Liquid stake the queued deposits of a batched denom, callable by anyone
+    ProcessBatch { denom: String },
+    /// Take the sender's deposits out of the batch of `denom`, refunded like a cancelled stake
+    WithdrawQueued { denom: String },
+    /// Set the keeper tip paid from the protocol fee for successful `RetryStake`,
+    /// `ProcessBatch` and `ExecuteDueSchedules` calls
+    SetKeeperTip {
+        /// Share of the protocol fee, zero disables tips
+        fee_share: Decimal,
+        /// Number of tips paid per block
+        tips_per_block: u32,
+    },
+    /// Send the accrued keeper tips to the sender
+    ClaimKeeperRewards {},
+    /// Send the balances of `denoms` that are not owed to users to `to`
+    Sweep { denoms: Vec<String>, to: String },
 }
 
 #[cw_serde]
@@ -39,4 +367,258 @@ pub enum ExecuteMsg {
 pub enum QueryMsg {
     #[returns(LsConfig)]
     LsConfig {},
+    /// Completed stakes where the address is either the sender or the receiver
+    #[returns(StakeHistoryResponse)]
+    StakeHistory {
+        address: String,
+        start_after: Option<u64>,
+        limit: Option<u32>,
+    },
+    #[returns(StakeRecord)]
+    Stake { id: u64 },
+    #[returns(ReferrerStatsResponse)]
+    ReferrerStats { code: String },
+    /// Configuration used for the denom, including the default one
+    #[returns(DenomConfig)]
+    DenomConfig { denom: String },
+    #[returns(PostAction)]
+    PostAction { name: String },
+    /// Config with runtime state: version, stake in flight, balances and counters
+    #[returns(StatusResponse)]
+    Status {},
+    /// Balances held by the contract that are not owed to users
+    #[returns(SweepableBalancesResponse)]
+    SweepableBalances {},
+    #[returns(GovernanceInfoResponse)]
+    GovernanceInfo {},
+    /// Receipt of a stake, checked against the token minted on the receipt contract
+    #[returns(ReceiptResponse)]
+    Receipt { id: u64 },
+    /// Config changes waiting for the timelock, oldest first
+    #[returns(PendingChangesResponse)]
+    PendingChanges {},
+    /// Prepaid balances of an address
+    #[returns(DepositBalanceResponse)]
+    DepositBalance { address: String },
+    /// Liquid staking schedules of the owner
+    #[returns(SchedulesResponse)]
+    Schedules { owner: String },
+    /// Operators approved by the owner
+    #[returns(OperatorsResponse)]
+    Operators { owner: String },
+    /// Deposits queued for the next batch of the denom
+    #[returns(Option<Batch>)]
+    Batch { denom: String },
+    /// Unclaimed tips of a keeper
+    #[returns(KeeperRewardsResponse)]
+    KeeperRewards { address: String },
+    /// Liquid stakes waiting for a retry, oldest first
+    #[returns(FailedStakesResponse)]
+    FailedStakes {
+        start_after: Option<u64>,
+        limit: Option<u32>,
+    },
+}
+
+/// Record of a completed liquid stake
+#[cw_serde]
+pub struct StakeRecord {
+    pub id: u64,
+    pub sender: Addr,
+    pub receiver: Addr,
+    pub native_denom: String,
+    pub native_amount: Uint128,
+    pub ls_token_denom: String,
+    /// LS tokens delivered to the receiver
+    pub ls_token_amount: Uint128,
+    /// LS tokens kept as protocol fee
+    pub fee: Uint128,
+    pub referral: Option<String>,
+    /// Post action the LS tokens were sent to instead of the receiver
+    pub post_action: Option<String>,
+    pub block_height: u64,
+    pub block_time: Timestamp,
+}
+
+#[cw_serde]
+pub struct StakeHistoryResponse {
+    pub stakes: Vec<StakeRecord>,
+}
+
+#[cw_serde]
+pub struct ReferrerStatsResponse {
+    pub code: String,
+    pub address: Addr,
+    pub fee_share: Decimal,
+    /// Number of stakes attributed to the referrer
+    pub stake_count: u64,
+    /// Native tokens staked with the referral code
+    pub volume: Vec<Coin>,
+    /// LS tokens paid to the referrer as fee share
+    pub fees_paid: Vec<Coin>,
+}
+
+#[cw_serde]
+pub struct StatusResponse {
+    pub config: LsConfig,
+    /// Unset for contracts instantiated before versions were recorded
+    pub contract_version: Option<ContractVersion>,
+    /// Interim state of a liquid stake waiting for its reply, should always be empty
+    pub stake_in_flight: Option<LSInfo>,
+    /// Number of completed stakes
+    pub stake_count: u64,
+    /// Denoms with a configuration or stakes, and the bonded denom
+    pub denoms: Vec<DenomStatus>,
+}
+
+/// Deposit receipt, minted as the cw721 token extension with the stake id as token id
+#[cw_serde]
+pub struct Receipt {
+    pub stake_id: u64,
+    pub sender: Addr,
+    pub receiver: Addr,
+    pub native_denom: String,
+    pub native_amount: Uint128,
+    pub ls_token_denom: String,
+    /// LS tokens delivered to the receiver
+    pub ls_token_amount: Uint128,
+    /// LS tokens kept as protocol fee
+    pub fee: Uint128,
+    pub block_height: u64,
+    pub block_time: Timestamp,
+}
+
+impl From<StakeRecord> for Receipt {
+    fn from(record: StakeRecord) -> Self {
+        Receipt {
+            stake_id: record.id,
+            sender: record.sender,
+            receiver: record.receiver,
+            native_denom: record.native_denom,
+            native_amount: record.native_amount,
+            ls_token_denom: record.ls_token_denom,
+            ls_token_amount: record.ls_token_amount,
+            fee: record.fee,
+            block_height: record.block_height,
+            block_time: record.block_time,
+        }
+    }
+}
+
+#[cw_serde]
+pub struct ReceiptResponse {
+    pub receipt: Receipt,
+    pub nft_contract: Addr,
+    /// Current owner of the receipt token, unset when the token no longer exists
+    pub owner: Option<String>,
+    /// The token exists and carries this receipt as its extension
+    pub verified: bool,
+}
+
+#[cw_serde]
+pub struct GovernanceInfoResponse {
+    pub admin: Addr,
+    pub contract: Option<Addr>,
+    /// Privileged messages are only accepted from `contract`
+    pub proposal_required: bool,
+}
+
+#[cw_serde]
+pub struct PendingChangesResponse {
+    pub changes: Vec<PendingChange>,
+}
+
+/// Deposit held by the contract after the provider failed on a transient error
+#[cw_serde]
+pub struct FailedStake {
+    pub id: u64,
+    pub sender: Addr,
+    pub receiver: Addr,
+    pub coin: Coin,
+    pub referral: Option<String>,
+    pub post_action: Option<String>,
+    #[serde(default)]
+    pub funding: StakeFunding,
+    /// Decoded provider error of the last attempt
+    pub error: String,
+    pub failed_at: Timestamp,
+}
+
+#[cw_serde]
+pub struct FailedStakesResponse {
+    pub stakes: Vec<FailedStake>,
+}
+
+#[cw_serde]
+pub struct DepositBalanceResponse {
+    pub address: Addr,
+    pub balances: Vec<Coin>,
+}
+
+/// Recurring liquid stake funded from the owner's deposit balance
+#[cw_serde]
+pub struct Schedule {
+    pub id: u64,
+    pub owner: Addr,
+    pub denom: String,
+    pub amount_per_run: Uint128,
+    /// Seconds between runs
+    pub interval: u64,
+    pub receiver: Addr,
+    /// The schedule is due from this time on
+    pub next_run: Timestamp,
+}
+
+#[cw_serde]
+pub struct SchedulesResponse {
+    pub owner: Addr,
+    pub schedules: Vec<Schedule>,
+}
+
+/// Approval of an operator to liquid stake from the owner's deposit balance
+#[cw_serde]
+pub struct Operator {
+    pub operator: Addr,
+    pub expires: Expiration,
+    pub max_amount: Option<Coin>,
+    /// Amount staked by the operator so far, counted against `max_amount`
+    pub spent: Uint128,
+    /// Changes with every approval, a refund only gives back `spent` of the approval it was
+    /// staked under
+    #[serde(default)]
+    pub approval_id: u64,
+}
+
+#[cw_serde]
+pub struct OperatorsResponse {
+    pub owner: Addr,
+    pub operators: Vec<Operator>,
+}
+
+#[cw_serde]
+pub struct KeeperRewardsResponse {
+    pub address: Addr,
+    pub rewards: Vec<Coin>,
+}
+
+#[cw_serde]
+pub struct SweepableBalancesResponse {
+    pub balances: Vec<Coin>,
+}
+
+#[cw_serde]
+pub struct DenomStatus {
+    pub denom: String,
+    pub provider: String,
+    /// Native tokens held by the contract
+    pub native_balance: Uint128,
+    /// Unknown for IBC denoms until their first stake resolves the denom trace
+    pub ls_token_denom: Option<String>,
+    /// LS tokens held by the contract
+    pub ls_token_balance: Option<Uint128>,
+    pub stake_count: u64,
+    /// Native tokens staked
+    pub native_volume: Uint128,
+    /// LS tokens minted, including the protocol fee
+    pub ls_token_minted: Uint128,
 }
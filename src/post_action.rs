@@ -0,0 +1,50 @@
+use cosmwasm_schema::cw_serde;
+use cosmwasm_std::{to_json_binary, Addr, Coin, CosmosMsg, StdResult, WasmMsg};
+
+use crate::{
+    dexter::{Asset, AssetInfo, VaultExecuteMsg},
+    msg::PostAction,
+};
+
+/// Deposit message accepted by vault contracts
+#[cw_serde]
+enum VaultMsg {
+    Deposit { recipient: String },
+}
+
+impl PostAction {
+    pub fn vault(&self) -> &Addr {
+        match self {
+            PostAction::DexterJoinPool { vault, .. } | PostAction::VaultDeposit { vault } => vault,
+        }
+    }
+
+    /// Message depositing `ls_token` on behalf of `receiver`
+    pub fn execute_msg(&self, receiver: &Addr, ls_token: Coin) -> StdResult<CosmosMsg> {
+        let msg = match self {
+            PostAction::DexterJoinPool { pool_id, .. } => {
+                to_json_binary(&VaultExecuteMsg::JoinPool {
+                    pool_id: *pool_id,
+                    recipient: Some(receiver.to_string()),
+                    assets: Some(vec![Asset {
+                        info: AssetInfo::NativeToken {
+                            denom: ls_token.denom.clone(),
+                        },
+                        amount: ls_token.amount,
+                    }]),
+                    min_lp_to_receive: None,
+                    auto_stake: None,
+                })?
+            }
+            PostAction::VaultDeposit { .. } => to_json_binary(&VaultMsg::Deposit {
+                recipient: receiver.to_string(),
+            })?,
+        };
+
+        Ok(CosmosMsg::Wasm(WasmMsg::Execute {
+            contract_addr: self.vault().to_string(),
+            msg,
+            funds: vec![ls_token],
+        }))
+    }
+}
@@ -0,0 +1,108 @@
+use cosmwasm_std::{Addr, Coin, CosmosMsg, WasmMsg};
+use persistence_std::types::{
+    cosmos::base::v1beta1::Coin as StdCoin,
+    pstake::{liquidstake, liquidstakeibc},
+};
+
+use crate::msg::{DenomConfig, LsProvider};
+
+pub const LIQUID_STAKE_IBC_TYPE_URL: &str = "/pstake.liquidstakeibc.v1beta1.MsgLiquidStake";
+pub const LIQUID_STAKE_TYPE_URL: &str = "/pstake.liquidstake.v1beta1.MsgLiquidStake";
+const IBC_DENOM_PREFIX: &str = "ibc/";
+
+impl DenomConfig {
+    /// Configuration of denoms without an explicit one: IBC denoms are routed to
+    /// liquidstakeibc, native denoms to liquidstake
+    pub fn default_for(denom: &str) -> Self {
+        let provider = if is_ibc_denom(denom) {
+            LsProvider::LiquidStakeIbc {}
+        } else {
+            LsProvider::LiquidStake {}
+        };
+        DenomConfig {
+            provider,
+            ls_token_denom: None,
+            min_amount: None,
+            max_amount: None,
+            enforce_module_minimum: false,
+            batch: None,
+        }
+    }
+
+    /// Denom of the LS token minted for `base_denom`, the explicit one if set
+    pub fn ls_token_denom(&self, ls_prefix: &str, base_denom: &str) -> String {
+        match &self.ls_token_denom {
+            Some(ls_token_denom) => ls_token_denom.clone(),
+            None => self.provider.ls_token_denom(ls_prefix, base_denom),
+        }
+    }
+
+    /// Whether the LS token denom is derived from the prefix
+    pub fn uses_ls_prefix(&self) -> bool {
+        self.ls_token_denom.is_none() && !matches!(self.provider, LsProvider::WasmHub { .. })
+    }
+}
+
+pub fn is_ibc_denom(denom: &str) -> bool {
+    denom.starts_with(IBC_DENOM_PREFIX)
+}
+
+impl LsProvider {
+    pub fn name(&self) -> &'static str {
+        match self {
+            LsProvider::LiquidStakeIbc {} => "liquidstakeibc",
+            LsProvider::LiquidStake {} => "liquidstake",
+            LsProvider::WasmHub { .. } => "wasm_hub",
+        }
+    }
+
+    /// Only liquidstakeibc needs the denom trace, the others stake the denom as is
+    pub fn requires_denom_trace(&self) -> bool {
+        matches!(self, LsProvider::LiquidStakeIbc {})
+    }
+
+    /// Denom of the LS token minted for `base_denom`
+    pub fn ls_token_denom(&self, ls_prefix: &str, base_denom: &str) -> String {
+        match self {
+            LsProvider::LiquidStakeIbc {} | LsProvider::LiquidStake {} => {
+                format!("{ls_prefix}{base_denom}")
+            }
+            LsProvider::WasmHub { ls_token_denom, .. } => ls_token_denom.clone(),
+        }
+    }
+
+    /// Message liquid staking `coin` held by `delegator`
+    pub fn liquid_stake_msg(&self, delegator: &Addr, coin: Coin) -> CosmosMsg {
+        let amount = Some(StdCoin {
+            denom: coin.denom.clone(),
+            amount: coin.amount.to_string(),
+        });
+        match self {
+            LsProvider::LiquidStakeIbc {} => CosmosMsg::Stargate {
+                type_url: LIQUID_STAKE_IBC_TYPE_URL.to_string(),
+                value: liquidstakeibc::v1beta1::MsgLiquidStake {
+                    amount,
+                    delegator_address: delegator.to_string(),
+                }
+                .into(),
+            },
+            LsProvider::LiquidStake {} => CosmosMsg::Stargate {
+                type_url: LIQUID_STAKE_TYPE_URL.to_string(),
+                value: liquidstake::v1beta1::MsgLiquidStake {
+                    delegator_address: delegator.to_string(),
+                    amount,
+                }
+                .into(),
+            },
+            LsProvider::WasmHub {
+                contract,
+                execute_msg,
+                ..
+            } => CosmosMsg::Wasm(WasmMsg::Execute {
+                contract_addr: contract.to_string(),
+                msg: execute_msg.clone(),
+                funds: vec![coin],
+            }),
+        }
+    }
+}
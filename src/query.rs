@@ -1,6 +1,302 @@
-use cosmwasm_std::{Deps, StdResult};
+use std::collections::BTreeSet;
+
+use cosmwasm_std::{Coin, Deps, Env, Order, StdResult};
+use cw2::CONTRACT;
+use cw_storage_plus::Bound;
+
+use crate::{
+    cw721::{Cw721QueryMsg, NftInfoResponse, OwnerOfResponse},
+    execute::query_base_denom,
+    msg::{
+        Batch, DenomConfig, DenomStatus, DepositBalanceResponse, FailedStakesResponse,
+        GovernanceInfoResponse, KeeperRewardsResponse, OperatorsResponse, PendingChangesResponse,
+        PostAction, Receipt, ReceiptResponse, ReferrerStatsResponse, SchedulesResponse,
+        StakeHistoryResponse, StakeRecord, StatusResponse, SweepableBalancesResponse,
+    },
+    state::{
+        owed_amount, schedules, stakes, ReceiptRecord, BATCHES, CURRENT_TX, DENOM_CONFIGS,
+        DENOM_STATS, DEPOSITS, FAILED_STAKES, GOVERNANCE, KEEPER_REWARDS, LS_CONFIG, OPERATORS,
+        PENDING_CHANGES, POST_ACTIONS, RECEIPTS, REFERRAL_FEES, REFERRAL_VOLUME, REFERRERS,
+        STAKE_SEQ,
+    },
+};
+
+const DEFAULT_LIMIT: u32 = 10;
+const MAX_LIMIT: u32 = 30;
 
 pub fn query_ls_config(deps: Deps) -> StdResult<crate::msg::LsConfig> {
     let ls_config = crate::state::LS_CONFIG.load(deps.storage)?;
     Ok(ls_config)
 }
+
+pub fn query_stake_history(
+    deps: Deps,
+    address: String,
+    start_after: Option<u64>,
+    limit: Option<u32>,
+) -> StdResult<StakeHistoryResponse> {
+    let address = deps.api.addr_validate(&address)?;
+    let limit = limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT) as usize;
+
+    // collect a page from each index, then merge them in id order
+    let mut records = vec![];
+    for index in [&stakes().idx.receiver, &stakes().idx.sender] {
+        let page = index
+            .prefix(address.clone())
+            .range(
+                deps.storage,
+                start_after.map(Bound::exclusive),
+                None,
+                Order::Ascending,
+            )
+            .take(limit)
+            .map(|item| item.map(|(_, record)| record))
+            .collect::<StdResult<Vec<_>>>()?;
+        records.extend(page);
+    }
+    records.sort_by_key(|record| record.id);
+    records.dedup_by_key(|record| record.id);
+    records.truncate(limit);
+
+    Ok(StakeHistoryResponse { stakes: records })
+}
+
+pub fn query_stake(deps: Deps, id: u64) -> StdResult<StakeRecord> {
+    stakes().load(deps.storage, id)
+}
+
+pub fn query_referrer_stats(deps: Deps, code: String) -> StdResult<ReferrerStatsResponse> {
+    let referrer = REFERRERS.load(deps.storage, &code)?;
+
+    let coins = |map: &cw_storage_plus::Map<(&str, &str), _>| {
+        map.prefix(&code)
+            .range(deps.storage, None, None, Order::Ascending)
+            .map(|item| item.map(|(denom, amount)| Coin { denom, amount }))
+            .collect::<StdResult<Vec<_>>>()
+    };
+
+    Ok(ReferrerStatsResponse {
+        volume: coins(&REFERRAL_VOLUME)?,
+        fees_paid: coins(&REFERRAL_FEES)?,
+        code,
+        address: referrer.address,
+        fee_share: referrer.fee_share,
+        stake_count: referrer.stake_count,
+    })
+}
+
+pub fn query_denom_config(deps: Deps, denom: String) -> StdResult<DenomConfig> {
+    Ok(DENOM_CONFIGS
+        .may_load(deps.storage, &denom)?
+        .unwrap_or_else(|| DenomConfig::default_for(&denom)))
+}
+
+pub fn query_post_action(deps: Deps, name: String) -> StdResult<PostAction> {
+    POST_ACTIONS.load(deps.storage, &name)
+}
+
+pub fn query_governance_info(deps: Deps) -> StdResult<GovernanceInfoResponse> {
+    let ls_config = LS_CONFIG.load(deps.storage)?;
+    let governance = GOVERNANCE.may_load(deps.storage)?;
+    Ok(GovernanceInfoResponse {
+        admin: ls_config.admin,
+        proposal_required: governance
+            .as_ref()
+            .is_some_and(|governance| governance.proposal_required),
+        contract: governance.map(|governance| governance.contract),
+    })
+}
+
+pub fn query_receipt(deps: Deps, id: u64) -> StdResult<ReceiptResponse> {
+    let ReceiptRecord {
+        nft_contract,
+        receipt,
+    } = RECEIPTS.load(deps.storage, id)?;
+
+    // a burned or never minted token fails both queries
+    let token_id = id.to_string();
+    let owner = deps
+        .querier
+        .query_wasm_smart::<OwnerOfResponse>(
+            &nft_contract,
+            &Cw721QueryMsg::OwnerOf {
+                token_id: token_id.clone(),
+                include_expired: None,
+            },
+        )
+        .ok()
+        .map(|res| res.owner);
+    let verified = deps
+        .querier
+        .query_wasm_smart::<NftInfoResponse<Receipt>>(
+            &nft_contract,
+            &Cw721QueryMsg::NftInfo { token_id },
+        )
+        .is_ok_and(|info| info.extension == receipt);
+
+    Ok(ReceiptResponse {
+        receipt,
+        nft_contract,
+        owner,
+        verified,
+    })
+}
+
+pub fn query_pending_changes(deps: Deps) -> StdResult<PendingChangesResponse> {
+    let changes = PENDING_CHANGES
+        .range(deps.storage, None, None, Order::Ascending)
+        .map(|item| item.map(|(_, change)| change))
+        .collect::<StdResult<_>>()?;
+    Ok(PendingChangesResponse { changes })
+}
+
+pub fn query_deposit_balance(deps: Deps, address: String) -> StdResult<DepositBalanceResponse> {
+    let address = deps.api.addr_validate(&address)?;
+    let balances = DEPOSITS
+        .prefix(&address)
+        .range(deps.storage, None, None, Order::Ascending)
+        .map(|item| item.map(|(denom, amount)| Coin { denom, amount }))
+        .collect::<StdResult<_>>()?;
+    Ok(DepositBalanceResponse { address, balances })
+}
+
+pub fn query_schedules(deps: Deps, owner: String) -> StdResult<SchedulesResponse> {
+    let owner = deps.api.addr_validate(&owner)?;
+    let schedules = schedules()
+        .idx
+        .owner
+        .prefix(owner.clone())
+        .range(deps.storage, None, None, Order::Ascending)
+        .map(|item| item.map(|(_, schedule)| schedule))
+        .collect::<StdResult<_>>()?;
+    Ok(SchedulesResponse { owner, schedules })
+}
+
+pub fn query_operators(deps: Deps, owner: String) -> StdResult<OperatorsResponse> {
+    let owner = deps.api.addr_validate(&owner)?;
+    let operators = OPERATORS
+        .prefix(&owner)
+        .range(deps.storage, None, None, Order::Ascending)
+        .map(|item| item.map(|(_, operator)| operator))
+        .collect::<StdResult<_>>()?;
+    Ok(OperatorsResponse { owner, operators })
+}
+
+pub fn query_batch(deps: Deps, denom: String) -> StdResult<Option<Batch>> {
+    BATCHES.may_load(deps.storage, &denom)
+}
+
+pub fn query_keeper_rewards(deps: Deps, address: String) -> StdResult<KeeperRewardsResponse> {
+    let address = deps.api.addr_validate(&address)?;
+    let rewards = KEEPER_REWARDS
+        .prefix(&address)
+        .range(deps.storage, None, None, Order::Ascending)
+        .map(|item| item.map(|(denom, amount)| Coin { denom, amount }))
+        .collect::<StdResult<_>>()?;
+    Ok(KeeperRewardsResponse { address, rewards })
+}
+
+pub fn query_failed_stakes(
+    deps: Deps,
+    start_after: Option<u64>,
+    limit: Option<u32>,
+) -> StdResult<FailedStakesResponse> {
+    let limit = limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT) as usize;
+    let stakes = FAILED_STAKES
+        .range(
+            deps.storage,
+            start_after.map(Bound::exclusive),
+            None,
+            Order::Ascending,
+        )
+        .take(limit)
+        .map(|item| item.map(|(_, stake)| stake))
+        .collect::<StdResult<_>>()?;
+    Ok(FailedStakesResponse { stakes })
+}
+
+pub fn query_sweepable_balances(deps: Deps, env: Env) -> StdResult<SweepableBalancesResponse> {
+    let mut balances = vec![];
+    for balance in deps.querier.query_all_balances(env.contract.address)? {
+        let sweepable = balance
+            .amount
+            .saturating_sub(owed_amount(deps.storage, &balance.denom)?);
+        if !sweepable.is_zero() {
+            balances.push(Coin {
+                denom: balance.denom,
+                amount: sweepable,
+            });
+        }
+    }
+
+    Ok(SweepableBalancesResponse { balances })
+}
+
+pub fn query_status(deps: Deps, env: Env) -> StdResult<StatusResponse> {
+    let config = LS_CONFIG.load(deps.storage)?;
+
+    let mut denoms = BTreeSet::from([deps.querier.query_bonded_denom()?]);
+    denoms.extend(
+        DENOM_CONFIGS
+            .keys(deps.storage, None, None, Order::Ascending)
+            .collect::<StdResult<Vec<_>>>()?,
+    );
+    denoms.extend(
+        DENOM_STATS
+            .keys(deps.storage, None, None, Order::Ascending)
+            .collect::<StdResult<Vec<_>>>()?,
+    );
+
+    let denoms = denoms
+        .into_iter()
+        .map(|denom| {
+            let denom_config = query_denom_config(deps, denom.clone())?;
+            let stats = DENOM_STATS
+                .may_load(deps.storage, &denom)?
+                .unwrap_or_default();
+            // IBC denoms are resolved to their base denom through the denom trace
+            let ls_token_denom = if denom_config.ls_token_denom.is_some() {
+                Some(denom_config.ls_token_denom(&config.ls_prefix, &denom))
+            } else {
+                match query_base_denom(deps, &denom_config.provider, &denom) {
+                    Ok(base_denom) => {
+                        Some(denom_config.ls_token_denom(&config.ls_prefix, &base_denom))
+                    }
+                    // no trace on this chain, fall back to the LS token of past stakes
+                    Err(_) if stats.stake_count > 0 => Some(stats.ls_token_denom),
+                    Err(_) => None,
+                }
+            };
+            let ls_token_balance = ls_token_denom
+                .as_ref()
+                .map(|ls_token_denom| {
+                    deps.querier
+                        .query_balance(&env.contract.address, ls_token_denom)
+                        .map(|balance| balance.amount)
+                })
+                .transpose()?;
+
+            Ok(DenomStatus {
+                native_balance: deps
+                    .querier
+                    .query_balance(&env.contract.address, &denom)?
+                    .amount,
+                provider: denom_config.provider.name().to_string(),
+                denom,
+                ls_token_denom,
+                ls_token_balance,
+                stake_count: stats.stake_count,
+                native_volume: stats.native_volume,
+                ls_token_minted: stats.ls_token_minted,
+            })
+        })
+        .collect::<StdResult<Vec<_>>>()?;
+
+    Ok(StatusResponse {
+        config,
+        contract_version: CONTRACT.may_load(deps.storage)?,
+        stake_in_flight: CURRENT_TX.may_load(deps.storage)?,
+        stake_count: STAKE_SEQ.may_load(deps.storage)?.unwrap_or_default(),
+        denoms,
+    })
+}
@@ -1,18 +1,68 @@
-use cosmwasm_std::{ensure, BankMsg, Coin, CosmosMsg, DepsMut, Env, Reply, Response};
+use std::collections::BTreeMap;
 
-use crate::{state::CURRENT_TX, ContractError};
+use cosmwasm_std::{
+    coin, coins, ensure, to_json_binary, Addr, BankMsg, Coin, CosmosMsg, DepsMut, Env, Reply,
+    Response, StdError, StdResult, Storage, SubMsg, SubMsgResult, Uint128, WasmMsg,
+};
+
+use crate::{
+    contract::{POST_ACTION_REPLY_ID, RECEIPT_REPLY_ID},
+    cw721::Cw721ExecuteMsg,
+    error::SubcallAction,
+    execute::{refund_batch_deposits, refund_stake, try_liquid_staking},
+    msg::{FailedStake, LsConfig, Receipt, StakeFunding, StakeRecord},
+    shares::allocate_pro_rata,
+    state::{
+        add_liability, save_stake_record, KeeperTips, LSInfo, PostActionInfo, ReceiptRecord,
+        BATCHES, CURRENT_BATCH, CURRENT_POST_ACTION, CURRENT_RECEIPT, CURRENT_RETRY, CURRENT_SWAP,
+        CURRENT_TX, DENOM_STATS, FAILED_STAKES, FAILED_STAKE_SEQ, KEEPER_REWARDS, KEEPER_TIPS,
+        LS_CONFIG, POST_ACTIONS, RECEIPTS, RECEIPT_CONTRACT, REFERRAL_FEES, REFERRAL_VOLUME,
+        REFERRERS,
+    },
+    ContractError,
+};
 
 pub fn handle_ls_reply(deps: DepsMut, env: Env, msg: Reply) -> Result<Response, ContractError> {
     deps.api
         .debug(format!("WASMDEBUG: ls reply msg: {msg:?}").as_str());
 
-    ensure!(
-        msg.result.is_ok(),
-        ContractError::SubcallError(msg.result.unwrap_err().to_string())
-    );
-
     // load interim state
     let current_tx = CURRENT_TX.load(deps.storage)?;
+    CURRENT_TX.remove(deps.storage);
+    let retry = CURRENT_RETRY.may_load(deps.storage)?;
+    CURRENT_RETRY.remove(deps.storage);
+
+    if let SubMsgResult::Err(err) = msg.result {
+        let err = ContractError::from_subcall_error(err);
+        let refund = Coin {
+            denom: current_tx.native_denom.clone(),
+            amount: current_tx.native_amount,
+        };
+        match err.subcall_action() {
+            SubcallAction::Refund => {}
+            // a failed retry reverts, which keeps the stake parked under its id
+            SubcallAction::Retry if retry.is_none() => {
+                return park_failed_stake(deps, env, current_tx, refund, err)
+            }
+            _ => return Err(err),
+        }
+        let res = Response::default()
+            .add_attribute("method", "handle_ls_reply")
+            .add_attribute("status", "refunded")
+            .add_attribute("refund", refund.to_string());
+        let refund = refund_stake(
+            deps.storage,
+            &current_tx.funding,
+            &current_tx.receiver,
+            refund,
+        )?;
+        return Ok(res
+            .add_submessages(refund.messages)
+            .add_attributes(refund.attributes)
+            .add_attribute("error", err.to_string()));
+    }
+
+    let config = LS_CONFIG.load(deps.storage)?;
 
     // get contract balance of ls asset
     let current_ls_token_balance = deps.querier.query_balance(
@@ -20,19 +70,548 @@ pub fn handle_ls_reply(deps: DepsMut, env: Env, msg: Reply) -> Result<Response,
         current_tx.ls_token_denom.clone(),
     )?;
 
-    let balance_diff = current_ls_token_balance.amount - current_tx.prev_ls_token_balance;
+    // the balance only grows during the stake, a decrease is an error rather than an overflow
+    let balance_diff = current_ls_token_balance
+        .amount
+        .checked_sub(current_tx.prev_ls_token_balance)
+        .map_err(StdError::from)?;
 
-    let res = Response::default()
-        .add_message(CosmosMsg::Bank(BankMsg::Send {
+    // protocol fee is kept from the minted amount, rounding in favour of the receiver
+    let fee = balance_diff * config.protocol_fee;
+    let receiver_amount = balance_diff - fee;
+
+    let referrer_payout = match &current_tx.referral {
+        Some(code) => record_referral(
+            deps.storage,
+            code,
+            &current_tx.native_denom,
+            current_tx.native_amount,
+            &current_tx.ls_token_denom,
+            fee,
+        )?,
+        None => None,
+    };
+
+    DENOM_STATS.update(
+        deps.storage,
+        &current_tx.native_denom,
+        |stats| -> StdResult<_> {
+            let mut stats = stats.unwrap_or_default();
+            stats.stake_count += 1;
+            stats.native_volume += current_tx.native_amount;
+            stats.ls_token_denom = current_tx.ls_token_denom.clone();
+            stats.ls_token_minted += balance_diff;
+            Ok(stats)
+        },
+    )?;
+
+    let record = StakeRecord {
+        id: 0,
+        sender: current_tx.sender,
+        receiver: current_tx.receiver.clone(),
+        native_denom: current_tx.native_denom,
+        native_amount: current_tx.native_amount,
+        ls_token_denom: current_tx.ls_token_denom.clone(),
+        ls_token_amount: receiver_amount,
+        fee,
+        referral: current_tx.referral.clone(),
+        post_action: current_tx.post_action.clone(),
+        block_height: env.block.height,
+        block_time: env.block.time,
+    };
+    let stake_id = save_stake_record(deps.storage, record.clone(), config.history_limit)?;
+
+    let receiver_coin = Coin {
+        denom: current_tx.ls_token_denom.clone(),
+        amount: receiver_amount,
+    };
+    let mut res = match &current_tx.post_action {
+        // post action gets the receiver's tokens, the reply falls back to a plain transfer
+        Some(name) => {
+            let action = POST_ACTIONS.load(deps.storage, name)?;
+            CURRENT_POST_ACTION.save(
+                deps.storage,
+                &PostActionInfo {
+                    name: name.clone(),
+                    receiver: current_tx.receiver.clone(),
+                    ls_token_denom: receiver_coin.denom.clone(),
+                    ls_token_amount: receiver_coin.amount,
+                },
+            )?;
+            Response::default().add_submessage(SubMsg::reply_always(
+                action.execute_msg(&current_tx.receiver, receiver_coin)?,
+                POST_ACTION_REPLY_ID,
+            ))
+        }
+        None => Response::default().add_message(CosmosMsg::Bank(BankMsg::Send {
             to_address: current_tx.receiver.clone().to_string(),
+            amount: vec![receiver_coin],
+        })),
+    };
+    let mut protocol_amount = fee;
+    if let Some((referrer, referrer_fee)) = &referrer_payout {
+        protocol_amount -= referrer_fee;
+        res = res.add_message(CosmosMsg::Bank(BankMsg::Send {
+            to_address: referrer.to_string(),
+            amount: vec![Coin {
+                denom: current_tx.ls_token_denom.clone(),
+                amount: *referrer_fee,
+            }],
+        }));
+    }
+    let mut keeper_tip = None;
+    if let Some(keeper) = retry.map(|retry| retry.keeper).or(current_tx.keeper) {
+        let tip = tip_keeper(
+            deps.storage,
+            &env,
+            &config,
+            &keeper,
+            &current_tx.ls_token_denom,
+            protocol_amount,
+        )?;
+        if !tip.is_zero() {
+            protocol_amount -= tip;
+            keeper_tip = Some((keeper, tip));
+        }
+    }
+    if !protocol_amount.is_zero() {
+        res = res.add_message(CosmosMsg::Bank(BankMsg::Send {
+            to_address: config.fee_recipient.to_string(),
             amount: vec![Coin {
                 denom: current_tx.ls_token_denom,
-                amount: balance_diff,
+                amount: protocol_amount,
             }],
-        }))
+        }));
+    }
+
+    res = res
         .add_attribute("method", "handle_ls_reply")
+        .add_attribute("stake_id", stake_id.to_string())
         .add_attribute("minted_lst_amount", balance_diff.to_string())
+        .add_attribute("fee", fee.to_string())
         .add_attribute("receiver", current_tx.receiver.to_string());
+    // a failed mint is caught in the reply, the stake stands without a receipt
+    if let Some(nft_contract) = RECEIPT_CONTRACT.may_load(deps.storage)? {
+        let receipt = Receipt::from(StakeRecord {
+            id: stake_id,
+            ..record
+        });
+        RECEIPTS.save(
+            deps.storage,
+            stake_id,
+            &ReceiptRecord {
+                nft_contract: nft_contract.clone(),
+                receipt: receipt.clone(),
+            },
+        )?;
+        CURRENT_RECEIPT.save(deps.storage, &stake_id)?;
+
+        res = res.add_submessage(SubMsg::reply_always(
+            WasmMsg::Execute {
+                contract_addr: nft_contract.to_string(),
+                msg: to_json_binary(&Cw721ExecuteMsg::Mint {
+                    token_id: stake_id.to_string(),
+                    owner: receipt.receiver.to_string(),
+                    token_uri: None,
+                    extension: receipt,
+                })?,
+                funds: vec![],
+            },
+            RECEIPT_REPLY_ID,
+        ));
+    }
+
+    if let Some((_, referrer_fee)) = referrer_payout {
+        res = res.add_attribute("referrer_fee", referrer_fee.to_string());
+    }
+    if let Some((keeper, tip)) = keeper_tip {
+        res = res
+            .add_attribute("keeper", keeper.to_string())
+            .add_attribute("keeper_tip", tip.to_string());
+    }
 
     Ok(res)
 }
+
+pub fn handle_batch_reply(deps: DepsMut, env: Env, msg: Reply) -> Result<Response, ContractError> {
+    deps.api
+        .debug(format!("WASMDEBUG: batch reply msg: {msg:?}").as_str());
+
+    let current_batch = CURRENT_BATCH.load(deps.storage)?;
+    CURRENT_BATCH.remove(deps.storage);
+    let batch = current_batch.batch;
+
+    if let SubMsgResult::Err(err) = msg.result {
+        let err = ContractError::from_subcall_error(err);
+        let res = Response::default()
+            .add_attribute("method", "handle_batch_reply")
+            .add_attribute("denom", batch.denom.clone())
+            .add_attribute("error", err.to_string());
+        return match err.subcall_action() {
+            // reverting on an unknown failure would keep every later dispatch of the batch
+            // failing, so its deposits are refunded as well
+            SubcallAction::Refund | SubcallAction::Fail => {
+                let refund = refund_batch_deposits(deps.storage, &batch.denom, batch.deposits)?;
+                Ok(res
+                    .add_submessages(refund.messages)
+                    .add_attribute("status", "refunded"))
+            }
+            // the deposits wait for the next dispatch of the batch
+            SubcallAction::Retry => {
+                add_liability(
+                    deps.storage,
+                    &Coin {
+                        denom: batch.denom.clone(),
+                        amount: batch.total,
+                    },
+                )?;
+                BATCHES.save(deps.storage, &batch.denom, &batch)?;
+                Ok(res.add_attribute("status", "requeued"))
+            }
+        };
+    }
+
+    let config = LS_CONFIG.load(deps.storage)?;
+    let ls_token_denom = current_batch.ls_token_denom;
+
+    let current_ls_token_balance = deps
+        .querier
+        .query_balance(env.contract.address.clone(), ls_token_denom.clone())?;
+    let minted = current_ls_token_balance
+        .amount
+        .checked_sub(current_batch.prev_ls_token_balance)
+        .map_err(StdError::from)?;
+
+    // every deposit gets its pro-rata part of the mint
+    let amounts: Vec<_> = batch
+        .deposits
+        .iter()
+        .map(|deposit| deposit.amount)
+        .collect();
+    let allocations = allocate_pro_rata(minted, &amounts)?;
+
+    let mut res = Response::default();
+    let mut total_fee = Uint128::zero();
+    let mut protocol_amount = Uint128::zero();
+    let mut referrer_payouts = BTreeMap::<Addr, Uint128>::new();
+    let deposit_count = batch.deposits.len();
+    for (deposit, amount) in batch.deposits.into_iter().zip(allocations) {
+        let fee = amount * config.protocol_fee;
+        let receiver_amount = amount - fee;
+        total_fee += fee;
+        protocol_amount += fee;
+
+        if let Some(code) = &deposit.referral {
+            let payout = record_referral(
+                deps.storage,
+                code,
+                &batch.denom,
+                deposit.amount,
+                &ls_token_denom,
+                fee,
+            )?;
+            if let Some((referrer, referrer_fee)) = payout {
+                protocol_amount -= referrer_fee;
+                *referrer_payouts.entry(referrer).or_default() += referrer_fee;
+            }
+        }
+
+        save_stake_record(
+            deps.storage,
+            StakeRecord {
+                id: 0,
+                sender: deposit.sender,
+                receiver: deposit.receiver.clone(),
+                native_denom: batch.denom.clone(),
+                native_amount: deposit.amount,
+                ls_token_denom: ls_token_denom.clone(),
+                ls_token_amount: receiver_amount,
+                fee,
+                referral: deposit.referral,
+                post_action: None,
+                block_height: env.block.height,
+                block_time: env.block.time,
+            },
+            config.history_limit,
+        )?;
+        if !receiver_amount.is_zero() {
+            res = res.add_message(BankMsg::Send {
+                to_address: deposit.receiver.to_string(),
+                amount: coins(receiver_amount.u128(), &ls_token_denom),
+            });
+        }
+    }
+
+    DENOM_STATS.update(deps.storage, &batch.denom, |stats| -> StdResult<_> {
+        let mut stats = stats.unwrap_or_default();
+        stats.stake_count += deposit_count as u64;
+        stats.native_volume += batch.total;
+        stats.ls_token_denom = ls_token_denom.clone();
+        stats.ls_token_minted += minted;
+        Ok(stats)
+    })?;
+
+    for (referrer, referrer_fee) in referrer_payouts {
+        res = res.add_message(BankMsg::Send {
+            to_address: referrer.to_string(),
+            amount: coins(referrer_fee.u128(), &ls_token_denom),
+        });
+    }
+    if let Some(keeper) = &current_batch.keeper {
+        let tip = tip_keeper(
+            deps.storage,
+            &env,
+            &config,
+            keeper,
+            &ls_token_denom,
+            protocol_amount,
+        )?;
+        if !tip.is_zero() {
+            protocol_amount -= tip;
+            res = res
+                .add_attribute("keeper", keeper.to_string())
+                .add_attribute("keeper_tip", tip.to_string());
+        }
+    }
+    if !protocol_amount.is_zero() {
+        res = res.add_message(BankMsg::Send {
+            to_address: config.fee_recipient.to_string(),
+            amount: coins(protocol_amount.u128(), &ls_token_denom),
+        });
+    }
+
+    Ok(res
+        .add_attribute("method", "handle_batch_reply")
+        .add_attribute("denom", batch.denom)
+        .add_attribute("deposits", deposit_count.to_string())
+        .add_attribute("minted_lst_amount", minted.to_string())
+        .add_attribute("fee", total_fee.to_string()))
+}
+
+/// Only failed runs reply, the schedule stays and its deposit is left untouched
+pub fn handle_schedule_reply(
+    deps: DepsMut,
+    _env: Env,
+    msg: Reply,
+) -> Result<Response, ContractError> {
+    deps.api
+        .debug(format!("WASMDEBUG: schedule reply msg: {msg:?}").as_str());
+
+    let res = Response::default().add_attribute("method", "handle_schedule_reply");
+    match msg.result {
+        SubMsgResult::Ok(_) => Ok(res.add_attribute("status", "success")),
+        SubMsgResult::Err(err) => Ok(res
+            .add_attribute("status", "failed")
+            .add_attribute("error", err)),
+    }
+}
+
+pub fn handle_receipt_reply(
+    deps: DepsMut,
+    _env: Env,
+    msg: Reply,
+) -> Result<Response, ContractError> {
+    deps.api
+        .debug(format!("WASMDEBUG: receipt reply msg: {msg:?}").as_str());
+
+    let stake_id = CURRENT_RECEIPT.load(deps.storage)?;
+    CURRENT_RECEIPT.remove(deps.storage);
+
+    let res = Response::default()
+        .add_attribute("method", "handle_receipt_reply")
+        .add_attribute("stake_id", stake_id.to_string());
+
+    match msg.result {
+        SubMsgResult::Ok(_) => Ok(res.add_attribute("status", "success")),
+        // no token was minted, so there is no receipt to verify
+        SubMsgResult::Err(err) => {
+            RECEIPTS.remove(deps.storage, stake_id);
+            Ok(res
+                .add_attribute("status", "failed")
+                .add_attribute("error", err))
+        }
+    }
+}
+
+pub fn handle_swap_reply(deps: DepsMut, env: Env, msg: Reply) -> Result<Response, ContractError> {
+    deps.api
+        .debug(format!("WASMDEBUG: swap reply msg: {msg:?}").as_str());
+
+    let swap = CURRENT_SWAP.load(deps.storage)?;
+    CURRENT_SWAP.remove(deps.storage);
+
+    if let SubMsgResult::Err(err) = msg.result {
+        return Ok(Response::default()
+            .add_message(CosmosMsg::Bank(BankMsg::Send {
+                to_address: swap.receiver.to_string(),
+                amount: vec![swap.offer.clone()],
+            }))
+            .add_attribute("method", "handle_swap_reply")
+            .add_attribute("status", "refunded")
+            .add_attribute("refund", swap.offer.to_string())
+            .add_attribute("receiver", swap.receiver.to_string())
+            .add_attribute("error", err));
+    }
+
+    let ask_balance = deps
+        .querier
+        .query_balance(env.contract.address.clone(), swap.ask_denom.clone())?;
+    let swapped = ask_balance
+        .amount
+        .checked_sub(swap.prev_ask_balance)
+        .map_err(StdError::from)?;
+    ensure!(!swapped.is_zero(), ContractError::InvalidAmount {});
+
+    // continue into the regular liquid stake with the swap output
+    let res = try_liquid_staking(
+        deps,
+        env,
+        Coin {
+            denom: swap.ask_denom,
+            amount: swapped,
+        },
+        swap.sender,
+        swap.receiver,
+        swap.referral,
+        swap.post_action,
+        StakeFunding::Funds,
+    )?;
+
+    Ok(res
+        .add_attribute("method", "handle_swap_reply")
+        .add_attribute("status", "swapped")
+        .add_attribute("swapped_amount", swapped.to_string()))
+}
+
+pub fn handle_post_action_reply(
+    deps: DepsMut,
+    _env: Env,
+    msg: Reply,
+) -> Result<Response, ContractError> {
+    deps.api
+        .debug(format!("WASMDEBUG: post action reply msg: {msg:?}").as_str());
+
+    let post_action = CURRENT_POST_ACTION.load(deps.storage)?;
+    CURRENT_POST_ACTION.remove(deps.storage);
+
+    let res = Response::default()
+        .add_attribute("method", "handle_post_action_reply")
+        .add_attribute("post_action", post_action.name)
+        .add_attribute("receiver", post_action.receiver.to_string());
+
+    match msg.result {
+        SubMsgResult::Ok(_) => Ok(res.add_attribute("status", "success")),
+        SubMsgResult::Err(err) => Ok(res
+            .add_message(CosmosMsg::Bank(BankMsg::Send {
+                to_address: post_action.receiver.to_string(),
+                amount: vec![Coin {
+                    denom: post_action.ls_token_denom,
+                    amount: post_action.ls_token_amount,
+                }],
+            }))
+            .add_attribute("status", "failed")
+            .add_attribute("error", err)),
+    }
+}
+
+/// Keep the deposit of a stake that failed on a transient error until it is retried or cancelled.
+///
+/// A failed submessage is rolled back, so the tokens it carried are still held by the contract
+/// when its reply parks or refunds them.
+fn park_failed_stake(
+    deps: DepsMut,
+    env: Env,
+    current_tx: LSInfo,
+    coin: Coin,
+    err: ContractError,
+) -> Result<Response, ContractError> {
+    let id = FAILED_STAKE_SEQ.may_load(deps.storage)?.unwrap_or_default() + 1;
+    FAILED_STAKE_SEQ.save(deps.storage, &id)?;
+
+    add_liability(deps.storage, &coin)?;
+    FAILED_STAKES.save(
+        deps.storage,
+        id,
+        &FailedStake {
+            id,
+            sender: current_tx.sender,
+            receiver: current_tx.receiver.clone(),
+            coin: coin.clone(),
+            referral: current_tx.referral,
+            post_action: current_tx.post_action,
+            funding: current_tx.funding,
+            error: err.to_string(),
+            failed_at: env.block.time,
+        },
+    )?;
+
+    Ok(Response::default()
+        .add_attribute("method", "handle_ls_reply")
+        .add_attribute("status", "failed")
+        .add_attribute("failed_stake_id", id.to_string())
+        .add_attribute("amount", coin.to_string())
+        .add_attribute("receiver", current_tx.receiver.to_string())
+        .add_attribute("error", err.to_string()))
+}
+
+/// Credits the keeper its share of the protocol's part of the fee, up to a number of tips per
+/// block, and returns the tip
+fn tip_keeper(
+    storage: &mut dyn Storage,
+    env: &Env,
+    config: &LsConfig,
+    keeper: &Addr,
+    ls_token_denom: &str,
+    protocol_amount: Uint128,
+) -> StdResult<Uint128> {
+    let tips = KEEPER_TIPS
+        .may_load(storage)?
+        .filter(|tips| tips.height == env.block.height)
+        .map_or(0, |tips| tips.count);
+    let tip = protocol_amount * config.keeper_fee_share;
+    if tips >= config.keeper_tips_per_block || tip.is_zero() {
+        return Ok(Uint128::zero());
+    }
+    KEEPER_REWARDS.update(
+        storage,
+        (keeper, ls_token_denom),
+        |rewards| -> StdResult<_> { Ok(rewards.unwrap_or_default() + tip) },
+    )?;
+    add_liability(storage, &coin(tip.u128(), ls_token_denom))?;
+    KEEPER_TIPS.save(
+        storage,
+        &KeeperTips {
+            height: env.block.height,
+            count: tips + 1,
+        },
+    )?;
+    Ok(tip)
+}
+
+/// Adds the stake to the referrer's volume and returns the referrer's share of the protocol
+/// fee, if any
+fn record_referral(
+    storage: &mut dyn Storage,
+    code: &str,
+    native_denom: &str,
+    native_amount: Uint128,
+    ls_token_denom: &str,
+    fee: Uint128,
+) -> StdResult<Option<(Addr, Uint128)>> {
+    let mut referrer = REFERRERS.load(storage, code)?;
+    referrer.stake_count += 1;
+    REFERRERS.save(storage, code, &referrer)?;
+
+    REFERRAL_VOLUME.update(storage, (code, native_denom), |volume| -> StdResult<_> {
+        Ok(volume.unwrap_or_default() + native_amount)
+    })?;
+
+    let referrer_fee = fee * referrer.fee_share;
+    if referrer_fee.is_zero() {
+        return Ok(None);
+    }
+    REFERRAL_FEES.update(storage, (code, ls_token_denom), |paid| -> StdResult<_> {
+        Ok(paid.unwrap_or_default() + referrer_fee)
+    })?;
+    Ok(Some((referrer.address, referrer_fee)))
+}
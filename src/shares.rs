@@ -0,0 +1,109 @@
+use cosmwasm_std::{ensure, Decimal256, StdError, Uint128, Uint256};
+
+use crate::ContractError;
+
+/// Splits `minted` over the deposits pro rata to their native amounts.
+///
+/// Every deposit gets the floor of its exact share, computed as a `Decimal256` ratio of the
+/// batch. The remainder is handed out one unit at a time to the largest fractional parts, ties
+/// going to the earlier deposit, so the split only depends on the queue.
+pub fn allocate_pro_rata(
+    minted: Uint128,
+    amounts: &[Uint128],
+) -> Result<Vec<Uint128>, ContractError> {
+    let total: Uint128 = amounts.iter().sum();
+    ensure!(!total.is_zero(), ContractError::InvalidAmount {});
+
+    let shares = amounts
+        .iter()
+        .map(|amount| {
+            Decimal256::checked_from_ratio(Uint256::from(minted) * Uint256::from(*amount), total)
+                .map_err(|err| StdError::generic_err(err.to_string()))
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let mut allocations = shares
+        .iter()
+        .map(|share| Uint128::try_from(share.to_uint_floor()))
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(StdError::from)?;
+    let distributed: Uint128 = allocations.iter().sum();
+    let remainder = (minted - distributed).u128() as usize;
+
+    let mut order: Vec<usize> = (0..shares.len()).collect();
+    order.sort_by(|a, b| {
+        let fraction = |index: usize| shares[index] - shares[index].floor();
+        fraction(*b).cmp(&fraction(*a)).then(a.cmp(b))
+    });
+    for index in order.into_iter().take(remainder) {
+        allocations[index] += Uint128::one();
+    }
+
+    // the whole mint is handed out and nobody gets more than the ceiling of its share
+    let distributed: Uint128 = allocations.iter().sum();
+    ensure!(distributed == minted, ContractError::InvalidAllocation {});
+    for (allocation, share) in allocations.iter().zip(&shares) {
+        ensure!(
+            Uint256::from(*allocation) <= share.to_uint_ceil(),
+            ContractError::InvalidAllocation {}
+        );
+    }
+    Ok(allocations)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn allocate(minted: u128, amounts: &[u128]) -> Vec<u128> {
+        let amounts: Vec<_> = amounts.iter().copied().map(Uint128::new).collect();
+        allocate_pro_rata(Uint128::new(minted), &amounts)
+            .unwrap()
+            .into_iter()
+            .map(|allocation| allocation.u128())
+            .collect()
+    }
+
+    #[test]
+    fn test_allocate_pro_rata() {
+        assert_eq!(allocate(6000, &[1000, 2000, 3000]), vec![1000, 2000, 3000]);
+        assert_eq!(allocate(4500, &[1000, 2000]), vec![1500, 3000]);
+
+        // the remainder goes to the largest fractions, ties to the earlier deposit
+        assert_eq!(allocate(10, &[1, 1, 1]), vec![4, 3, 3]);
+        assert_eq!(allocate(11, &[1, 1, 1]), vec![4, 4, 3]);
+        assert_eq!(allocate(7, &[1, 2, 4]), vec![1, 2, 4]);
+        assert_eq!(allocate(5, &[1, 2, 4]), vec![1, 1, 3]);
+        assert_eq!(allocate(0, &[1, 2]), vec![0, 0]);
+
+        assert_eq!(
+            allocate_pro_rata(Uint128::new(10), &[Uint128::zero()]).unwrap_err(),
+            ContractError::InvalidAmount {}
+        );
+    }
+
+    #[test]
+    fn test_allocation_invariants() {
+        // deterministic pseudo random batches, the invariants are also checked on allocation
+        let mut seed = 42u64;
+        let mut next = |bound: u64| {
+            seed = seed
+                .wrapping_mul(6364136223846793005)
+                .wrapping_add(1442695040888963407);
+            (seed >> 33) % bound + 1
+        };
+        for _ in 0..500 {
+            let deposits = next(20) as usize;
+            let amounts: Vec<u128> = (0..deposits).map(|_| next(1_000_000) as u128).collect();
+            let total: u128 = amounts.iter().sum();
+            let minted = total * next(150) as u128 / 100;
+
+            let allocations = allocate(minted, &amounts);
+            assert_eq!(allocations.iter().sum::<u128>(), minted);
+            for (allocation, amount) in allocations.iter().zip(&amounts) {
+                assert!(*allocation * total < minted * amount + total);
+                assert!(*allocation * total + total > minted * amount);
+            }
+        }
+    }
+}
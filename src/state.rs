@@ -1,19 +1,299 @@
-use cosmwasm_std::{Addr, Uint128};
-use cw_storage_plus::Item;
+use cosmwasm_std::{Addr, Coin, Decimal, Order, StdResult, Storage, Uint128};
+use cw_storage_plus::{Index, IndexList, IndexedMap, Item, Map, MultiIndex};
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 
-use crate::msg::LsConfig;
+use crate::msg::{
+    Batch, DenomConfig, FailedStake, LsConfig, Operator, PendingChange, PostAction, Receipt,
+    Schedule, StakeFunding, StakeRecord,
+};
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
 pub struct LSInfo {
+    pub sender: Addr,
     pub receiver: Addr,
+    pub native_denom: String,
+    pub native_amount: Uint128,
     pub ls_token_denom: String,
     pub prev_ls_token_balance: Uint128,
+    pub referral: Option<String>,
+    pub post_action: Option<String>,
+    pub funding: StakeFunding,
+    /// Keeper that ran the schedule of the stake, tipped on success
+    pub keeper: Option<Addr>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct BatchInfo {
+    pub batch: Batch,
+    pub ls_token_denom: String,
+    pub prev_ls_token_balance: Uint128,
+    /// Keeper that processed the batch, tipped on success
+    pub keeper: Option<Addr>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct SwapInfo {
+    pub sender: Addr,
+    pub receiver: Addr,
+    pub offer: Coin,
+    pub ask_denom: String,
+    pub prev_ask_balance: Uint128,
+    pub referral: Option<String>,
+    pub post_action: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct PostActionInfo {
+    pub name: String,
+    pub receiver: Addr,
+    pub ls_token_denom: String,
+    pub ls_token_amount: Uint128,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema, Default)]
+pub struct DenomStats {
+    pub stake_count: u64,
+    pub native_volume: Uint128,
+    pub ls_token_denom: String,
+    pub ls_token_minted: Uint128,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct ReceiptRecord {
+    pub nft_contract: Addr,
+    pub receipt: Receipt,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct Governance {
+    pub contract: Addr,
+    pub proposal_required: bool,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct RetryInfo {
+    pub id: u64,
+    pub keeper: Addr,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct KeeperTips {
+    pub height: u64,
+    pub count: u32,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct Referrer {
+    pub address: Addr,
+    pub fee_share: Decimal,
+    pub stake_count: u64,
 }
 
 /// configuration relevant to entering into an LS
 pub const LS_CONFIG: Item<LsConfig> = Item::new("ls_config");
 
+// Contract sharing the admin powers, or holding them alone in proposal-required mode
+pub const GOVERNANCE: Item<Governance> = Item::new("governance");
+
+// Liquid staking configuration per native denom, see `DenomConfig`
+pub const DENOM_CONFIGS: Map<&str, DenomConfig> = Map::new("denom_configs");
+
 // Holds temp state for the ls message that the contract is currently processing
 pub const CURRENT_TX: Item<LSInfo> = Item::new("current_tx");
+
+// Prepaid balances per (owner, denom), they are liabilities until staked or withdrawn
+pub const DEPOSITS: Map<(&Addr, &str), Uint128> = Map::new("deposits");
+
+// Operator approvals per (owner, operator)
+pub const OPERATORS: Map<(&Addr, &Addr), Operator> = Map::new("operators");
+
+// Id of the last operator approval
+pub const APPROVAL_SEQ: Item<u64> = Item::new("approval_seq");
+
+// Id of the last liquid staking schedule
+pub const SCHEDULE_SEQ: Item<u64> = Item::new("schedule_seq");
+
+// Queued deposits per native denom, they are liabilities until the batch is dispatched
+pub const BATCHES: Map<&str, Batch> = Map::new("batches");
+
+// Holds temp state for the batch that the contract is currently staking
+pub const CURRENT_BATCH: Item<BatchInfo> = Item::new("current_batch");
+
+// Holds temp state for the swap preceding a liquid stake
+pub const CURRENT_SWAP: Item<SwapInfo> = Item::new("current_swap");
+
+// Holds the LS tokens handed to the post action that is currently executing
+pub const CURRENT_POST_ACTION: Item<PostActionInfo> = Item::new("current_post_action");
+
+// cw721 contract minting a receipt per stake, receipts are off when unset
+pub const RECEIPT_CONTRACT: Item<Addr> = Item::new("receipt_contract");
+
+// Minted receipts by stake id, kept when the stake record is pruned from the history
+pub const RECEIPTS: Map<u64, ReceiptRecord> = Map::new("receipts");
+
+// Stake id of the receipt that is currently being minted
+pub const CURRENT_RECEIPT: Item<u64> = Item::new("current_receipt");
+
+// Liquid stakes parked after a transient provider error, their deposits are liabilities
+pub const FAILED_STAKES: Map<u64, FailedStake> = Map::new("failed_stakes");
+
+// Id of the last failed stake
+pub const FAILED_STAKE_SEQ: Item<u64> = Item::new("failed_stake_seq");
+
+// Failed stake that is currently being retried and the keeper retrying it
+pub const CURRENT_RETRY: Item<RetryInfo> = Item::new("current_retry");
+
+// Unclaimed keeper tips per (keeper, ls token denom), they are liabilities until claimed
+pub const KEEPER_REWARDS: Map<(&Addr, &str), Uint128> = Map::new("keeper_rewards");
+
+// Keeper tips paid in the last block with a tip
+pub const KEEPER_TIPS: Item<KeeperTips> = Item::new("keeper_tips");
+
+// Post actions users can pick by name
+pub const POST_ACTIONS: Map<&str, PostAction> = Map::new("post_actions");
+
+// Registered referral partners by code
+pub const REFERRERS: Map<&str, Referrer> = Map::new("referrers");
+
+// Native tokens staked per (referral code, native denom)
+pub const REFERRAL_VOLUME: Map<(&str, &str), Uint128> = Map::new("referral_volume");
+
+// LS tokens paid to referrers per (referral code, ls token denom)
+pub const REFERRAL_FEES: Map<(&str, &str), Uint128> = Map::new("referral_fees");
+
+// Tokens held by the contract on behalf of users per denom, excluded from sweeps
+pub const LIABILITIES: Map<&str, Uint128> = Map::new("liabilities");
+
+// Stake counters per native denom
+pub const DENOM_STATS: Map<&str, DenomStats> = Map::new("denom_stats");
+
+// Timelocked config changes by id
+pub const PENDING_CHANGES: Map<u64, PendingChange> = Map::new("pending_changes");
+
+// Id of the last pending config change
+pub const PENDING_CHANGE_SEQ: Item<u64> = Item::new("pending_change_seq");
+
+// Id of the last stake record saved in the history
+pub const STAKE_SEQ: Item<u64> = Item::new("stake_seq");
+
+// Upper bound of records pruned per stake, so lowering the limit never blocks a reply
+const MAX_PRUNE_PER_STAKE: usize = 10;
+
+/// Record tokens the contract holds on behalf of a user
+pub fn add_liability(storage: &mut dyn Storage, coin: &Coin) -> StdResult<()> {
+    LIABILITIES.update(storage, &coin.denom, |owed| -> StdResult<_> {
+        Ok(owed.unwrap_or_default() + coin.amount)
+    })?;
+    Ok(())
+}
+
+/// Release tokens recorded by `add_liability`
+pub fn remove_liability(storage: &mut dyn Storage, coin: &Coin) -> StdResult<()> {
+    let owed = LIABILITIES
+        .may_load(storage, &coin.denom)?
+        .unwrap_or_default();
+    let owed = owed.checked_sub(coin.amount)?;
+    if owed.is_zero() {
+        LIABILITIES.remove(storage, &coin.denom);
+    } else {
+        LIABILITIES.save(storage, &coin.denom, &owed)?;
+    }
+    Ok(())
+}
+
+/// Amount of `denom` held by the contract that belongs to users: the recorded liabilities
+/// and the tokens of a swap or post action waiting for its reply. A liquid stake in flight
+/// holds nothing, its native tokens are with the provider until the reply distributes the mint.
+pub fn owed_amount(storage: &dyn Storage, denom: &str) -> StdResult<Uint128> {
+    let mut owed = LIABILITIES.may_load(storage, denom)?.unwrap_or_default();
+    if let Some(swap) = CURRENT_SWAP.may_load(storage)? {
+        if swap.offer.denom == denom {
+            owed += swap.offer.amount;
+        }
+    }
+    if let Some(post_action) = CURRENT_POST_ACTION.may_load(storage)? {
+        if post_action.ls_token_denom == denom {
+            owed += post_action.ls_token_amount;
+        }
+    }
+    Ok(owed)
+}
+
+pub struct StakeIndexes<'a> {
+    pub receiver: MultiIndex<'a, Addr, StakeRecord, u64>,
+    pub sender: MultiIndex<'a, Addr, StakeRecord, u64>,
+}
+
+impl<'a> IndexList<StakeRecord> for StakeIndexes<'a> {
+    fn get_indexes(&'_ self) -> Box<dyn Iterator<Item = &'_ dyn Index<StakeRecord>> + '_> {
+        let v: Vec<&dyn Index<StakeRecord>> = vec![&self.receiver, &self.sender];
+        Box::new(v.into_iter())
+    }
+}
+
+/// history of completed stakes, indexed by receiver and sender
+pub fn stakes<'a>() -> IndexedMap<'a, u64, StakeRecord, StakeIndexes<'a>> {
+    let indexes = StakeIndexes {
+        receiver: MultiIndex::new(|_pk, r| r.receiver.clone(), "stakes", "stakes__receiver"),
+        sender: MultiIndex::new(|_pk, r| r.sender.clone(), "stakes", "stakes__sender"),
+    };
+    IndexedMap::new("stakes", indexes)
+}
+
+pub struct ScheduleIndexes<'a> {
+    pub owner: MultiIndex<'a, Addr, Schedule, u64>,
+    pub next_run: MultiIndex<'a, u64, Schedule, u64>,
+}
+
+impl<'a> IndexList<Schedule> for ScheduleIndexes<'a> {
+    fn get_indexes(&'_ self) -> Box<dyn Iterator<Item = &'_ dyn Index<Schedule>> + '_> {
+        let v: Vec<&dyn Index<Schedule>> = vec![&self.owner, &self.next_run];
+        Box::new(v.into_iter())
+    }
+}
+
+/// liquid staking schedules, indexed by owner and by the seconds of their next run
+pub fn schedules<'a>() -> IndexedMap<'a, u64, Schedule, ScheduleIndexes<'a>> {
+    let indexes = ScheduleIndexes {
+        owner: MultiIndex::new(|_pk, s| s.owner.clone(), "schedules", "schedules__owner"),
+        next_run: MultiIndex::new(
+            |_pk, s| s.next_run.seconds(),
+            "schedules",
+            "schedules__next_run",
+        ),
+    };
+    IndexedMap::new("schedules", indexes)
+}
+
+/// Saves a stake record under the next id and prunes the oldest records above `limit`
+pub fn save_stake_record(
+    storage: &mut dyn Storage,
+    mut record: StakeRecord,
+    limit: u64,
+) -> StdResult<u64> {
+    let id = STAKE_SEQ.may_load(storage)?.unwrap_or_default() + 1;
+    STAKE_SEQ.save(storage, &id)?;
+
+    record.id = id;
+    stakes().save(storage, id, &record)?;
+
+    // ids are sequential and pruned from the front, so the oldest key tells the size
+    let oldest = stakes()
+        .keys(storage, None, None, Order::Ascending)
+        .next()
+        .transpose()?
+        .unwrap_or(id);
+    let excess = (id - oldest + 1).saturating_sub(limit);
+    if excess > 0 {
+        let pruned = stakes()
+            .keys(storage, None, None, Order::Ascending)
+            .take((excess as usize).min(MAX_PRUNE_PER_STAKE))
+            .collect::<StdResult<Vec<_>>>()?;
+        for key in pruned {
+            stakes().remove(storage, key)?;
+        }
+    }
+
+    Ok(id)
+}
@@ -0,0 +1,375 @@
+//! Mock querier and fixtures for unit tests of the contract and of contracts integrating with it,
+//! enabled with the `testing` feature
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+
+use cosmwasm_std::{
+    from_json,
+    testing::{MockApi, MockQuerier, MockStorage},
+    to_json_binary, Addr, Coin, ContractInfoResponse, ContractResult, Decimal, Empty, OwnedDeps,
+    Querier, QuerierResult, QueryRequest, SystemError, SystemResult, WasmQuery,
+};
+use persistence_std::types::{
+    ibc::applications::transfer::v1::{
+        DenomTrace, QueryDenomTraceRequest, QueryDenomTraceResponse,
+    },
+    pstake::liquidstakeibc::v1beta1::{
+        ica_account::ChannelState, HostChain, IcaAccount, Params, QueryHostChainRequest,
+        QueryHostChainResponse, QueryHostChainsResponse, QueryParamsResponse,
+    },
+};
+use prost::Message;
+
+use crate::{
+    contract::DEFAULT_HISTORY_LIMIT,
+    execute::{DENOM_TRACE_QUERY_TYPE, HOST_CHAINS_QUERY_TYPE, LIQUIDSTAKEIBC_PARAMS_QUERY_TYPE},
+    msg::{InstantiateMsg, LsConfig},
+};
+
+pub const HOST_CHAIN_QUERY_TYPE: &str = "/pstake.liquidstakeibc.v1beta1.Query/HostChain";
+pub const DEFAULT_BONDED_DENOM: &str = "uxprt";
+pub const DEFAULT_LS_PREFIX: &str = "stk/";
+
+/// Querier answering the Stargate queries of the contract, other queries are
+/// handled by the cosmwasm `MockQuerier`
+pub struct WasmMockQuerier {
+    base: MockQuerier,
+    denom_traces: HashMap<String, DenomTrace>,
+    host_chains: BTreeMap<String, HostChain>,
+    liquidstakeibc_params: Params,
+    contracts: BTreeSet<String>,
+}
+
+impl Querier for WasmMockQuerier {
+    fn raw_query(&self, bin_request: &[u8]) -> QuerierResult {
+        let request: QueryRequest<Empty> = match from_json(bin_request) {
+            Ok(v) => v,
+            Err(e) => {
+                return SystemResult::Err(SystemError::InvalidRequest {
+                    error: format!("Parsing query request: {}", e),
+                    request: bin_request.into(),
+                })
+            }
+        };
+        self.handle_query(&request)
+    }
+}
+
+impl Default for WasmMockQuerier {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl WasmMockQuerier {
+    pub fn new() -> Self {
+        let mut base = MockQuerier::new(&[]);
+        base.update_staking(DEFAULT_BONDED_DENOM, &[], &[]);
+        WasmMockQuerier {
+            base,
+            denom_traces: HashMap::new(),
+            host_chains: BTreeMap::new(),
+            liquidstakeibc_params: Params {
+                lower_c_value_limit: "0.85".to_string(),
+                upper_c_value_limit: "1".to_string(),
+                ..Default::default()
+            },
+            contracts: BTreeSet::new(),
+        }
+    }
+
+    pub fn handle_query(&self, request: &QueryRequest<Empty>) -> QuerierResult {
+        match request {
+            QueryRequest::Stargate { path, data } => match path.as_str() {
+                DENOM_TRACE_QUERY_TYPE => {
+                    let Ok(request) = QueryDenomTraceRequest::decode(data.as_slice()) else {
+                        return invalid_request(path);
+                    };
+                    match self.denom_traces.get(&request.hash) {
+                        Some(denom_trace) => ok(&QueryDenomTraceResponse {
+                            denom_trace: Some(denom_trace.clone()),
+                        }),
+                        None => SystemResult::Err(SystemError::Unknown {}),
+                    }
+                }
+                HOST_CHAIN_QUERY_TYPE => {
+                    let Ok(request) = QueryHostChainRequest::decode(data.as_slice()) else {
+                        return invalid_request(path);
+                    };
+                    match self.host_chains.get(&request.chain_id) {
+                        Some(host_chain) => ok(&QueryHostChainResponse {
+                            host_chain: Some(host_chain.clone()),
+                        }),
+                        None => SystemResult::Ok(ContractResult::Err(format!(
+                            "host chain {} not found",
+                            request.chain_id
+                        ))),
+                    }
+                }
+                HOST_CHAINS_QUERY_TYPE => ok(&QueryHostChainsResponse {
+                    host_chains: self.host_chains.values().cloned().collect(),
+                }),
+                LIQUIDSTAKEIBC_PARAMS_QUERY_TYPE => ok(&QueryParamsResponse {
+                    params: Some(self.liquidstakeibc_params.clone()),
+                }),
+                _ => SystemResult::Err(SystemError::UnsupportedRequest { kind: path.clone() }),
+            },
+            QueryRequest::Wasm(WasmQuery::ContractInfo { contract_addr })
+                if self.contracts.contains(contract_addr) =>
+            {
+                ok(&ContractInfoResponse::default())
+            }
+            _ => self.base.handle_query(request),
+        }
+    }
+
+    /// Set the bank balance of any address
+    pub fn set_balance(&mut self, address: impl Into<String>, balance: Vec<Coin>) {
+        self.base.update_balance(address, balance);
+    }
+
+    /// Answer contract info queries for `address`, as for an instantiated contract
+    pub fn set_contract(&mut self, address: impl Into<String>) {
+        self.contracts.insert(address.into());
+    }
+
+    pub fn set_bonded_denom(&mut self, denom: &str) {
+        self.base.update_staking(denom, &[], &[]);
+    }
+
+    pub fn mock_denom_trace(&mut self, ibc_denom: impl Into<String>, path: &str, base_denom: &str) {
+        self.denom_traces.insert(
+            ibc_denom.into(),
+            DenomTrace {
+                path: path.to_string(),
+                base_denom: base_denom.to_string(),
+            },
+        );
+    }
+
+    pub fn set_host_chain(&mut self, host_chain: HostChain) {
+        self.host_chains
+            .insert(host_chain.chain_id.clone(), host_chain);
+    }
+
+    /// Set the liquidstakeibc params, the default c-value limits are 0.85 and 1
+    pub fn set_liquidstakeibc_params(&mut self, params: Params) {
+        self.liquidstakeibc_params = params;
+    }
+
+    /// Set the c-value of a host chain added with `set_host_chain`
+    pub fn set_c_value(&mut self, chain_id: &str, c_value: Decimal) {
+        let host_chain = self
+            .host_chains
+            .get_mut(chain_id)
+            .unwrap_or_else(|| panic!("host chain {chain_id} not mocked"));
+        host_chain.c_value = c_value.to_string();
+    }
+}
+
+fn ok<T: serde::Serialize>(response: &T) -> QuerierResult {
+    SystemResult::Ok(ContractResult::from(to_json_binary(response)))
+}
+
+fn invalid_request(path: &str) -> QuerierResult {
+    SystemResult::Err(SystemError::InvalidRequest {
+        error: format!("Decoding request for {path}"),
+        request: Default::default(),
+    })
+}
+
+/// Dependencies with a `WasmMockQuerier` and no balances
+pub fn mock_dependencies() -> OwnedDeps<MockStorage, MockApi, WasmMockQuerier, Empty> {
+    OwnedDeps {
+        storage: MockStorage::default(),
+        api: MockApi::default(),
+        querier: WasmMockQuerier::new(),
+        custom_query_type: Default::default(),
+    }
+}
+
+/// Active host chain with an open delegation account and a c-value of one
+pub fn mock_host_chain(chain_id: &str, host_denom: &str, channel_id: &str) -> HostChain {
+    HostChain {
+        chain_id: chain_id.to_string(),
+        host_denom: host_denom.to_string(),
+        channel_id: channel_id.to_string(),
+        port_id: "transfer".to_string(),
+        delegation_account: Some(IcaAccount {
+            address: format!("{chain_id}-delegation"),
+            channel_state: ChannelState::IcaChannelCreated as i32,
+            ..Default::default()
+        }),
+        minimum_deposit: "1".to_string(),
+        c_value: Decimal::one().to_string(),
+        last_c_value: Decimal::one().to_string(),
+        active: true,
+        ..Default::default()
+    }
+}
+
+/// Builds an `InstantiateMsg`, every optional field unset by default
+pub struct InstantiateMsgBuilder {
+    msg: InstantiateMsg,
+}
+
+impl Default for InstantiateMsgBuilder {
+    fn default() -> Self {
+        InstantiateMsgBuilder {
+            msg: InstantiateMsg {
+                ls_prefix: DEFAULT_LS_PREFIX.to_string(),
+                protocol_fee: None,
+                fee_recipient: None,
+                history_limit: None,
+                swap_router: None,
+                config_timelock: None,
+            },
+        }
+    }
+}
+
+impl InstantiateMsgBuilder {
+    pub fn ls_prefix(mut self, ls_prefix: &str) -> Self {
+        self.msg.ls_prefix = ls_prefix.to_string();
+        self
+    }
+
+    pub fn protocol_fee(mut self, protocol_fee: Decimal) -> Self {
+        self.msg.protocol_fee = Some(protocol_fee);
+        self
+    }
+
+    pub fn fee_recipient(mut self, fee_recipient: &str) -> Self {
+        self.msg.fee_recipient = Some(fee_recipient.to_string());
+        self
+    }
+
+    pub fn history_limit(mut self, history_limit: u64) -> Self {
+        self.msg.history_limit = Some(history_limit);
+        self
+    }
+
+    pub fn swap_router(mut self, swap_router: &str) -> Self {
+        self.msg.swap_router = Some(swap_router.to_string());
+        self
+    }
+
+    pub fn config_timelock(mut self, config_timelock: u64) -> Self {
+        self.msg.config_timelock = Some(config_timelock);
+        self
+    }
+
+    pub fn build(self) -> InstantiateMsg {
+        self.msg
+    }
+}
+
+/// Builds the `LsConfig` a contract instantiated by `admin` with defaults has
+pub struct LsConfigBuilder {
+    config: LsConfig,
+}
+
+impl LsConfigBuilder {
+    pub fn new(admin: &str) -> Self {
+        LsConfigBuilder {
+            config: LsConfig {
+                admin: Addr::unchecked(admin),
+                active: true,
+                ls_prefix: DEFAULT_LS_PREFIX.to_string(),
+                protocol_fee: Decimal::zero(),
+                fee_recipient: Addr::unchecked(admin),
+                history_limit: DEFAULT_HISTORY_LIMIT,
+                swap_router: None,
+                config_timelock: 0,
+                keeper_fee_share: Decimal::zero(),
+                keeper_tips_per_block: 0,
+            },
+        }
+    }
+
+    pub fn active(mut self, active: bool) -> Self {
+        self.config.active = active;
+        self
+    }
+
+    pub fn ls_prefix(mut self, ls_prefix: &str) -> Self {
+        self.config.ls_prefix = ls_prefix.to_string();
+        self
+    }
+
+    pub fn protocol_fee(mut self, protocol_fee: Decimal) -> Self {
+        self.config.protocol_fee = protocol_fee;
+        self
+    }
+
+    pub fn fee_recipient(mut self, fee_recipient: &str) -> Self {
+        self.config.fee_recipient = Addr::unchecked(fee_recipient);
+        self
+    }
+
+    pub fn history_limit(mut self, history_limit: u64) -> Self {
+        self.config.history_limit = history_limit;
+        self
+    }
+
+    pub fn swap_router(mut self, swap_router: &str) -> Self {
+        self.config.swap_router = Some(Addr::unchecked(swap_router));
+        self
+    }
+
+    pub fn config_timelock(mut self, config_timelock: u64) -> Self {
+        self.config.config_timelock = config_timelock;
+        self
+    }
+
+    pub fn keeper_tip(mut self, fee_share: Decimal, tips_per_block: u32) -> Self {
+        self.config.keeper_fee_share = fee_share;
+        self.config.keeper_tips_per_block = tips_per_block;
+        self
+    }
+
+    pub fn build(self) -> LsConfig {
+        self.config
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use cosmwasm_std::{coins, QuerierWrapper, Uint128};
+
+    use super::*;
+
+    #[test]
+    fn test_mock_querier() {
+        let mut querier = WasmMockQuerier::new();
+        querier.set_balance("anyone", coins(100, "stk/uatom"));
+        querier.set_host_chain(mock_host_chain("cosmoshub-4", "uatom", "channel-0"));
+        querier.set_c_value("cosmoshub-4", Decimal::percent(95));
+
+        let wrapper = QuerierWrapper::<Empty>::new(&querier);
+        assert_eq!(
+            wrapper.query_balance("anyone", "stk/uatom").unwrap().amount,
+            Uint128::new(100)
+        );
+        assert_eq!(wrapper.query_bonded_denom().unwrap(), DEFAULT_BONDED_DENOM);
+
+        let res: QueryHostChainResponse = wrapper
+            .query(&QueryRequest::Stargate {
+                path: HOST_CHAIN_QUERY_TYPE.to_string(),
+                data: QueryHostChainRequest {
+                    chain_id: "cosmoshub-4".to_string(),
+                }
+                .into(),
+            })
+            .unwrap();
+        assert_eq!(res.host_chain.unwrap().c_value, "0.95");
+
+        // unknown paths are errors rather than panics
+        let err = wrapper
+            .query::<Empty>(&QueryRequest::Stargate {
+                path: "/pstake.liquidstake.v1beta1.Query/Params".to_string(),
+                data: Default::default(),
+            })
+            .unwrap_err();
+        assert!(err.to_string().contains("Unsupported query type"));
+    }
+}
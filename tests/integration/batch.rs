@@ -0,0 +1,271 @@
+use cosmwasm_std::{coins, Decimal, Event, Uint128};
+use ica_liquid_staking::{
+    msg::{
+        Batch, BatchConfig, DenomConfig, ExecuteMsg, InstantiateMsg, KeeperRewardsResponse,
+        QueryMsg,
+    },
+    ContractError,
+};
+
+use crate::{
+    mock_pstake::MODULE_ACCOUNT,
+    suite::{contract_error, default_instantiate_msg, Suite, ADMIN, ATOM, IBC_ATOM, STK_ATOM},
+};
+
+const ALICE: &str = "persistencealice";
+const BOB: &str = "persistencebob";
+
+/// Suite with a 10% protocol fee batching IBC atom deposits by count
+fn setup(max_deposits: u32) -> Suite {
+    let mut suite = Suite::with_instantiate(InstantiateMsg {
+        protocol_fee: Some(Decimal::percent(10)),
+        ..default_instantiate_msg()
+    });
+    suite
+        .execute(
+            ADMIN,
+            &ExecuteMsg::SetDenomConfig {
+                denom: IBC_ATOM.to_string(),
+                config: Some(DenomConfig {
+                    batch: Some(BatchConfig {
+                        max_amount: None,
+                        max_deposits,
+                        max_age: None,
+                    }),
+                    ..DenomConfig::default_for(IBC_ATOM)
+                }),
+            },
+            &[],
+        )
+        .unwrap();
+    suite
+}
+
+fn process_batch() -> ExecuteMsg {
+    ExecuteMsg::ProcessBatch {
+        denom: IBC_ATOM.to_string(),
+    }
+}
+
+fn query_batch(suite: &Suite) -> Option<Batch> {
+    suite
+        .query(&QueryMsg::Batch {
+            denom: IBC_ATOM.to_string(),
+        })
+        .unwrap()
+}
+
+#[test]
+fn batch_distributes_the_mint_pro_rata() {
+    let mut suite = setup(10);
+    suite.set_c_value(ATOM, Decimal::percent(150));
+
+    suite.liquid_stake("sender", 1000, IBC_ATOM, ALICE).unwrap();
+    suite.liquid_stake("sender", 2000, IBC_ATOM, BOB).unwrap();
+    assert_eq!(suite.contract_balance(IBC_ATOM), Uint128::new(3000));
+    assert_eq!(query_batch(&suite).unwrap().total, Uint128::new(3000));
+
+    // queued deposits are owed to their receivers
+    let err = suite
+        .execute(
+            ADMIN,
+            &ExecuteMsg::Sweep {
+                denoms: vec![IBC_ATOM.to_string()],
+                to: ADMIN.to_string(),
+            },
+            &[],
+        )
+        .unwrap_err();
+    assert_eq!(contract_error(err), ContractError::NothingToSweep {});
+
+    suite.execute("keeper", &process_batch(), &[]).unwrap();
+    assert_eq!(suite.balance(MODULE_ACCOUNT, IBC_ATOM), Uint128::new(3000));
+    assert_eq!(suite.balance(ALICE, STK_ATOM), Uint128::new(1350));
+    assert_eq!(suite.balance(BOB, STK_ATOM), Uint128::new(2700));
+    assert_eq!(suite.balance(ADMIN, STK_ATOM), Uint128::new(450));
+    assert_eq!(suite.contract_balance(IBC_ATOM), Uint128::zero());
+    assert_eq!(suite.contract_balance(STK_ATOM), Uint128::zero());
+    assert_eq!(query_batch(&suite), None);
+}
+
+#[test]
+fn full_batch_is_dispatched_by_the_last_deposit() {
+    let mut suite = setup(2);
+
+    suite.liquid_stake("sender", 1000, IBC_ATOM, ALICE).unwrap();
+    assert_eq!(suite.balance(ALICE, STK_ATOM), Uint128::zero());
+    suite.liquid_stake("sender", 1000, IBC_ATOM, BOB).unwrap();
+    assert_eq!(suite.balance(ALICE, STK_ATOM), Uint128::new(900));
+    assert_eq!(suite.balance(BOB, STK_ATOM), Uint128::new(900));
+    assert_eq!(query_batch(&suite), None);
+}
+
+#[test]
+fn paused_module_keeps_the_batch_queued() {
+    let mut suite = setup(10);
+    suite.liquid_stake("sender", 1000, IBC_ATOM, ALICE).unwrap();
+
+    suite.set_pstake_failure(Some("unbonding epoch in progress"));
+    suite.execute("keeper", &process_batch(), &[]).unwrap();
+    assert_eq!(query_batch(&suite).unwrap().total, Uint128::new(1000));
+    assert_eq!(suite.contract_balance(IBC_ATOM), Uint128::new(1000));
+
+    suite.set_pstake_failure(None);
+    suite.execute("keeper", &process_batch(), &[]).unwrap();
+    assert_eq!(suite.balance(ALICE, STK_ATOM), Uint128::new(900));
+
+    let err = suite.execute("keeper", &process_batch(), &[]).unwrap_err();
+    assert_eq!(
+        contract_error(err),
+        ContractError::EmptyBatch {
+            denom: IBC_ATOM.to_string()
+        }
+    );
+}
+
+#[test]
+fn unknown_failure_refunds_the_batch() {
+    let mut suite = setup(10);
+    suite.liquid_stake("sender", 1000, IBC_ATOM, ALICE).unwrap();
+    suite.liquid_stake("sender", 2000, IBC_ATOM, BOB).unwrap();
+
+    suite.set_pstake_failure(Some("out of gas"));
+    suite.execute("keeper", &process_batch(), &[]).unwrap();
+    assert_eq!(suite.balance(ALICE, IBC_ATOM), Uint128::new(1000));
+    assert_eq!(suite.balance(BOB, IBC_ATOM), Uint128::new(2000));
+    assert_eq!(suite.contract_balance(IBC_ATOM), Uint128::zero());
+    assert_eq!(query_batch(&suite), None);
+}
+
+#[test]
+fn queued_deposits_are_withdrawn_by_their_sender() {
+    let mut suite = setup(10);
+    suite.liquid_stake("alice", 1000, IBC_ATOM, ALICE).unwrap();
+    suite.liquid_stake("bob", 2000, IBC_ATOM, BOB).unwrap();
+
+    let withdraw = ExecuteMsg::WithdrawQueued {
+        denom: IBC_ATOM.to_string(),
+    };
+    let err = suite.execute("stranger", &withdraw, &[]).unwrap_err();
+    assert_eq!(
+        contract_error(err),
+        ContractError::NoQueuedDeposits {
+            denom: IBC_ATOM.to_string()
+        }
+    );
+    suite.execute("alice", &withdraw, &[]).unwrap();
+    assert_eq!(suite.balance(ALICE, IBC_ATOM), Uint128::new(1000));
+    let batch = query_batch(&suite).unwrap();
+    assert_eq!(batch.total, Uint128::new(2000));
+    assert_eq!(batch.deposits.len(), 1);
+
+    suite.execute(BOB, &withdraw, &[]).unwrap();
+    assert_eq!(suite.balance(BOB, IBC_ATOM), Uint128::new(2000));
+    assert_eq!(suite.contract_balance(IBC_ATOM), Uint128::zero());
+    assert_eq!(query_batch(&suite), None);
+}
+
+#[test]
+fn batch_above_the_maximum_stays_queued() {
+    let mut suite = setup(2);
+    suite
+        .execute(
+            ADMIN,
+            &ExecuteMsg::SetDenomConfig {
+                denom: IBC_ATOM.to_string(),
+                config: Some(DenomConfig {
+                    max_amount: Some(Uint128::new(2500)),
+                    batch: Some(BatchConfig {
+                        max_amount: None,
+                        max_deposits: 2,
+                        max_age: None,
+                    }),
+                    ..DenomConfig::default_for(IBC_ATOM)
+                }),
+            },
+            &[],
+        )
+        .unwrap();
+    suite.liquid_stake("alice", 1000, IBC_ATOM, ALICE).unwrap();
+
+    // the deposit filling the batch stays queued when the batch can not be dispatched
+    let res = suite.liquid_stake("bob", 2000, IBC_ATOM, BOB).unwrap();
+    assert!(res.has_event(&Event::new("wasm").add_attribute("batch_size", "2")));
+    assert_eq!(suite.contract_balance(IBC_ATOM), Uint128::new(3000));
+    let err = suite
+        .liquid_stake("carol", 100, IBC_ATOM, "persistencecarol")
+        .unwrap_err();
+    assert_eq!(
+        contract_error(err),
+        ContractError::BatchFull {
+            denom: IBC_ATOM.to_string()
+        }
+    );
+
+    let err = suite.execute("keeper", &process_batch(), &[]).unwrap_err();
+    assert_eq!(
+        contract_error(err),
+        ContractError::AboveMaximum {
+            max: Uint128::new(2500)
+        }
+    );
+    assert_eq!(query_batch(&suite).unwrap().total, Uint128::new(3000));
+
+    let withdraw = ExecuteMsg::WithdrawQueued {
+        denom: IBC_ATOM.to_string(),
+    };
+    suite.execute("alice", &withdraw, &[]).unwrap();
+    suite.execute("keeper", &process_batch(), &[]).unwrap();
+    assert_eq!(suite.balance(BOB, STK_ATOM), Uint128::new(1800));
+}
+
+#[test]
+fn disabling_batching_refunds_the_queue() {
+    let mut suite = setup(10);
+    suite.liquid_stake("sender", 1000, IBC_ATOM, ALICE).unwrap();
+
+    let res = suite
+        .execute(
+            ADMIN,
+            &ExecuteMsg::SetDenomConfig {
+                denom: IBC_ATOM.to_string(),
+                config: Some(DenomConfig::default_for(IBC_ATOM)),
+            },
+            &[],
+        )
+        .unwrap();
+    assert!(res.has_event(&Event::new("wasm").add_attribute("refunded_deposits", "1")));
+    assert_eq!(suite.balance(ALICE, IBC_ATOM), Uint128::new(1000));
+    assert_eq!(suite.contract_balance(IBC_ATOM), Uint128::zero());
+    assert_eq!(query_batch(&suite), None);
+}
+
+#[test]
+fn keeper_processing_the_batch_is_tipped() {
+    let mut suite = setup(10);
+    suite
+        .execute(
+            ADMIN,
+            &ExecuteMsg::SetKeeperTip {
+                fee_share: Decimal::percent(20),
+                tips_per_block: 5,
+            },
+            &[],
+        )
+        .unwrap();
+    suite.liquid_stake("sender", 1000, IBC_ATOM, ALICE).unwrap();
+    suite.liquid_stake("sender", 2000, IBC_ATOM, BOB).unwrap();
+
+    // the whole batch is one tip out of the protocol's part of the fee
+    suite.execute("keeper", &process_batch(), &[]).unwrap();
+    assert_eq!(suite.balance(ALICE, STK_ATOM), Uint128::new(900));
+    assert_eq!(suite.balance(BOB, STK_ATOM), Uint128::new(1800));
+    assert_eq!(suite.balance(ADMIN, STK_ATOM), Uint128::new(240));
+    let rewards: KeeperRewardsResponse = suite
+        .query(&QueryMsg::KeeperRewards {
+            address: "keeper".to_string(),
+        })
+        .unwrap();
+    assert_eq!(rewards.rewards, coins(60, STK_ATOM));
+    assert_eq!(suite.contract_balance(STK_ATOM), Uint128::new(60));
+}
@@ -0,0 +1,73 @@
+use cosmwasm_std::{coins, Addr, Coin, Uint128};
+use ica_liquid_staking::{msg::ExecuteMsg, ContractError};
+
+use crate::suite::{contract_error, Suite, IBC_ATOM, RECEIVER, STK_ATOM};
+
+const OWNER: &str = "owner";
+
+#[test]
+fn deposit_is_staked_and_withdrawn_from_the_balance() {
+    let mut suite = Suite::new();
+    suite.fund(OWNER, 5000, IBC_ATOM);
+    suite
+        .execute(OWNER, &ExecuteMsg::Deposit {}, &coins(5000, IBC_ATOM))
+        .unwrap();
+    assert_eq!(suite.deposit_balance(OWNER), coins(5000, IBC_ATOM));
+
+    let stake = |amount: u128| ExecuteMsg::LiquidStakeFromBalance {
+        amount: Coin::new(amount, IBC_ATOM),
+        receiver: Addr::unchecked(RECEIVER),
+    };
+    suite.execute(OWNER, &stake(2000), &[]).unwrap();
+    suite.execute(OWNER, &stake(1000), &[]).unwrap();
+    assert_eq!(suite.balance(RECEIVER, STK_ATOM), Uint128::new(3000));
+    assert_eq!(suite.deposit_balance(OWNER), coins(2000, IBC_ATOM));
+
+    let err = suite.execute(OWNER, &stake(2001), &[]).unwrap_err();
+    assert_eq!(
+        contract_error(err),
+        ContractError::InsufficientDeposit {
+            available: Uint128::new(2000)
+        }
+    );
+
+    suite
+        .execute(
+            OWNER,
+            &ExecuteMsg::Withdraw {
+                amount: Coin::new(2000, IBC_ATOM),
+            },
+            &[],
+        )
+        .unwrap();
+    assert_eq!(suite.balance(OWNER, IBC_ATOM), Uint128::new(2000));
+    assert_eq!(suite.contract_balance(IBC_ATOM), Uint128::zero());
+    assert!(suite.deposit_balance(OWNER).is_empty());
+}
+
+#[test]
+fn cancelled_stake_is_credited_back_to_the_balance() {
+    let mut suite = Suite::new();
+    suite.fund(OWNER, 5000, IBC_ATOM);
+    suite
+        .execute(OWNER, &ExecuteMsg::Deposit {}, &coins(5000, IBC_ATOM))
+        .unwrap();
+
+    suite.set_pstake_failure(Some("unbonding epoch in progress"));
+    let stake = ExecuteMsg::LiquidStakeFromBalance {
+        amount: Coin::new(2000, IBC_ATOM),
+        receiver: Addr::unchecked(RECEIVER),
+    };
+    suite.execute(OWNER, &stake, &[]).unwrap();
+    suite.set_pstake_failure(None);
+    assert_eq!(suite.deposit_balance(OWNER), coins(3000, IBC_ATOM));
+
+    // the owner paid for the parked stake and takes it back into its balance
+    let cancel = ExecuteMsg::CancelStake { id: 1 };
+    let err = suite.execute("stranger", &cancel, &[]).unwrap_err();
+    assert_eq!(contract_error(err), ContractError::Unauthorized {});
+    suite.execute(OWNER, &cancel, &[]).unwrap();
+    assert_eq!(suite.deposit_balance(OWNER), coins(5000, IBC_ATOM));
+    assert_eq!(suite.balance(RECEIVER, IBC_ATOM), Uint128::zero());
+    assert_eq!(suite.contract_balance(IBC_ATOM), Uint128::new(5000));
+}
@@ -0,0 +1,413 @@
+//! Random sequences of stakes, transfers into the contract, failed subcalls and config changes,
+//! checked against a model of every balance after each step.
+//!
+//! Failing sequences are shrunk by proptest and their seeds persisted in
+//! `tests/proptest-regressions/fuzz.txt`, so a failure reproduces on the next run.
+
+use std::collections::BTreeMap;
+
+use cosmwasm_std::{coins, Addr, Decimal, Uint128};
+use ica_liquid_staking::{
+    msg::{ExecuteMsg, InstantiateMsg},
+    ContractError,
+};
+
+use proptest::{collection::vec, prelude::*, sample::Index};
+
+use crate::{
+    mock_pstake::MODULE_ACCOUNT,
+    suite::{
+        contract_error, default_instantiate_msg, Suite, ADMIN, ATOM, IBC_ATOM, STK_ATOM, STK_XPRT,
+        XPRT,
+    },
+};
+
+const CASES: u32 = 32;
+const STEPS: usize = 60;
+
+const SENDERS: [&str; 3] = ["alice", "bob", "carol"];
+const RECEIVERS: [&str; 3] = ["persistencealice", "persistencebob", "persistencecarol"];
+const TREASURY: &str = "treasury";
+const REFERRER: &str = "referrer";
+const SWEEP_RECIPIENT: &str = "sweeper";
+const REFERRER_SHARE: u64 = 50;
+const DENOMS: [&str; 4] = [IBC_ATOM, XPRT, STK_ATOM, STK_XPRT];
+
+#[derive(Clone, Debug)]
+enum Op {
+    /// Liquid stake by sender `index`, of ATOM or XPRT
+    Stake {
+        index: usize,
+        atom: bool,
+        amount: u64,
+        referral: bool,
+    },
+    /// Liquid stake subcall failing on a transient error, which parks the deposit
+    ParkedStake {
+        index: usize,
+        atom: bool,
+        amount: u64,
+        referral: bool,
+    },
+    RetryStake(Index),
+    CancelStake(Index),
+    /// Third party transfer into the contract
+    Transfer {
+        denom: usize,
+        amount: u64,
+    },
+    ProtocolFee {
+        percent: u64,
+    },
+    /// Pause or resume, a paused contract is always resumed
+    SetActive {
+        keep_active: bool,
+    },
+    /// Sweep everything not owed to users, which is only what third parties sent
+    Sweep,
+    /// c-value change on the pstake side
+    CValue {
+        atom: bool,
+        percent: u64,
+    },
+}
+
+fn op() -> impl Strategy<Value = Op> {
+    let amount = prop_oneof![1 => 10..=100u64, 4 => 100..=10_000_000u64];
+    prop_oneof![
+        5 => (0..3usize, any::<bool>(), amount, prop::bool::weighted(0.3)).prop_map(
+            |(index, atom, amount, referral)| Op::Stake {
+                index,
+                atom,
+                amount,
+                referral,
+            }
+        ),
+        1 => (0..3usize, any::<bool>(), 10..=1_000_000u64, prop::bool::weighted(0.3)).prop_map(
+            |(index, atom, amount, referral)| Op::ParkedStake {
+                index,
+                atom,
+                amount,
+                referral,
+            }
+        ),
+        1 => any::<Index>().prop_map(Op::RetryStake),
+        1 => any::<Index>().prop_map(Op::CancelStake),
+        1 => (0..DENOMS.len(), 1..=1_000_000u64)
+            .prop_map(|(denom, amount)| Op::Transfer { denom, amount }),
+        1 => (0..=20u64).prop_map(|percent| Op::ProtocolFee { percent }),
+        1 => prop::bool::weighted(0.7).prop_map(|keep_active| Op::SetActive { keep_active }),
+        1 => Just(Op::Sweep),
+        1 => (any::<bool>(), 50..=120u64).prop_map(|(atom, percent)| Op::CValue { atom, percent }),
+    ]
+}
+
+/// Expected balances, contract parameters and mock pstake c-values
+struct Model {
+    balances: BTreeMap<(String, String), Uint128>,
+    protocol_fee: Decimal,
+    active: bool,
+    c_values: BTreeMap<&'static str, Decimal>,
+    /// Deposits parked after a failed stake by id, with the sender index and referral flag
+    failed_stakes: BTreeMap<u64, (usize, &'static str, Uint128, bool)>,
+    failed_stake_seq: u64,
+}
+
+impl Model {
+    fn add(&mut self, address: &str, denom: &str, amount: Uint128) {
+        *self
+            .balances
+            .entry((address.to_string(), denom.to_string()))
+            .or_default() += amount;
+    }
+
+    fn sub(&mut self, address: &str, denom: &str, amount: Uint128) {
+        let balance = self
+            .balances
+            .entry((address.to_string(), denom.to_string()))
+            .or_default();
+        *balance = balance.checked_sub(amount).unwrap();
+    }
+
+    /// Part of the contract balance held for parked stakes
+    fn parked(&self, denom: &str) -> Uint128 {
+        self.failed_stakes
+            .values()
+            .filter(|(_, parked_denom, _, _)| *parked_denom == denom)
+            .map(|(_, _, amount, _)| *amount)
+            .sum()
+    }
+
+    /// Expected payouts of a successful stake
+    fn stake(
+        &mut self,
+        from: &str,
+        index: usize,
+        denoms: (&str, &str, &str),
+        amount: Uint128,
+        referral: bool,
+    ) {
+        let (denom, base_denom, ls_token_denom) = denoms;
+        let minted = amount * self.c_values[base_denom];
+        let fee = minted * self.protocol_fee;
+        let referrer_fee = if referral {
+            fee * Decimal::percent(REFERRER_SHARE)
+        } else {
+            Uint128::zero()
+        };
+        self.sub(from, denom, amount);
+        self.add(MODULE_ACCOUNT, denom, amount);
+        self.add(RECEIVERS[index], ls_token_denom, minted - fee);
+        self.add(REFERRER, ls_token_denom, referrer_fee);
+        self.add(TREASURY, ls_token_denom, fee - referrer_fee);
+    }
+
+    fn assert_matches(&self, suite: &Suite, context: &str) {
+        for ((address, denom), expected) in &self.balances {
+            assert_eq!(
+                suite.balance(address, denom),
+                *expected,
+                "{context}: balance of {address} in {denom}"
+            );
+        }
+    }
+}
+
+fn ls_denoms(denom: &str) -> (&'static str, &'static str, &'static str) {
+    if denom == IBC_ATOM {
+        (IBC_ATOM, ATOM, STK_ATOM)
+    } else {
+        (XPRT, XPRT, STK_XPRT)
+    }
+}
+
+/// Native denom, its base denom and LS token
+fn stake_denom(atom: bool) -> (&'static str, &'static str, &'static str) {
+    if atom {
+        (IBC_ATOM, ATOM, STK_ATOM)
+    } else {
+        (XPRT, XPRT, STK_XPRT)
+    }
+}
+
+fn liquid_stake_msg(receiver: &str, referral: bool) -> ExecuteMsg {
+    ExecuteMsg::LiquidStake {
+        receiver: Addr::unchecked(receiver),
+        referral: referral.then(|| "wallet".to_string()),
+        post_action: None,
+    }
+}
+
+fn update_config(active: Option<bool>, protocol_fee: Option<Decimal>) -> ExecuteMsg {
+    ExecuteMsg::UpdateConfig {
+        active,
+        ls_prefix: None,
+        protocol_fee,
+        fee_recipient: None,
+        history_limit: None,
+        swap_router: None,
+        verify_ls_prefix: None,
+        config_timelock: None,
+    }
+}
+
+fn run(ops: Vec<Op>) {
+    let mut suite = Suite::with_instantiate(InstantiateMsg {
+        fee_recipient: Some(TREASURY.to_string()),
+        ..default_instantiate_msg()
+    });
+    suite
+        .execute(
+            ADMIN,
+            &ExecuteMsg::RegisterReferrer {
+                code: "wallet".to_string(),
+                address: REFERRER.to_string(),
+                fee_share: Some(Decimal::percent(REFERRER_SHARE)),
+            },
+            &[],
+        )
+        .unwrap();
+    let contract = suite.contract.to_string();
+
+    let mut model = Model {
+        balances: BTreeMap::new(),
+        protocol_fee: Decimal::zero(),
+        active: true,
+        c_values: BTreeMap::from([(ATOM, Decimal::one()), (XPRT, Decimal::one())]),
+        failed_stakes: BTreeMap::new(),
+        failed_stake_seq: 0,
+    };
+    // the contract only holds parked deposits and what was transferred in by third parties
+    for denom in DENOMS {
+        model.add(&contract, denom, Uint128::zero());
+    }
+
+    for (step, op) in ops.into_iter().enumerate() {
+        let context = format!("step {step} {op:?}");
+        match op {
+            Op::Stake {
+                index,
+                atom,
+                amount,
+                referral,
+            } => {
+                let denoms = stake_denom(atom);
+                let amount = Uint128::from(amount);
+
+                suite.fund(SENDERS[index], amount.u128(), denoms.0);
+                model.add(SENDERS[index], denoms.0, amount);
+                let res = suite.execute(
+                    SENDERS[index],
+                    &liquid_stake_msg(RECEIVERS[index], referral),
+                    &coins(amount.u128(), denoms.0),
+                );
+
+                if !model.active {
+                    let err = res.expect_err(&context);
+                    assert_eq!(
+                        contract_error(err),
+                        ContractError::NotActive {},
+                        "{context}"
+                    );
+                } else {
+                    res.unwrap_or_else(|err| panic!("{context}: {err:?}"));
+                    model.stake(SENDERS[index], index, denoms, amount, referral);
+                }
+            }
+            Op::ParkedStake {
+                index,
+                atom,
+                amount,
+                referral,
+            } => {
+                let (denom, _, _) = stake_denom(atom);
+                let amount = Uint128::from(amount);
+
+                suite.set_pstake_failure(Some("unbonding epoch in progress"));
+                suite.fund(SENDERS[index], amount.u128(), denom);
+                model.add(SENDERS[index], denom, amount);
+                let res = suite.execute(
+                    SENDERS[index],
+                    &liquid_stake_msg(RECEIVERS[index], referral),
+                    &coins(amount.u128(), denom),
+                );
+                suite.set_pstake_failure(None);
+
+                if !model.active {
+                    let err = res.expect_err(&context);
+                    assert_eq!(
+                        contract_error(err),
+                        ContractError::NotActive {},
+                        "{context}"
+                    );
+                } else {
+                    res.unwrap_or_else(|err| panic!("{context}: {err:?}"));
+                    model.failed_stake_seq += 1;
+                    model
+                        .failed_stakes
+                        .insert(model.failed_stake_seq, (index, denom, amount, referral));
+                    model.sub(SENDERS[index], denom, amount);
+                    model.add(&contract, denom, amount);
+                }
+            }
+            // nothing parked to pick from
+            Op::RetryStake(_) | Op::CancelStake(_) if model.failed_stakes.is_empty() => {}
+            Op::RetryStake(pick) => {
+                let ids: Vec<u64> = model.failed_stakes.keys().copied().collect();
+                let id = *pick.get(&ids);
+                let (index, denom, amount, referral) = model.failed_stakes[&id];
+
+                let res = suite.execute("keeper", &ExecuteMsg::RetryStake { id }, &[]);
+                if !model.active {
+                    let err = res.expect_err(&context);
+                    assert_eq!(
+                        contract_error(err),
+                        ContractError::NotActive {},
+                        "{context}"
+                    );
+                } else {
+                    res.unwrap_or_else(|err| panic!("{context}: {err:?}"));
+                    model.stake(&contract, index, ls_denoms(denom), amount, referral);
+                    model.failed_stakes.remove(&id);
+                }
+            }
+            Op::CancelStake(pick) => {
+                let ids: Vec<u64> = model.failed_stakes.keys().copied().collect();
+                let id = *pick.get(&ids);
+                let (index, denom, amount, _) = model.failed_stakes[&id];
+
+                suite
+                    .execute(RECEIVERS[index], &ExecuteMsg::CancelStake { id }, &[])
+                    .unwrap_or_else(|err| panic!("{context}: {err:?}"));
+                model.sub(&contract, denom, amount);
+                model.add(RECEIVERS[index], denom, amount);
+                model.failed_stakes.remove(&id);
+            }
+            Op::Transfer { denom, amount } => {
+                let denom = DENOMS[denom];
+                let amount = Uint128::from(amount);
+                suite.fund(&contract, amount.u128(), denom);
+                model.add(&contract, denom, amount);
+            }
+            Op::ProtocolFee { percent } => {
+                let protocol_fee = Decimal::percent(percent);
+                suite
+                    .execute(ADMIN, &update_config(None, Some(protocol_fee)), &[])
+                    .unwrap();
+                model.protocol_fee = protocol_fee;
+            }
+            Op::SetActive { keep_active } => {
+                let active = !model.active || keep_active;
+                suite
+                    .execute(ADMIN, &update_config(Some(active), None), &[])
+                    .unwrap();
+                model.active = active;
+            }
+            Op::Sweep => {
+                let empty = DENOMS
+                    .iter()
+                    .all(|denom| suite.balance(&contract, denom) == model.parked(denom));
+                let res = suite.execute(
+                    ADMIN,
+                    &ExecuteMsg::Sweep {
+                        denoms: DENOMS.iter().map(|denom| denom.to_string()).collect(),
+                        to: SWEEP_RECIPIENT.to_string(),
+                    },
+                    &[],
+                );
+                if empty {
+                    assert_eq!(
+                        contract_error(res.expect_err(&context)),
+                        ContractError::NothingToSweep {},
+                        "{context}"
+                    );
+                } else {
+                    res.unwrap_or_else(|err| panic!("{context}: {err:?}"));
+                }
+                for denom in DENOMS {
+                    let amount = model.balances[&(contract.clone(), denom.to_string())]
+                        - model.parked(denom);
+                    model.sub(&contract, denom, amount);
+                    model.add(SWEEP_RECIPIENT, denom, amount);
+                }
+            }
+            Op::CValue { atom, percent } => {
+                let base_denom = if atom { ATOM } else { XPRT };
+                let c_value = Decimal::percent(percent);
+                suite.set_c_value(base_denom, c_value);
+                model.c_values.insert(base_denom, c_value);
+            }
+        }
+
+        model.assert_matches(&suite, &context);
+    }
+}
+
+proptest! {
+    #![proptest_config(ProptestConfig::with_cases(CASES))]
+
+    #[test]
+    fn random_operations_keep_balances_consistent(ops in vec(op(), 1..=STEPS)) {
+        run(ops);
+    }
+}
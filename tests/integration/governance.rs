@@ -0,0 +1,88 @@
+use cosmwasm_std::{Addr, Decimal};
+use cw_multi_test::Executor;
+use ica_liquid_staking::{
+    msg::{ExecuteMsg, GovernanceInfoResponse, QueryMsg},
+    ContractError,
+};
+
+use crate::{
+    mock_dex::{router_contract, RouterInstantiateMsg},
+    suite::{contract_error, Suite, ADMIN},
+};
+
+/// Stand-in for a multisig, any instantiated contract passes the contract info check
+fn instantiate_dao(suite: &mut Suite) -> Addr {
+    let code_id = suite.app.store_code(router_contract());
+    suite
+        .app
+        .instantiate_contract(
+            code_id,
+            Addr::unchecked(ADMIN),
+            &RouterInstantiateMsg {
+                rate: Decimal::one(),
+            },
+            &[],
+            "dao",
+            None,
+        )
+        .unwrap()
+}
+
+fn pause() -> ExecuteMsg {
+    ExecuteMsg::UpdateConfig {
+        active: Some(false),
+        ls_prefix: None,
+        protocol_fee: None,
+        fee_recipient: None,
+        history_limit: None,
+        swap_router: None,
+        verify_ls_prefix: None,
+        config_timelock: None,
+    }
+}
+
+#[test]
+fn governance_must_be_a_contract() {
+    let mut suite = Suite::new();
+
+    let err = suite
+        .execute(
+            ADMIN,
+            &ExecuteMsg::SetGovernance {
+                contract: Some("multisig".to_string()),
+                proposal_required: true,
+            },
+            &[],
+        )
+        .unwrap_err();
+    assert_eq!(
+        contract_error(err),
+        ContractError::NotAContract {
+            address: "multisig".to_string()
+        }
+    );
+}
+
+#[test]
+fn proposal_required_mode_locks_out_the_admin_key() {
+    let mut suite = Suite::new();
+    let dao = instantiate_dao(&mut suite);
+    suite
+        .execute(
+            ADMIN,
+            &ExecuteMsg::SetGovernance {
+                contract: Some(dao.to_string()),
+                proposal_required: true,
+            },
+            &[],
+        )
+        .unwrap();
+
+    let err = suite.execute(ADMIN, &pause(), &[]).unwrap_err();
+    assert_eq!(contract_error(err), ContractError::Unauthorized {});
+    suite.execute(dao.as_str(), &pause(), &[]).unwrap();
+
+    let info: GovernanceInfoResponse = suite.query(&QueryMsg::GovernanceInfo {}).unwrap();
+    assert_eq!(info.contract, Some(dao));
+    assert!(info.proposal_required);
+}
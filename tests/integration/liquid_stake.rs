@@ -0,0 +1,357 @@
+use cosmwasm_std::{coins, Addr, Decimal, Event, Uint128};
+use ica_liquid_staking::{
+    msg::{
+        ExecuteMsg, FailedStakesResponse, InstantiateMsg, KeeperRewardsResponse, QueryMsg,
+        ReferrerStatsResponse, StakeHistoryResponse, StatusResponse,
+    },
+    ContractError,
+};
+
+use crate::{
+    mock_pstake::MODULE_ACCOUNT,
+    suite::{
+        contract_error, default_instantiate_msg, Suite, ADMIN, ATOM, COSMOSHUB, IBC_ATOM, RECEIVER,
+        STK_ATOM, STK_XPRT, XPRT,
+    },
+};
+
+#[test]
+fn liquid_stake_sends_minted_tokens_to_receiver() {
+    let mut suite = Suite::new();
+    suite.set_c_value(ATOM, Decimal::percent(90));
+
+    suite
+        .liquid_stake("osmo_hooks", 1000, IBC_ATOM, RECEIVER)
+        .unwrap();
+
+    assert_eq!(suite.balance(RECEIVER, STK_ATOM), Uint128::new(900));
+    assert_eq!(suite.balance(MODULE_ACCOUNT, IBC_ATOM), Uint128::new(1000));
+    assert_eq!(suite.contract_balance(IBC_ATOM), Uint128::zero());
+    assert_eq!(suite.contract_balance(STK_ATOM), Uint128::zero());
+
+    let history: StakeHistoryResponse = suite
+        .query(&QueryMsg::StakeHistory {
+            address: RECEIVER.to_string(),
+            start_after: None,
+            limit: None,
+        })
+        .unwrap();
+    assert_eq!(history.stakes.len(), 1);
+    assert_eq!(history.stakes[0].native_denom, IBC_ATOM);
+    assert_eq!(history.stakes[0].native_amount, Uint128::new(1000));
+    assert_eq!(history.stakes[0].ls_token_amount, Uint128::new(900));
+
+    let status: StatusResponse = suite.query(&QueryMsg::Status {}).unwrap();
+    assert_eq!(status.stake_in_flight, None);
+    assert_eq!(status.stake_count, 1);
+}
+
+#[test]
+fn liquid_stake_native_xprt() {
+    let mut suite = Suite::new();
+
+    suite.liquid_stake("sender", 500, XPRT, RECEIVER).unwrap();
+    assert_eq!(suite.balance(RECEIVER, STK_XPRT), Uint128::new(500));
+
+    // other native denoms have no provider
+    let err = suite
+        .liquid_stake("sender", 500, "uusdc", RECEIVER)
+        .unwrap_err();
+    assert_eq!(
+        contract_error(err),
+        ContractError::InvalidDenom {
+            denom: "uusdc".to_string()
+        }
+    );
+}
+
+#[test]
+fn protocol_fee_and_referral_are_paid_out() {
+    let mut suite = Suite::with_instantiate(InstantiateMsg {
+        protocol_fee: Some(Decimal::percent(10)),
+        fee_recipient: Some("treasury".to_string()),
+        ..default_instantiate_msg()
+    });
+    suite
+        .execute(
+            "admin",
+            &ExecuteMsg::RegisterReferrer {
+                code: "wallet".to_string(),
+                address: "referrer".to_string(),
+                fee_share: Some(Decimal::percent(50)),
+            },
+            &[],
+        )
+        .unwrap();
+
+    suite.fund("sender", 1000, IBC_ATOM);
+    suite
+        .execute(
+            "sender",
+            &ExecuteMsg::LiquidStake {
+                receiver: Addr::unchecked(RECEIVER),
+                referral: Some("wallet".to_string()),
+                post_action: None,
+            },
+            &coins(1000, IBC_ATOM),
+        )
+        .unwrap();
+
+    assert_eq!(suite.balance(RECEIVER, STK_ATOM), Uint128::new(900));
+    assert_eq!(suite.balance("referrer", STK_ATOM), Uint128::new(50));
+    assert_eq!(suite.balance("treasury", STK_ATOM), Uint128::new(50));
+    assert_eq!(suite.contract_balance(STK_ATOM), Uint128::zero());
+
+    let stats: ReferrerStatsResponse = suite
+        .query(&QueryMsg::ReferrerStats {
+            code: "wallet".to_string(),
+        })
+        .unwrap();
+    assert_eq!(stats.stake_count, 1);
+    assert_eq!(stats.volume, coins(1000, IBC_ATOM));
+    assert_eq!(stats.fees_paid, coins(50, STK_ATOM));
+}
+
+#[test]
+fn failed_liquid_stake_reverts_the_transfer() {
+    let mut suite = Suite::new();
+    suite.set_pstake_failure(Some("out of gas"));
+
+    // an unknown failure reverts the transaction
+    let err = suite
+        .liquid_stake("sender", 1000, IBC_ATOM, RECEIVER)
+        .unwrap_err();
+    // the mock error may carry a backtrace after the message
+    assert!(matches!(
+        contract_error(err),
+        ContractError::SubcallError(msg) if msg.starts_with("out of gas")
+    ));
+
+    // the whole transaction is rolled back, the sender keeps its tokens
+    assert_eq!(suite.balance("sender", IBC_ATOM), Uint128::new(1000));
+    assert_eq!(suite.contract_balance(IBC_ATOM), Uint128::zero());
+    let history: StakeHistoryResponse = suite
+        .query(&QueryMsg::StakeHistory {
+            address: RECEIVER.to_string(),
+            start_after: None,
+            limit: None,
+        })
+        .unwrap();
+    assert!(history.stakes.is_empty());
+
+    // nothing is left behind for the next stake
+    suite.set_pstake_failure(None);
+    suite
+        .liquid_stake("sender", 1000, IBC_ATOM, RECEIVER)
+        .unwrap();
+    assert_eq!(suite.balance(RECEIVER, STK_ATOM), Uint128::new(1000));
+}
+
+#[test]
+fn transient_failure_parks_the_stake_for_a_retry() {
+    let mut suite = Suite::new();
+    suite.set_pstake_failure(Some("host chain is not active"));
+
+    let res = suite
+        .liquid_stake("sender", 1000, IBC_ATOM, RECEIVER)
+        .unwrap();
+    assert!(res.has_event(
+        &Event::new("wasm")
+            .add_attribute("status", "failed")
+            .add_attribute("failed_stake_id", "1")
+    ));
+    assert_eq!(suite.contract_balance(IBC_ATOM), Uint128::new(1000));
+    let query = QueryMsg::FailedStakes {
+        start_after: None,
+        limit: None,
+    };
+    let failed: FailedStakesResponse = suite.query(&query).unwrap();
+    assert_eq!(failed.stakes.len(), 1);
+    assert_eq!(failed.stakes[0].coin, coins(1000, IBC_ATOM)[0]);
+
+    // the parked deposit is owed to the receiver and can not be swept
+    let err = suite
+        .execute(
+            ADMIN,
+            &ExecuteMsg::Sweep {
+                denoms: vec![IBC_ATOM.to_string()],
+                to: ADMIN.to_string(),
+            },
+            &[],
+        )
+        .unwrap_err();
+    assert_eq!(contract_error(err), ContractError::NothingToSweep {});
+
+    // a retry failing again reverts and keeps the stake parked
+    let retry = ExecuteMsg::RetryStake { id: 1 };
+    let err = suite.execute("keeper", &retry, &[]).unwrap_err();
+    assert_eq!(
+        contract_error(err),
+        ContractError::HostChainDisabled {
+            codespace: String::new(),
+            code: 0
+        }
+    );
+    let failed: FailedStakesResponse = suite.query(&query).unwrap();
+    assert_eq!(failed.stakes.len(), 1);
+
+    // any keeper can retry once the module recovered
+    suite.set_pstake_failure(None);
+    suite.execute("keeper", &retry, &[]).unwrap();
+    assert_eq!(suite.balance(RECEIVER, STK_ATOM), Uint128::new(1000));
+    assert_eq!(suite.contract_balance(IBC_ATOM), Uint128::zero());
+    let failed: FailedStakesResponse = suite.query(&query).unwrap();
+    assert!(failed.stakes.is_empty());
+}
+
+#[test]
+fn keeper_is_tipped_from_the_protocol_fee() {
+    let mut suite = Suite::with_instantiate(InstantiateMsg {
+        protocol_fee: Some(Decimal::percent(10)),
+        ..default_instantiate_msg()
+    });
+    suite
+        .execute(
+            ADMIN,
+            &ExecuteMsg::SetKeeperTip {
+                fee_share: Decimal::percent(20),
+                tips_per_block: 5,
+            },
+            &[],
+        )
+        .unwrap();
+    suite.set_pstake_failure(Some("unbonding epoch in progress"));
+    suite
+        .liquid_stake("sender", 1000, IBC_ATOM, RECEIVER)
+        .unwrap();
+    suite.set_pstake_failure(None);
+
+    suite
+        .execute("keeper", &ExecuteMsg::RetryStake { id: 1 }, &[])
+        .unwrap();
+    assert_eq!(suite.balance(RECEIVER, STK_ATOM), Uint128::new(900));
+    assert_eq!(suite.balance(ADMIN, STK_ATOM), Uint128::new(80));
+    assert_eq!(suite.contract_balance(STK_ATOM), Uint128::new(20));
+
+    // unclaimed tips are owed to the keeper
+    let err = suite
+        .execute(
+            ADMIN,
+            &ExecuteMsg::Sweep {
+                denoms: vec![STK_ATOM.to_string()],
+                to: ADMIN.to_string(),
+            },
+            &[],
+        )
+        .unwrap_err();
+    assert_eq!(contract_error(err), ContractError::NothingToSweep {});
+
+    let query = QueryMsg::KeeperRewards {
+        address: "keeper".to_string(),
+    };
+    let rewards: KeeperRewardsResponse = suite.query(&query).unwrap();
+    assert_eq!(rewards.rewards, coins(20, STK_ATOM));
+
+    suite
+        .execute("keeper", &ExecuteMsg::ClaimKeeperRewards {}, &[])
+        .unwrap();
+    assert_eq!(suite.balance("keeper", STK_ATOM), Uint128::new(20));
+    assert_eq!(suite.contract_balance(STK_ATOM), Uint128::zero());
+    let rewards: KeeperRewardsResponse = suite.query(&query).unwrap();
+    assert!(rewards.rewards.is_empty());
+}
+
+#[test]
+fn receiver_can_cancel_a_parked_stake() {
+    let mut suite = Suite::new();
+    suite.set_pstake_failure(Some("unbonding epoch in progress"));
+    suite
+        .liquid_stake("sender", 1000, IBC_ATOM, RECEIVER)
+        .unwrap();
+
+    let cancel = ExecuteMsg::CancelStake { id: 1 };
+    let err = suite.execute("stranger", &cancel, &[]).unwrap_err();
+    assert_eq!(contract_error(err), ContractError::Unauthorized {});
+
+    suite.execute(RECEIVER, &cancel, &[]).unwrap();
+    assert_eq!(suite.balance(RECEIVER, IBC_ATOM), Uint128::new(1000));
+    assert_eq!(suite.contract_balance(IBC_ATOM), Uint128::zero());
+
+    let err = suite
+        .execute("keeper", &ExecuteMsg::RetryStake { id: 1 }, &[])
+        .unwrap_err();
+    assert_eq!(
+        contract_error(err),
+        ContractError::UnknownFailedStake { id: 1 }
+    );
+}
+
+#[test]
+fn rejected_deposit_is_refunded_to_receiver() {
+    let mut suite = Suite::new();
+    suite.set_pstake_failure(Some("deposit amount less than minimum deposit"));
+
+    let res = suite
+        .liquid_stake("sender", 1000, IBC_ATOM, RECEIVER)
+        .unwrap();
+    assert!(res.has_event(
+        &Event::new("wasm")
+            .add_attribute("status", "refunded")
+            .add_attribute("receiver", RECEIVER)
+    ));
+
+    assert_eq!(suite.balance(RECEIVER, IBC_ATOM), Uint128::new(1000));
+    assert_eq!(suite.balance("sender", IBC_ATOM), Uint128::zero());
+    assert_eq!(suite.contract_balance(IBC_ATOM), Uint128::zero());
+    let status: StatusResponse = suite.query(&QueryMsg::Status {}).unwrap();
+    assert_eq!(status.stake_in_flight, None);
+    assert_eq!(status.stake_count, 0);
+}
+
+#[test]
+fn inactive_host_chain_is_rejected_before_dispatch() {
+    let mut suite = Suite::new();
+    suite.set_host_chain_active(COSMOSHUB, false);
+
+    let err = suite
+        .liquid_stake("sender", 1000, IBC_ATOM, RECEIVER)
+        .unwrap_err();
+    assert_eq!(
+        contract_error(err),
+        ContractError::HostChainInactive {
+            chain_id: COSMOSHUB.to_string()
+        }
+    );
+
+    // native XPRT does not depend on a host chain
+    suite.liquid_stake("sender", 1000, XPRT, RECEIVER).unwrap();
+    assert_eq!(suite.balance(RECEIVER, STK_XPRT), Uint128::new(1000));
+}
+
+#[test]
+fn stakes_in_one_block_only_pay_out_their_own_mint() {
+    let mut suite = Suite::new();
+    suite.set_c_value(ATOM, Decimal::percent(80));
+
+    // LS tokens already held by the contract are not paid out to stakers
+    let contract = suite.contract.to_string();
+    suite.fund(&contract, 500, STK_ATOM);
+
+    suite
+        .liquid_stake("alice", 1000, IBC_ATOM, "persistencealice")
+        .unwrap();
+    suite
+        .liquid_stake("bob", 3000, IBC_ATOM, "persistencebob")
+        .unwrap();
+
+    assert_eq!(
+        suite.balance("persistencealice", STK_ATOM),
+        Uint128::new(800)
+    );
+    assert_eq!(
+        suite.balance("persistencebob", STK_ATOM),
+        Uint128::new(2400)
+    );
+    assert_eq!(suite.contract_balance(STK_ATOM), Uint128::new(500));
+    assert_eq!(suite.balance(MODULE_ACCOUNT, IBC_ATOM), Uint128::new(4000));
+}
@@ -0,0 +1,16 @@
+//! End-to-end tests of the contract on cw-multi-test, with the pstake chain modules mocked
+mod mock_dex;
+mod mock_nft;
+mod mock_pstake;
+mod suite;
+
+mod batch;
+mod deposit;
+mod fuzz;
+mod governance;
+mod liquid_stake;
+mod operator;
+mod post_action;
+mod receipt;
+mod schedule;
+mod swap;
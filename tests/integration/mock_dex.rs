@@ -0,0 +1,160 @@
+//! Minimal Dexter router and vault contracts accepting the messages sent by the contract
+
+use cosmwasm_schema::cw_serde;
+use cosmwasm_std::{
+    coin, BankMsg, Binary, Decimal, Deps, DepsMut, Empty, Env, MessageInfo, Response, StdError,
+    StdResult, Uint128,
+};
+use cw_multi_test::{Contract, ContractWrapper};
+use cw_storage_plus::Item;
+use cw_utils::one_coin;
+
+use crate::mock_pstake::{PstakeMsg, PstakeQuery};
+
+#[cw_serde]
+pub enum AssetInfo {
+    NativeToken { denom: String },
+}
+
+#[cw_serde]
+pub struct Asset {
+    pub info: AssetInfo,
+    pub amount: Uint128,
+}
+
+#[cw_serde]
+pub struct HopSwapRequest {
+    pub pool_id: Uint128,
+    pub asset_in: AssetInfo,
+    pub asset_out: AssetInfo,
+}
+
+#[cw_serde]
+pub struct RouterInstantiateMsg {
+    /// ask amount paid per offered unit, on every hop
+    pub rate: Decimal,
+}
+
+#[cw_serde]
+pub enum RouterExecuteMsg {
+    ExecuteMultihopSwap {
+        requests: Vec<HopSwapRequest>,
+        offer_amount: Uint128,
+        recipient: Option<String>,
+        minimum_receive: Option<Uint128>,
+    },
+}
+
+#[cw_serde]
+pub struct VaultInstantiateMsg {
+    pub pool_ids: Vec<Uint128>,
+}
+
+#[cw_serde]
+pub enum VaultExecuteMsg {
+    JoinPool {
+        pool_id: Uint128,
+        recipient: Option<String>,
+        assets: Option<Vec<Asset>>,
+        min_lp_to_receive: Option<Uint128>,
+        auto_stake: Option<bool>,
+    },
+}
+
+const RATE: Item<Decimal> = Item::new("rate");
+const POOL_IDS: Item<Vec<Uint128>> = Item::new("pool_ids");
+
+fn router_instantiate(
+    deps: DepsMut<PstakeQuery>,
+    _env: Env,
+    _info: MessageInfo,
+    msg: RouterInstantiateMsg,
+) -> StdResult<Response<PstakeMsg>> {
+    RATE.save(deps.storage, &msg.rate)?;
+    Ok(Response::new())
+}
+
+/// Pays out of the router's own balance, so it has to be funded with the ask denom
+fn router_execute(
+    deps: DepsMut<PstakeQuery>,
+    _env: Env,
+    info: MessageInfo,
+    msg: RouterExecuteMsg,
+) -> StdResult<Response<PstakeMsg>> {
+    let RouterExecuteMsg::ExecuteMultihopSwap {
+        requests,
+        offer_amount,
+        recipient,
+        minimum_receive,
+    } = msg;
+    let offer = one_coin(&info).map_err(|err| StdError::generic_err(err.to_string()))?;
+    if offer.amount != offer_amount {
+        return Err(StdError::generic_err("offer amount mismatch"));
+    }
+    let Some(AssetInfo::NativeToken { denom: ask_denom }) =
+        requests.last().map(|hop| hop.asset_out.clone())
+    else {
+        return Err(StdError::generic_err("empty route"));
+    };
+
+    let rate = RATE.load(deps.storage)?;
+    let ask_amount = requests
+        .iter()
+        .fold(offer_amount, |amount, _| amount * rate);
+    if ask_amount < minimum_receive.unwrap_or_default() {
+        return Err(StdError::generic_err("slippage tolerance exceeded"));
+    }
+
+    Ok(Response::new().add_message(BankMsg::Send {
+        to_address: recipient.unwrap_or_else(|| info.sender.to_string()),
+        amount: vec![coin(ask_amount.u128(), ask_denom)],
+    }))
+}
+
+fn vault_instantiate(
+    deps: DepsMut<PstakeQuery>,
+    _env: Env,
+    _info: MessageInfo,
+    msg: VaultInstantiateMsg,
+) -> StdResult<Response<PstakeMsg>> {
+    POOL_IDS.save(deps.storage, &msg.pool_ids)?;
+    Ok(Response::new())
+}
+
+/// Keeps the joined assets, only failing for unknown pools
+fn vault_execute(
+    deps: DepsMut<PstakeQuery>,
+    _env: Env,
+    _info: MessageInfo,
+    msg: VaultExecuteMsg,
+) -> StdResult<Response<PstakeMsg>> {
+    let VaultExecuteMsg::JoinPool {
+        pool_id, recipient, ..
+    } = msg;
+    if !POOL_IDS.load(deps.storage)?.contains(&pool_id) {
+        return Err(StdError::generic_err(format!("pool {pool_id} not found")));
+    }
+    Ok(Response::new()
+        .add_attribute("action", "join_pool")
+        .add_attribute("recipient", recipient.unwrap_or_default()))
+}
+
+fn query(_deps: Deps<PstakeQuery>, _env: Env, _msg: Empty) -> StdResult<Binary> {
+    Err(StdError::generic_err("no queries"))
+}
+
+pub fn router_contract() -> Box<dyn Contract<PstakeMsg, PstakeQuery>> {
+    Box::new(ContractWrapper::new(
+        router_execute,
+        router_instantiate,
+        query,
+    ))
+}
+
+pub fn vault_contract() -> Box<dyn Contract<PstakeMsg, PstakeQuery>> {
+    Box::new(ContractWrapper::new(
+        vault_execute,
+        vault_instantiate,
+        query,
+    ))
+}
@@ -0,0 +1,120 @@
+//! Minimal cw721 contract minting receipt tokens for a single minter
+
+use cosmwasm_schema::cw_serde;
+use cosmwasm_std::{
+    to_json_binary, Binary, Deps, DepsMut, Empty, Env, MessageInfo, Response, StdError, StdResult,
+};
+use cw_multi_test::{Contract, ContractWrapper};
+use cw_storage_plus::{Item, Map};
+use ica_liquid_staking::msg::Receipt;
+
+use crate::mock_pstake::{PstakeMsg, PstakeQuery};
+
+#[cw_serde]
+pub struct NftInstantiateMsg {
+    pub minter: String,
+}
+
+#[cw_serde]
+pub enum NftExecuteMsg {
+    Mint {
+        token_id: String,
+        owner: String,
+        token_uri: Option<String>,
+        extension: Box<Receipt>,
+    },
+    Burn {
+        token_id: String,
+    },
+}
+
+#[cw_serde]
+pub enum NftQueryMsg {
+    OwnerOf {
+        token_id: String,
+        include_expired: Option<bool>,
+    },
+    NftInfo {
+        token_id: String,
+    },
+}
+
+#[cw_serde]
+pub struct OwnerOfResponse {
+    pub owner: String,
+    pub approvals: Vec<Empty>,
+}
+
+#[cw_serde]
+pub struct NftInfoResponse {
+    pub token_uri: Option<String>,
+    pub extension: Receipt,
+}
+
+const MINTER: Item<String> = Item::new("minter");
+const TOKENS: Map<&str, (String, Receipt)> = Map::new("tokens");
+
+fn instantiate(
+    deps: DepsMut<PstakeQuery>,
+    _env: Env,
+    _info: MessageInfo,
+    msg: NftInstantiateMsg,
+) -> StdResult<Response<PstakeMsg>> {
+    MINTER.save(deps.storage, &msg.minter)?;
+    Ok(Response::new())
+}
+
+fn execute(
+    deps: DepsMut<PstakeQuery>,
+    _env: Env,
+    info: MessageInfo,
+    msg: NftExecuteMsg,
+) -> StdResult<Response<PstakeMsg>> {
+    match msg {
+        NftExecuteMsg::Mint {
+            token_id,
+            owner,
+            extension,
+            ..
+        } => {
+            if info.sender != MINTER.load(deps.storage)? {
+                return Err(StdError::generic_err("minter only"));
+            }
+            if TOKENS.has(deps.storage, &token_id) {
+                return Err(StdError::generic_err("token already minted"));
+            }
+            TOKENS.save(deps.storage, &token_id, &(owner, *extension))?;
+        }
+        NftExecuteMsg::Burn { token_id } => {
+            let (owner, _) = TOKENS.load(deps.storage, &token_id)?;
+            if info.sender != owner {
+                return Err(StdError::generic_err("owner only"));
+            }
+            TOKENS.remove(deps.storage, &token_id);
+        }
+    }
+    Ok(Response::new())
+}
+
+fn query(deps: Deps<PstakeQuery>, _env: Env, msg: NftQueryMsg) -> StdResult<Binary> {
+    match msg {
+        NftQueryMsg::OwnerOf { token_id, .. } => {
+            let (owner, _) = TOKENS.load(deps.storage, &token_id)?;
+            to_json_binary(&OwnerOfResponse {
+                owner,
+                approvals: vec![],
+            })
+        }
+        NftQueryMsg::NftInfo { token_id } => {
+            let (_, extension) = TOKENS.load(deps.storage, &token_id)?;
+            to_json_binary(&NftInfoResponse {
+                token_uri: None,
+                extension,
+            })
+        }
+    }
+}
+
+pub fn nft_contract() -> Box<dyn Contract<PstakeMsg, PstakeQuery>> {
+    Box::new(ContractWrapper::new(execute, instantiate, query))
+}
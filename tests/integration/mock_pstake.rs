@@ -0,0 +1,319 @@
+//! Custom multi-test module standing in for the pstake chain modules.
+//!
+//! Stargate messages and queries are forwarded to it as `PstakeMsg` / `PstakeQuery`
+//! (see `suite.rs`). It simulates `MsgLiquidStake` of both liquidstakeibc and
+//! liquidstake, the ibc transfer `DenomTrace` query and the liquidstakeibc `HostChains` and
+//! `Params` queries.
+
+use anyhow::{bail, Result as AnyResult};
+use cosmwasm_schema::cw_serde;
+use cosmwasm_std::{
+    to_json_binary, Addr, Api, BankMsg, Binary, BlockInfo, Coin, CustomQuery, Decimal, Order,
+    Querier, Storage, Uint128,
+};
+use cw_multi_test::{AppResponse, BankSudo, CosmosRouter, Module};
+use cw_storage_plus::{Item, Map};
+use ica_liquid_staking::{
+    execute::{DENOM_TRACE_QUERY_TYPE, HOST_CHAINS_QUERY_TYPE, LIQUIDSTAKEIBC_PARAMS_QUERY_TYPE},
+    provider::{LIQUID_STAKE_IBC_TYPE_URL, LIQUID_STAKE_TYPE_URL},
+};
+use persistence_std::types::{
+    cosmos::base::v1beta1::Coin as StdCoin,
+    ibc::applications::transfer::v1::{
+        DenomTrace, QueryDenomTraceRequest, QueryDenomTraceResponse,
+    },
+    pstake::{
+        liquidstake,
+        liquidstakeibc::{
+            self,
+            v1beta1::{
+                ica_account::ChannelState, HostChain, IcaAccount, Params, QueryHostChainsResponse,
+                QueryParamsResponse,
+            },
+        },
+    },
+};
+use prost::Message;
+use schemars::JsonSchema;
+use serde::de::DeserializeOwned;
+
+/// Account holding the staked native tokens
+pub const MODULE_ACCOUNT: &str = "pstake_module";
+pub const LS_PREFIX: &str = "stk/";
+
+#[cw_serde]
+pub enum PstakeMsg {
+    Stargate { type_url: String, value: Binary },
+}
+
+#[cw_serde]
+pub enum PstakeQuery {
+    Stargate { path: String, data: Binary },
+}
+
+impl CustomQuery for PstakeQuery {}
+
+/// c-value per base denom, defaults to one
+const C_VALUES: Map<&str, Decimal> = Map::new("mock_pstake_c_values");
+/// ibc denom -> (path, base denom)
+const DENOM_TRACES: Map<&str, (String, String)> = Map::new("mock_pstake_denom_traces");
+/// liquidstakeibc host chains by chain id, their c-value is taken from `C_VALUES`
+const HOST_CHAINS: Map<&str, HostChain> = Map::new("mock_pstake_host_chains");
+/// when set every liquid stake fails with this error
+const FAILURE: Item<String> = Item::new("mock_pstake_failure");
+
+pub struct MockPstake;
+
+impl MockPstake {
+    pub fn set_c_value(&self, storage: &mut dyn Storage, base_denom: &str, c_value: Decimal) {
+        C_VALUES.save(storage, base_denom, &c_value).unwrap();
+    }
+
+    pub fn set_denom_trace(
+        &self,
+        storage: &mut dyn Storage,
+        ibc_denom: &str,
+        path: &str,
+        base_denom: &str,
+    ) {
+        DENOM_TRACES
+            .save(
+                storage,
+                ibc_denom,
+                &(path.to_string(), base_denom.to_string()),
+            )
+            .unwrap();
+    }
+
+    /// Registers an active host chain with an open delegation ICA
+    pub fn set_host_chain(
+        &self,
+        storage: &mut dyn Storage,
+        chain_id: &str,
+        host_denom: &str,
+        channel_id: &str,
+    ) {
+        let host_chain = HostChain {
+            chain_id: chain_id.to_string(),
+            host_denom: host_denom.to_string(),
+            channel_id: channel_id.to_string(),
+            port_id: "transfer".to_string(),
+            delegation_account: Some(IcaAccount {
+                address: format!("{chain_id}-delegation"),
+                channel_state: ChannelState::IcaChannelCreated as i32,
+                ..Default::default()
+            }),
+            minimum_deposit: "1".to_string(),
+            active: true,
+            ..Default::default()
+        };
+        HOST_CHAINS.save(storage, chain_id, &host_chain).unwrap();
+    }
+
+    pub fn set_host_chain_active(&self, storage: &mut dyn Storage, chain_id: &str, active: bool) {
+        HOST_CHAINS
+            .update(storage, chain_id, |host_chain| -> AnyResult<_> {
+                let mut host_chain = host_chain.unwrap();
+                host_chain.active = active;
+                Ok(host_chain)
+            })
+            .unwrap();
+    }
+
+    fn host_chain(storage: &dyn Storage, base_denom: &str) -> AnyResult<Option<HostChain>> {
+        for item in HOST_CHAINS.range(storage, None, None, Order::Ascending) {
+            let (_, host_chain) = item?;
+            if host_chain.host_denom == base_denom {
+                return Ok(Some(host_chain));
+            }
+        }
+        Ok(None)
+    }
+
+    pub fn set_failure(&self, storage: &mut dyn Storage, failure: Option<&str>) {
+        match failure {
+            Some(failure) => FAILURE.save(storage, &failure.to_string()).unwrap(),
+            None => FAILURE.remove(storage),
+        }
+    }
+
+    /// Takes `amount` from the delegator and mints the LS token at the c-value of its base denom
+    #[allow(clippy::too_many_arguments)]
+    fn liquid_stake<ExecC, QueryC>(
+        &self,
+        api: &dyn Api,
+        storage: &mut dyn Storage,
+        router: &dyn CosmosRouter<ExecC = ExecC, QueryC = QueryC>,
+        block: &BlockInfo,
+        delegator: String,
+        amount: Option<StdCoin>,
+        base_denom: impl Fn(&dyn Storage, &str) -> AnyResult<String>,
+    ) -> AnyResult<AppResponse>
+    where
+        ExecC: std::fmt::Debug + Clone + PartialEq + JsonSchema + DeserializeOwned + 'static,
+        QueryC: CustomQuery + DeserializeOwned + 'static,
+    {
+        if let Some(failure) = FAILURE.may_load(storage)? {
+            bail!(failure);
+        }
+        let Some(amount) = amount else {
+            bail!("missing amount");
+        };
+        let deposit = Coin {
+            denom: amount.denom,
+            amount: amount.amount.parse::<Uint128>()?,
+        };
+        if deposit.amount.is_zero() {
+            bail!("zero amount");
+        }
+
+        let base_denom = base_denom(storage, &deposit.denom)?;
+        let c_value = C_VALUES
+            .may_load(storage, &base_denom)?
+            .unwrap_or_else(Decimal::one);
+        let minted = deposit.amount * c_value;
+
+        let delegator = Addr::unchecked(delegator);
+        router.execute(
+            api,
+            storage,
+            block,
+            delegator.clone(),
+            BankMsg::Send {
+                to_address: MODULE_ACCOUNT.to_string(),
+                amount: vec![deposit],
+            }
+            .into(),
+        )?;
+        router.sudo(
+            api,
+            storage,
+            block,
+            BankSudo::Mint {
+                to_address: delegator.to_string(),
+                amount: vec![Coin {
+                    denom: format!("{LS_PREFIX}{base_denom}"),
+                    amount: minted,
+                }],
+            }
+            .into(),
+        )?;
+
+        Ok(AppResponse::default())
+    }
+}
+
+impl Module for MockPstake {
+    type ExecT = PstakeMsg;
+    type QueryT = PstakeQuery;
+    type SudoT = PstakeMsg;
+
+    fn execute<ExecC, QueryC>(
+        &self,
+        api: &dyn Api,
+        storage: &mut dyn Storage,
+        router: &dyn CosmosRouter<ExecC = ExecC, QueryC = QueryC>,
+        block: &BlockInfo,
+        _sender: Addr,
+        msg: PstakeMsg,
+    ) -> AnyResult<AppResponse>
+    where
+        ExecC: std::fmt::Debug + Clone + PartialEq + JsonSchema + DeserializeOwned + 'static,
+        QueryC: CustomQuery + DeserializeOwned + 'static,
+    {
+        let PstakeMsg::Stargate { type_url, value } = msg;
+        match type_url.as_str() {
+            LIQUID_STAKE_IBC_TYPE_URL => {
+                let msg = liquidstakeibc::v1beta1::MsgLiquidStake::decode(value.as_slice())?;
+                self.liquid_stake(
+                    api,
+                    storage,
+                    router,
+                    block,
+                    msg.delegator_address,
+                    msg.amount,
+                    |storage, denom| {
+                        let Some((_, base_denom)) = DENOM_TRACES.may_load(storage, denom)? else {
+                            bail!("host chain not found for denom {denom}");
+                        };
+                        match Self::host_chain(storage, &base_denom)? {
+                            Some(host_chain) if host_chain.active => Ok(base_denom),
+                            _ => bail!("host chain not active"),
+                        }
+                    },
+                )
+            }
+            LIQUID_STAKE_TYPE_URL => {
+                let msg = liquidstake::v1beta1::MsgLiquidStake::decode(value.as_slice())?;
+                self.liquid_stake(
+                    api,
+                    storage,
+                    router,
+                    block,
+                    msg.delegator_address,
+                    msg.amount,
+                    |_, denom| Ok(denom.to_string()),
+                )
+            }
+            _ => bail!("unsupported stargate message {type_url}"),
+        }
+    }
+
+    fn sudo<ExecC, QueryC>(
+        &self,
+        _api: &dyn Api,
+        _storage: &mut dyn Storage,
+        _router: &dyn CosmosRouter<ExecC = ExecC, QueryC = QueryC>,
+        _block: &BlockInfo,
+        msg: PstakeMsg,
+    ) -> AnyResult<AppResponse>
+    where
+        ExecC: std::fmt::Debug + Clone + PartialEq + JsonSchema + DeserializeOwned + 'static,
+        QueryC: CustomQuery + DeserializeOwned + 'static,
+    {
+        bail!("unsupported sudo {msg:?}")
+    }
+
+    fn query(
+        &self,
+        _api: &dyn Api,
+        storage: &dyn Storage,
+        _querier: &dyn Querier,
+        _block: &BlockInfo,
+        request: PstakeQuery,
+    ) -> AnyResult<Binary> {
+        let PstakeQuery::Stargate { path, data } = request;
+        match path.as_str() {
+            DENOM_TRACE_QUERY_TYPE => {
+                let request = QueryDenomTraceRequest::decode(data.as_slice())?;
+                let denom_trace = DENOM_TRACES
+                    .may_load(storage, &request.hash)?
+                    .map(|(path, base_denom)| DenomTrace { path, base_denom });
+                Ok(to_json_binary(&QueryDenomTraceResponse { denom_trace })?)
+            }
+            HOST_CHAINS_QUERY_TYPE => {
+                let host_chains = HOST_CHAINS
+                    .range(storage, None, None, Order::Ascending)
+                    .map(|item| {
+                        let (_, mut host_chain) = item?;
+                        host_chain.c_value = C_VALUES
+                            .may_load(storage, &host_chain.host_denom)?
+                            .unwrap_or_else(Decimal::one)
+                            .to_string();
+                        Ok(host_chain)
+                    })
+                    .collect::<AnyResult<_>>()?;
+                Ok(to_json_binary(&QueryHostChainsResponse { host_chains })?)
+            }
+            // limits wide enough for any c-value set in tests
+            LIQUIDSTAKEIBC_PARAMS_QUERY_TYPE => Ok(to_json_binary(&QueryParamsResponse {
+                params: Some(Params {
+                    lower_c_value_limit: "0.1".to_string(),
+                    upper_c_value_limit: "10".to_string(),
+                    ..Default::default()
+                }),
+            })?),
+            _ => bail!("unsupported stargate query {path}"),
+        }
+    }
+}
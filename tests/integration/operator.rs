@@ -0,0 +1,176 @@
+use cosmwasm_std::{coins, Addr, Coin, Event, Uint128};
+use ica_liquid_staking::{
+    msg::{ExecuteMsg, OperatorsResponse, QueryMsg},
+    ContractError,
+};
+
+use crate::{
+    mock_pstake::MODULE_ACCOUNT,
+    suite::{contract_error, Suite, ADMIN, IBC_ATOM, RECEIVER, STK_ATOM},
+};
+
+const OWNER: &str = "cold";
+const OPERATOR: &str = "hot";
+
+fn stake_from(amount: u128) -> ExecuteMsg {
+    ExecuteMsg::LiquidStakeFrom {
+        owner: OWNER.to_string(),
+        coin: Coin::new(amount, IBC_ATOM),
+        receiver: Addr::unchecked(RECEIVER),
+    }
+}
+
+#[test]
+fn operator_stakes_from_the_owner_deposit() {
+    let mut suite = Suite::new();
+    suite.fund(OWNER, 5000, IBC_ATOM);
+    suite
+        .execute(OWNER, &ExecuteMsg::Deposit {}, &coins(5000, IBC_ATOM))
+        .unwrap();
+    suite
+        .execute(
+            OWNER,
+            &ExecuteMsg::ApproveOperator {
+                operator: OPERATOR.to_string(),
+                expires: None,
+                max_amount: Some(Coin::new(3000, IBC_ATOM)),
+            },
+            &[],
+        )
+        .unwrap();
+
+    // deposits are owed to the owner
+    let err = suite
+        .execute(
+            ADMIN,
+            &ExecuteMsg::Sweep {
+                denoms: vec![IBC_ATOM.to_string()],
+                to: ADMIN.to_string(),
+            },
+            &[],
+        )
+        .unwrap_err();
+    assert_eq!(contract_error(err), ContractError::NothingToSweep {});
+
+    suite.execute(OPERATOR, &stake_from(2000), &[]).unwrap();
+    assert_eq!(suite.balance(RECEIVER, STK_ATOM), Uint128::new(2000));
+    assert_eq!(suite.balance(MODULE_ACCOUNT, IBC_ATOM), Uint128::new(2000));
+    assert_eq!(suite.contract_balance(IBC_ATOM), Uint128::new(3000));
+
+    let err = suite.execute(OPERATOR, &stake_from(1001), &[]).unwrap_err();
+    assert_eq!(
+        contract_error(err),
+        ContractError::OperatorLimitExceeded {
+            remaining: format!("1000{IBC_ATOM}")
+        }
+    );
+
+    let operators: OperatorsResponse = suite
+        .query(&QueryMsg::Operators {
+            owner: OWNER.to_string(),
+        })
+        .unwrap();
+    assert_eq!(operators.operators[0].spent, Uint128::new(2000));
+
+    // revoked operators can not stake anymore
+    suite
+        .execute(
+            OWNER,
+            &ExecuteMsg::RevokeOperator {
+                operator: OPERATOR.to_string(),
+            },
+            &[],
+        )
+        .unwrap();
+    let err = suite.execute(OPERATOR, &stake_from(1000), &[]).unwrap_err();
+    assert_eq!(
+        contract_error(err),
+        ContractError::OperatorNotApproved {
+            operator: OPERATOR.to_string()
+        }
+    );
+}
+
+#[test]
+fn rejected_operator_stake_is_credited_back_to_the_owner() {
+    let mut suite = Suite::new();
+    suite.fund(OWNER, 5000, IBC_ATOM);
+    suite
+        .execute(OWNER, &ExecuteMsg::Deposit {}, &coins(5000, IBC_ATOM))
+        .unwrap();
+    suite
+        .execute(
+            OWNER,
+            &ExecuteMsg::ApproveOperator {
+                operator: OPERATOR.to_string(),
+                expires: None,
+                max_amount: Some(Coin::new(3000, IBC_ATOM)),
+            },
+            &[],
+        )
+        .unwrap();
+
+    suite.set_pstake_failure(Some("deposit amount less than minimum deposit"));
+    let res = suite.execute(OPERATOR, &stake_from(2000), &[]).unwrap();
+    assert!(res.has_event(
+        &Event::new("wasm")
+            .add_attribute("status", "refunded")
+            .add_attribute("deposit_owner", OWNER)
+    ));
+    assert_eq!(suite.balance(RECEIVER, IBC_ATOM), Uint128::zero());
+    assert_eq!(suite.contract_balance(IBC_ATOM), Uint128::new(5000));
+
+    // the refund is owed to the owner and the operator can spend it again
+    assert_eq!(suite.deposit_balance(OWNER), coins(5000, IBC_ATOM));
+    let operators: OperatorsResponse = suite
+        .query(&QueryMsg::Operators {
+            owner: OWNER.to_string(),
+        })
+        .unwrap();
+    assert_eq!(operators.operators[0].spent, Uint128::zero());
+
+    suite.set_pstake_failure(None);
+    suite.execute(OPERATOR, &stake_from(3000), &[]).unwrap();
+    assert_eq!(suite.balance(RECEIVER, STK_ATOM), Uint128::new(3000));
+}
+
+#[test]
+fn refund_under_a_renewed_approval_keeps_the_new_spent_amount() {
+    let approve = ExecuteMsg::ApproveOperator {
+        operator: OPERATOR.to_string(),
+        expires: None,
+        max_amount: Some(Coin::new(3000, IBC_ATOM)),
+    };
+    let mut suite = Suite::new();
+    suite.fund(OWNER, 5000, IBC_ATOM);
+    suite
+        .execute(OWNER, &ExecuteMsg::Deposit {}, &coins(5000, IBC_ATOM))
+        .unwrap();
+    suite.execute(OWNER, &approve, &[]).unwrap();
+
+    suite.set_pstake_failure(Some("unbonding epoch in progress"));
+    suite.execute(OPERATOR, &stake_from(2000), &[]).unwrap();
+    suite.set_pstake_failure(None);
+
+    // the renewed approval starts over, the parked stake was made under the old one
+    suite.execute(OWNER, &approve, &[]).unwrap();
+    suite.execute(OPERATOR, &stake_from(1000), &[]).unwrap();
+    suite
+        .execute(OWNER, &ExecuteMsg::CancelStake { id: 1 }, &[])
+        .unwrap();
+
+    assert_eq!(suite.deposit_balance(OWNER), coins(4000, IBC_ATOM));
+    let operators: OperatorsResponse = suite
+        .query(&QueryMsg::Operators {
+            owner: OWNER.to_string(),
+        })
+        .unwrap();
+    assert_eq!(operators.operators[0].spent, Uint128::new(1000));
+    let err = suite.execute(OPERATOR, &stake_from(2001), &[]).unwrap_err();
+    assert_eq!(
+        contract_error(err),
+        ContractError::OperatorLimitExceeded {
+            remaining: format!("2000{IBC_ATOM}")
+        }
+    );
+}
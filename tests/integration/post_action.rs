@@ -0,0 +1,80 @@
+use cosmwasm_std::{coins, Addr, Uint128};
+use cw_multi_test::Executor;
+use ica_liquid_staking::msg::{ExecuteMsg, PostAction};
+
+use crate::{
+    mock_dex::{vault_contract, VaultInstantiateMsg},
+    suite::{Suite, ADMIN, IBC_ATOM, RECEIVER, STK_ATOM},
+};
+
+/// Suite with a vault holding pool 1, and post actions joining pool 1 and the missing pool 2
+fn setup() -> (Suite, Addr) {
+    let mut suite = Suite::new();
+    let code_id = suite.app.store_code(vault_contract());
+    let vault = suite
+        .app
+        .instantiate_contract(
+            code_id,
+            Addr::unchecked(ADMIN),
+            &VaultInstantiateMsg {
+                pool_ids: vec![Uint128::one()],
+            },
+            &[],
+            "vault",
+            None,
+        )
+        .unwrap();
+    for (name, pool_id) in [("join_pool", 1), ("missing_pool", 2)] {
+        suite
+            .execute(
+                ADMIN,
+                &ExecuteMsg::SetPostAction {
+                    name: name.to_string(),
+                    action: Some(PostAction::DexterJoinPool {
+                        vault: vault.clone(),
+                        pool_id: Uint128::new(pool_id),
+                    }),
+                },
+                &[],
+            )
+            .unwrap();
+    }
+    (suite, vault)
+}
+
+fn liquid_stake_with(suite: &mut Suite, post_action: &str) {
+    suite.fund("sender", 1000, IBC_ATOM);
+    suite
+        .execute(
+            "sender",
+            &ExecuteMsg::LiquidStake {
+                receiver: Addr::unchecked(RECEIVER),
+                referral: None,
+                post_action: Some(post_action.to_string()),
+            },
+            &coins(1000, IBC_ATOM),
+        )
+        .unwrap();
+}
+
+#[test]
+fn post_action_receives_minted_tokens() {
+    let (mut suite, vault) = setup();
+
+    liquid_stake_with(&mut suite, "join_pool");
+
+    assert_eq!(suite.balance(vault.as_str(), STK_ATOM), Uint128::new(1000));
+    assert_eq!(suite.balance(RECEIVER, STK_ATOM), Uint128::zero());
+    assert_eq!(suite.contract_balance(STK_ATOM), Uint128::zero());
+}
+
+#[test]
+fn failed_post_action_falls_back_to_transfer() {
+    let (mut suite, vault) = setup();
+
+    liquid_stake_with(&mut suite, "missing_pool");
+
+    assert_eq!(suite.balance(vault.as_str(), STK_ATOM), Uint128::zero());
+    assert_eq!(suite.balance(RECEIVER, STK_ATOM), Uint128::new(1000));
+    assert_eq!(suite.contract_balance(STK_ATOM), Uint128::zero());
+}
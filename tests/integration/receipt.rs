@@ -0,0 +1,83 @@
+use cosmwasm_std::{Addr, Uint128};
+use cw_multi_test::Executor;
+use ica_liquid_staking::msg::{ExecuteMsg, QueryMsg, ReceiptResponse};
+
+use crate::{
+    mock_nft::{nft_contract, NftExecuteMsg, NftInstantiateMsg},
+    suite::{Suite, ADMIN, IBC_ATOM, RECEIVER, STK_ATOM},
+};
+
+/// Suite minting receipts on an NFT contract that lets `minter` mint
+fn setup(minter: Option<&str>) -> (Suite, Addr) {
+    let mut suite = Suite::new();
+    let minter = minter.map_or_else(|| suite.contract.to_string(), str::to_string);
+    let code_id = suite.app.store_code(nft_contract());
+    let nft = suite
+        .app
+        .instantiate_contract(
+            code_id,
+            Addr::unchecked(ADMIN),
+            &NftInstantiateMsg { minter },
+            &[],
+            "receipts",
+            None,
+        )
+        .unwrap();
+    suite
+        .execute(
+            ADMIN,
+            &ExecuteMsg::SetReceiptContract {
+                contract: Some(nft.to_string()),
+            },
+            &[],
+        )
+        .unwrap();
+    (suite, nft)
+}
+
+#[test]
+fn stake_mints_verifiable_receipt() {
+    let (mut suite, nft) = setup(None);
+
+    suite
+        .liquid_stake("sender", 1000, IBC_ATOM, RECEIVER)
+        .unwrap();
+
+    let res: ReceiptResponse = suite.query(&QueryMsg::Receipt { id: 1 }).unwrap();
+    assert_eq!(res.nft_contract, nft);
+    assert_eq!(res.owner, Some(RECEIVER.to_string()));
+    assert!(res.verified);
+    assert_eq!(res.receipt.sender, Addr::unchecked("sender"));
+    assert_eq!(res.receipt.native_amount, Uint128::new(1000));
+    assert_eq!(res.receipt.ls_token_amount, Uint128::new(1000));
+
+    // a burned token no longer backs the receipt
+    suite
+        .app
+        .execute_contract(
+            Addr::unchecked(RECEIVER),
+            nft,
+            &NftExecuteMsg::Burn {
+                token_id: "1".to_string(),
+            },
+            &[],
+        )
+        .unwrap();
+    let res: ReceiptResponse = suite.query(&QueryMsg::Receipt { id: 1 }).unwrap();
+    assert_eq!(res.owner, None);
+    assert!(!res.verified);
+}
+
+#[test]
+fn failed_mint_keeps_the_stake() {
+    let (mut suite, _) = setup(Some("someone_else"));
+
+    suite
+        .liquid_stake("sender", 1000, IBC_ATOM, RECEIVER)
+        .unwrap();
+
+    assert_eq!(suite.balance(RECEIVER, STK_ATOM), Uint128::new(1000));
+    suite
+        .query::<ReceiptResponse>(&QueryMsg::Receipt { id: 1 })
+        .unwrap_err();
+}
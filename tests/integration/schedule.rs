@@ -0,0 +1,134 @@
+use cosmwasm_std::{coins, Addr, Decimal, Uint128};
+use ica_liquid_staking::msg::{
+    ExecuteMsg, InstantiateMsg, KeeperRewardsResponse, QueryMsg, SchedulesResponse,
+};
+
+use crate::suite::{default_instantiate_msg, Suite, ADMIN, IBC_ATOM, RECEIVER, STK_ATOM};
+
+const OWNER: &str = "owner";
+const KEEPER: &str = "keeper";
+const INTERVAL: u64 = 86_400;
+
+fn advance(suite: &mut Suite, seconds: u64) {
+    suite
+        .app
+        .update_block(|block| block.time = block.time.plus_seconds(seconds));
+}
+
+fn run_due(suite: &mut Suite) {
+    suite
+        .execute(
+            KEEPER,
+            &ExecuteMsg::ExecuteDueSchedules { limit: None },
+            &[],
+        )
+        .unwrap();
+}
+
+#[test]
+fn schedule_stakes_from_the_deposit_balance_every_interval() {
+    let mut suite = Suite::new();
+    suite.fund(OWNER, 2500, IBC_ATOM);
+    suite
+        .execute(OWNER, &ExecuteMsg::Deposit {}, &coins(2500, IBC_ATOM))
+        .unwrap();
+    suite
+        .execute(
+            OWNER,
+            &ExecuteMsg::CreateSchedule {
+                denom: IBC_ATOM.to_string(),
+                amount_per_run: Uint128::new(1000),
+                interval: INTERVAL,
+                receiver: Addr::unchecked(RECEIVER),
+            },
+            &[],
+        )
+        .unwrap();
+
+    run_due(&mut suite);
+    assert_eq!(suite.balance(RECEIVER, STK_ATOM), Uint128::new(1000));
+
+    // nothing is due until the interval passed
+    run_due(&mut suite);
+    advance(&mut suite, INTERVAL - 1);
+    run_due(&mut suite);
+    assert_eq!(suite.balance(RECEIVER, STK_ATOM), Uint128::new(1000));
+
+    advance(&mut suite, 1);
+    run_due(&mut suite);
+    assert_eq!(suite.balance(RECEIVER, STK_ATOM), Uint128::new(2000));
+    assert_eq!(suite.deposit_balance(OWNER), coins(500, IBC_ATOM));
+
+    // a run the balance can not cover is skipped, the schedule stays
+    advance(&mut suite, INTERVAL);
+    run_due(&mut suite);
+    assert_eq!(suite.balance(RECEIVER, STK_ATOM), Uint128::new(2000));
+    assert_eq!(suite.deposit_balance(OWNER), coins(500, IBC_ATOM));
+    assert_eq!(suite.contract_balance(IBC_ATOM), Uint128::new(500));
+
+    suite.fund(OWNER, 500, IBC_ATOM);
+    suite
+        .execute(OWNER, &ExecuteMsg::Deposit {}, &coins(500, IBC_ATOM))
+        .unwrap();
+    advance(&mut suite, INTERVAL);
+    run_due(&mut suite);
+    assert_eq!(suite.balance(RECEIVER, STK_ATOM), Uint128::new(3000));
+    assert!(suite.deposit_balance(OWNER).is_empty());
+
+    suite
+        .execute(OWNER, &ExecuteMsg::CancelSchedule { id: 1 }, &[])
+        .unwrap();
+    let schedules: SchedulesResponse = suite
+        .query(&QueryMsg::Schedules {
+            owner: OWNER.to_string(),
+        })
+        .unwrap();
+    assert!(schedules.schedules.is_empty());
+}
+
+#[test]
+fn schedule_runs_share_the_keeper_tip_budget() {
+    let mut suite = Suite::with_instantiate(InstantiateMsg {
+        protocol_fee: Some(Decimal::percent(10)),
+        ..default_instantiate_msg()
+    });
+    suite
+        .execute(
+            ADMIN,
+            &ExecuteMsg::SetKeeperTip {
+                fee_share: Decimal::percent(20),
+                tips_per_block: 1,
+            },
+            &[],
+        )
+        .unwrap();
+    suite.fund(OWNER, 2000, IBC_ATOM);
+    suite
+        .execute(OWNER, &ExecuteMsg::Deposit {}, &coins(2000, IBC_ATOM))
+        .unwrap();
+    for _ in 0..2 {
+        suite
+            .execute(
+                OWNER,
+                &ExecuteMsg::CreateSchedule {
+                    denom: IBC_ATOM.to_string(),
+                    amount_per_run: Uint128::new(1000),
+                    interval: INTERVAL,
+                    receiver: Addr::unchecked(RECEIVER),
+                },
+                &[],
+            )
+            .unwrap();
+    }
+
+    // both runs are staked, only the first fits in the tips of the block
+    run_due(&mut suite);
+    assert_eq!(suite.balance(RECEIVER, STK_ATOM), Uint128::new(1800));
+    assert_eq!(suite.balance(ADMIN, STK_ATOM), Uint128::new(180));
+    let rewards: KeeperRewardsResponse = suite
+        .query(&QueryMsg::KeeperRewards {
+            address: KEEPER.to_string(),
+        })
+        .unwrap();
+    assert_eq!(rewards.rewards, coins(20, STK_ATOM));
+}
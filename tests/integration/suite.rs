@@ -0,0 +1,299 @@
+//! Multi-test app running the contract against the mock pstake module
+
+use anyhow::Result as AnyResult;
+use cosmwasm_std::{
+    coins, from_json,
+    testing::{MockApi, MockStorage},
+    to_json_vec, Addr, Binary, Coin, CosmosMsg, Decimal, Deps, DepsMut, Empty, Env, MessageInfo,
+    Querier, QuerierResult, QuerierWrapper, QueryRequest, Reply, Response, StdResult, SubMsg,
+    SystemError, SystemResult, Uint128,
+};
+use cw_multi_test::{
+    App, AppResponse, BankKeeper, BankSudo, BasicAppBuilder, Contract, ContractWrapper, Executor,
+    StakingInfo, WasmKeeper,
+};
+use ica_liquid_staking::{
+    contract,
+    msg::{DepositBalanceResponse, ExecuteMsg, InstantiateMsg, QueryMsg},
+    ContractError,
+};
+use serde::de::DeserializeOwned;
+
+use crate::mock_pstake::{MockPstake, PstakeMsg, PstakeQuery, LS_PREFIX};
+
+pub const ADMIN: &str = "admin";
+pub const RECEIVER: &str = "persistencereceiver";
+pub const IBC_ATOM: &str = "ibc/C8A74ABBE2AF892E15680D916A7C22130585CE5704F9B17A10F184A90D53BECA";
+pub const ATOM: &str = "uatom";
+pub const COSMOSHUB: &str = "cosmoshub-4";
+pub const STK_ATOM: &str = "stk/uatom";
+pub const XPRT: &str = "uxprt";
+pub const STK_XPRT: &str = "stk/uxprt";
+
+pub type PstakeApp =
+    App<BankKeeper, MockApi, MockStorage, MockPstake, WasmKeeper<PstakeMsg, PstakeQuery>>;
+
+/// Forwards the contract's Stargate queries to the custom module
+struct StargateQuerier<'a>(QuerierWrapper<'a, PstakeQuery>);
+
+impl Querier for StargateQuerier<'_> {
+    fn raw_query(&self, bin_request: &[u8]) -> QuerierResult {
+        let request: QueryRequest<Empty> = match from_json(bin_request) {
+            Ok(request) => request,
+            Err(err) => {
+                return SystemResult::Err(SystemError::InvalidRequest {
+                    error: err.to_string(),
+                    request: bin_request.into(),
+                })
+            }
+        };
+        let request = match request {
+            QueryRequest::Stargate { path, data } => {
+                QueryRequest::Custom(PstakeQuery::Stargate { path, data })
+            }
+            QueryRequest::Bank(query) => QueryRequest::Bank(query),
+            QueryRequest::Staking(query) => QueryRequest::Staking(query),
+            QueryRequest::Wasm(query) => QueryRequest::Wasm(query),
+            other => {
+                return SystemResult::Err(SystemError::UnsupportedRequest {
+                    kind: format!("{other:?}"),
+                })
+            }
+        };
+        self.0.raw_query(&to_json_vec(&request).unwrap())
+    }
+}
+
+/// Stargate messages are executed by the custom module
+fn customize(res: Response) -> Response<PstakeMsg> {
+    let messages = res.messages.into_iter().map(|sub_msg| SubMsg {
+        id: sub_msg.id,
+        msg: match sub_msg.msg {
+            CosmosMsg::Stargate { type_url, value } => {
+                CosmosMsg::Custom(PstakeMsg::Stargate { type_url, value })
+            }
+            CosmosMsg::Bank(msg) => CosmosMsg::Bank(msg),
+            CosmosMsg::Wasm(msg) => CosmosMsg::Wasm(msg),
+            other => panic!("unsupported message {other:?}"),
+        },
+        gas_limit: sub_msg.gas_limit,
+        reply_on: sub_msg.reply_on,
+    });
+    let mut custom = Response::new()
+        .add_submessages(messages)
+        .add_attributes(res.attributes)
+        .add_events(res.events);
+    if let Some(data) = res.data {
+        custom = custom.set_data(data);
+    }
+    custom
+}
+
+fn instantiate(
+    deps: DepsMut<PstakeQuery>,
+    env: Env,
+    info: MessageInfo,
+    msg: InstantiateMsg,
+) -> Result<Response<PstakeMsg>, ContractError> {
+    let querier = StargateQuerier(deps.querier);
+    let deps = DepsMut {
+        storage: deps.storage,
+        api: deps.api,
+        querier: QuerierWrapper::new(&querier),
+    };
+    contract::instantiate(deps, env, info, msg).map(customize)
+}
+
+fn execute(
+    deps: DepsMut<PstakeQuery>,
+    env: Env,
+    info: MessageInfo,
+    msg: ExecuteMsg,
+) -> Result<Response<PstakeMsg>, ContractError> {
+    let querier = StargateQuerier(deps.querier);
+    let deps = DepsMut {
+        storage: deps.storage,
+        api: deps.api,
+        querier: QuerierWrapper::new(&querier),
+    };
+    contract::execute(deps, env, info, msg).map(customize)
+}
+
+fn reply(
+    deps: DepsMut<PstakeQuery>,
+    env: Env,
+    msg: Reply,
+) -> Result<Response<PstakeMsg>, ContractError> {
+    let querier = StargateQuerier(deps.querier);
+    let deps = DepsMut {
+        storage: deps.storage,
+        api: deps.api,
+        querier: QuerierWrapper::new(&querier),
+    };
+    contract::reply(deps, env, msg).map(customize)
+}
+
+fn query(deps: Deps<PstakeQuery>, env: Env, msg: QueryMsg) -> StdResult<Binary> {
+    let querier = StargateQuerier(deps.querier);
+    let deps = Deps {
+        storage: deps.storage,
+        api: deps.api,
+        querier: QuerierWrapper::new(&querier),
+    };
+    contract::query(deps, env, msg)
+}
+
+fn ls_contract() -> Box<dyn Contract<PstakeMsg, PstakeQuery>> {
+    Box::new(ContractWrapper::new(execute, instantiate, query).with_reply(reply))
+}
+
+pub fn default_instantiate_msg() -> InstantiateMsg {
+    InstantiateMsg {
+        ls_prefix: LS_PREFIX.to_string(),
+        protocol_fee: None,
+        fee_recipient: None,
+        history_limit: None,
+        swap_router: None,
+        config_timelock: None,
+    }
+}
+
+pub struct Suite {
+    pub app: PstakeApp,
+    pub contract: Addr,
+}
+
+impl Suite {
+    pub fn new() -> Self {
+        Self::with_instantiate(default_instantiate_msg())
+    }
+
+    pub fn with_instantiate(msg: InstantiateMsg) -> Self {
+        let mut app = BasicAppBuilder::<PstakeMsg, PstakeQuery>::new_custom()
+            .with_custom(MockPstake)
+            .build(|router, _, storage| {
+                router
+                    .staking
+                    .setup(
+                        storage,
+                        StakingInfo {
+                            bonded_denom: XPRT.to_string(),
+                            unbonding_time: 21 * 24 * 60 * 60,
+                            apr: Decimal::percent(10),
+                        },
+                    )
+                    .unwrap();
+                router
+                    .custom
+                    .set_denom_trace(storage, IBC_ATOM, "transfer/channel-0", ATOM);
+                router
+                    .custom
+                    .set_host_chain(storage, COSMOSHUB, ATOM, "channel-0");
+            });
+
+        let code_id = app.store_code(ls_contract());
+        let contract = app
+            .instantiate_contract(
+                code_id,
+                Addr::unchecked(ADMIN),
+                &msg,
+                &[],
+                "ica-liquid-staking",
+                None,
+            )
+            .unwrap();
+
+        Suite { app, contract }
+    }
+
+    pub fn fund(&mut self, address: &str, amount: u128, denom: &str) {
+        self.app
+            .sudo(
+                BankSudo::Mint {
+                    to_address: address.to_string(),
+                    amount: coins(amount, denom),
+                }
+                .into(),
+            )
+            .unwrap();
+    }
+
+    pub fn balance(&self, address: &str, denom: &str) -> Uint128 {
+        self.app
+            .wrap()
+            .query_balance(address, denom)
+            .unwrap()
+            .amount
+    }
+
+    pub fn contract_balance(&self, denom: &str) -> Uint128 {
+        self.balance(self.contract.as_str(), denom)
+    }
+
+    pub fn deposit_balance(&self, owner: &str) -> Vec<Coin> {
+        let balance: DepositBalanceResponse = self
+            .query(&QueryMsg::DepositBalance {
+                address: owner.to_string(),
+            })
+            .unwrap();
+        balance.balances
+    }
+
+    pub fn set_c_value(&mut self, base_denom: &str, c_value: Decimal) {
+        self.app.init_modules(|router, _, storage| {
+            router.custom.set_c_value(storage, base_denom, c_value)
+        });
+    }
+
+    pub fn set_host_chain_active(&mut self, chain_id: &str, active: bool) {
+        self.app.init_modules(|router, _, storage| {
+            router
+                .custom
+                .set_host_chain_active(storage, chain_id, active)
+        });
+    }
+
+    pub fn set_pstake_failure(&mut self, failure: Option<&str>) {
+        self.app
+            .init_modules(|router, _, storage| router.custom.set_failure(storage, failure));
+    }
+
+    pub fn execute(
+        &mut self,
+        sender: &str,
+        msg: &ExecuteMsg,
+        funds: &[Coin],
+    ) -> AnyResult<AppResponse> {
+        self.app
+            .execute_contract(Addr::unchecked(sender), self.contract.clone(), msg, funds)
+    }
+
+    /// Funds `sender` with `amount` and liquid stakes it for `receiver`
+    pub fn liquid_stake(
+        &mut self,
+        sender: &str,
+        amount: u128,
+        denom: &str,
+        receiver: &str,
+    ) -> AnyResult<AppResponse> {
+        self.fund(sender, amount, denom);
+        self.execute(
+            sender,
+            &ExecuteMsg::LiquidStake {
+                receiver: Addr::unchecked(receiver),
+                referral: None,
+                post_action: None,
+            },
+            &coins(amount, denom),
+        )
+    }
+
+    pub fn query<T: DeserializeOwned>(&self, msg: &QueryMsg) -> StdResult<T> {
+        self.app.wrap().query_wasm_smart(self.contract.clone(), msg)
+    }
+}
+
+/// Contract error of a failed execution
+pub fn contract_error(err: anyhow::Error) -> ContractError {
+    err.downcast().unwrap()
+}
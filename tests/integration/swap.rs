@@ -0,0 +1,91 @@
+use cosmwasm_std::{coins, Addr, Decimal, Uint128};
+use cw_multi_test::Executor;
+use ica_liquid_staking::msg::{ExecuteMsg, SwapHop};
+
+use crate::{
+    mock_dex::{router_contract, RouterInstantiateMsg},
+    suite::{Suite, ADMIN, IBC_ATOM, RECEIVER, STK_ATOM},
+};
+
+const USDC: &str = "uusdc";
+
+/// Suite with a 1:1 router able to pay out `liquidity` of IBC atom
+fn setup(liquidity: u128) -> (Suite, Addr) {
+    let mut suite = Suite::new();
+    let code_id = suite.app.store_code(router_contract());
+    let router = suite
+        .app
+        .instantiate_contract(
+            code_id,
+            Addr::unchecked(ADMIN),
+            &RouterInstantiateMsg {
+                rate: Decimal::one(),
+            },
+            &[],
+            "router",
+            None,
+        )
+        .unwrap();
+    suite.fund(router.as_str(), liquidity, IBC_ATOM);
+    suite
+        .execute(
+            ADMIN,
+            &ExecuteMsg::UpdateConfig {
+                active: None,
+                ls_prefix: None,
+                protocol_fee: None,
+                fee_recipient: None,
+                history_limit: None,
+                swap_router: Some(router.to_string()),
+                verify_ls_prefix: None,
+                config_timelock: None,
+            },
+            &[],
+        )
+        .unwrap();
+    (suite, router)
+}
+
+fn swap_and_liquid_stake(suite: &mut Suite, amount: u128, min_out: u128) {
+    suite.fund("sender", amount, USDC);
+    suite
+        .execute(
+            "sender",
+            &ExecuteMsg::SwapAndLiquidStake {
+                receiver: Addr::unchecked(RECEIVER),
+                route: vec![SwapHop {
+                    pool_id: Uint128::one(),
+                    ask_denom: IBC_ATOM.to_string(),
+                }],
+                min_out: Uint128::new(min_out),
+                referral: None,
+                post_action: None,
+            },
+            &coins(amount, USDC),
+        )
+        .unwrap();
+}
+
+#[test]
+fn swap_output_is_liquid_staked() {
+    let (mut suite, router) = setup(10_000);
+
+    swap_and_liquid_stake(&mut suite, 500, 450);
+
+    assert_eq!(suite.balance(RECEIVER, STK_ATOM), Uint128::new(500));
+    assert_eq!(suite.balance(router.as_str(), USDC), Uint128::new(500));
+    assert_eq!(suite.contract_balance(USDC), Uint128::zero());
+    assert_eq!(suite.contract_balance(IBC_ATOM), Uint128::zero());
+}
+
+#[test]
+fn failed_swap_refunds_receiver() {
+    let (mut suite, _) = setup(10_000);
+
+    // the router cannot meet the minimum output
+    swap_and_liquid_stake(&mut suite, 500, 600);
+
+    assert_eq!(suite.balance(RECEIVER, USDC), Uint128::new(500));
+    assert_eq!(suite.balance(RECEIVER, STK_ATOM), Uint128::zero());
+    assert_eq!(suite.contract_balance(USDC), Uint128::zero());
+}